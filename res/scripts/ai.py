@@ -0,0 +1,12 @@
+# Script leaves for behaviour trees. Each takes the agent's blackboard as a
+# dict and returns "success", "failure" or "running".
+
+
+def slime_target_above(blackboard):
+    position = blackboard.get("position")
+    target = blackboard.get("target_position")
+    if position is None or target is None:
+        return "failure"
+    if target[1] - position[1] > 0.5:
+        return "success"
+    return "failure"
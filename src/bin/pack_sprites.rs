@@ -0,0 +1,145 @@
+// Packs a folder of frames into a sprite sheet and writes its RON description.
+//
+// Each subfolder of the input is one animation and its PNGs, sorted by name,
+// are the frames. Every animation gets a row of equally sized cells.
+//
+//     cargo run --bin pack_sprites -- <frames dir> <sheet.png> <sheet.ron> [--mirror] [--squeeze]
+//
+// With `--mirror` every animation also gets a flipped variant named `<name>_mirrored`.
+
+use image::{DynamicImage, GenericImage, GenericImageView, RgbaImage};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::exit;
+
+struct Animation {
+    name: String,
+    frames: Vec<DynamicImage>,
+}
+
+fn usage() -> ! {
+    eprintln!("Usage: pack_sprites <frames dir> <sheet.png> <sheet.ron> [--mirror] [--squeeze]");
+    exit(1);
+}
+
+fn sorted_entries(dir: &Path) -> Vec<PathBuf> {
+    let mut entries: Vec<PathBuf> = match fs::read_dir(dir) {
+        Ok(entries) => entries
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.path())
+            .collect(),
+        Err(e) => {
+            eprintln!("Failed to read {:?}: {}", dir, e);
+            exit(1);
+        }
+    };
+    entries.sort();
+    entries
+}
+
+fn load_animations(dir: &Path) -> Vec<Animation> {
+    let mut animations = Vec::new();
+    for path in sorted_entries(dir).into_iter().filter(|path| path.is_dir()) {
+        let frames: Vec<DynamicImage> = sorted_entries(&path)
+            .into_iter()
+            .filter(|frame| frame.extension().map_or(false, |ext| ext == "png"))
+            .map(|frame| match image::open(&frame) {
+                Ok(img) => img,
+                Err(e) => {
+                    eprintln!("Failed to load frame {:?}: {}", frame, e);
+                    exit(1);
+                }
+            })
+            .collect();
+        if frames.is_empty() {
+            continue;
+        }
+        animations.push(Animation {
+            name: path.file_name().unwrap().to_string_lossy().to_string(),
+            frames,
+        });
+    }
+    animations
+}
+
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let positional: Vec<&String> = args.iter().filter(|arg| !arg.starts_with("--")).collect();
+    let known = |arg: &str| arg == "--mirror" || arg == "--squeeze";
+    if positional.len() != 3 || args.iter().any(|arg| arg.starts_with("--") && !known(arg)) {
+        usage();
+    }
+    let (frames_dir, sheet_path, ron_path) = (positional[0], positional[1], positional[2]);
+    let mirror = args.iter().any(|arg| arg == "--mirror");
+    let squeeze = args.iter().any(|arg| arg == "--squeeze");
+
+    let animations = load_animations(Path::new(frames_dir));
+    if animations.is_empty() {
+        eprintln!("No animations found in {:?}", frames_dir);
+        exit(1);
+    }
+
+    // Frames are centred in cells big enough for the largest one.
+    let frames = animations.iter().flat_map(|animation| &animation.frames);
+    let cell_w = frames.clone().map(|frame| frame.width()).max().unwrap();
+    let cell_h = frames.map(|frame| frame.height()).max().unwrap();
+    let columns = animations.iter().map(|a| a.frames.len()).max().unwrap() as u32;
+    let rows = animations.len() as u32;
+
+    let mut sheet = RgbaImage::new(columns * cell_w, rows * cell_h);
+    for (row, animation) in animations.iter().enumerate() {
+        for (column, frame) in animation.frames.iter().enumerate() {
+            let x = column as u32 * cell_w + (cell_w - frame.width()) / 2;
+            let y = row as u32 * cell_h + (cell_h - frame.height()) / 2;
+            if let Err(e) = sheet.copy_from(&frame.to_rgba8(), x, y) {
+                eprintln!("Failed to place frame of {:?}: {}", animation.name, e);
+                exit(1);
+            }
+        }
+    }
+    if let Err(e) = sheet.save(sheet_path) {
+        eprintln!("Failed to write {:?}: {}", sheet_path, e);
+        exit(1);
+    }
+
+    let mut ron = String::new();
+    ron.push_str("SpriteSheetDesc(\n");
+    ron.push_str(&format!("    source_path: {:?},\n", sheet_path));
+    ron.push_str(&format!(
+        "    grid: Some(GridDesc(cell: ({}, {}))),\n",
+        cell_w, cell_h
+    ));
+    ron.push_str("    animations: [\n");
+    for (row, animation) in animations.iter().enumerate() {
+        let key = if mirror { row * 2 } else { row };
+        ron.push_str("        AnimationDesc(\n");
+        ron.push_str(&format!("            name: {:?},\n", animation.name));
+        ron.push_str(&format!("            key: {},\n", key));
+        ron.push_str(&format!(
+            "            frames: Grid(row: {}, first: 0, count: {}),\n",
+            row,
+            animation.frames.len()
+        ));
+        ron.push_str(&format!("            squeeze: {},\n", squeeze));
+        if mirror {
+            ron.push_str(&format!(
+                "            mirror: Some(MirrorDesc(name: \"{}_mirrored\", key: {})),\n",
+                animation.name,
+                key + 1
+            ));
+        }
+        ron.push_str("        ),\n");
+    }
+    ron.push_str("    ],\n)\n");
+
+    if let Err(e) = fs::write(ron_path, ron) {
+        eprintln!("Failed to write {:?}: {}", ron_path, e);
+        exit(1);
+    }
+    println!(
+        "Packed {} animations into {}x{} cells",
+        animations.len(),
+        cell_w,
+        cell_h
+    );
+}
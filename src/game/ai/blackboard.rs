@@ -0,0 +1,71 @@
+use legion::Entity;
+use nalgebra::Vector2;
+use serde::Deserialize;
+use std::collections::HashMap;
+
+#[derive(Debug, Copy, Clone, PartialEq, Deserialize)]
+pub enum Value {
+    Bool(bool),
+    Number(f32),
+    Vector(f32, f32),
+    // Only set at runtime, e.g. the current target.
+    #[serde(skip_deserializing)]
+    Entity(Entity),
+}
+
+// What an agent knows, shared between the nodes of its tree and script leaves.
+// The AI system keeps "position", "health", "target", "target_position" and
+// "target_distance" up to date; trees may add their own entries.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(transparent)]
+pub struct Blackboard {
+    values: HashMap<String, Value>,
+}
+
+impl Blackboard {
+    pub fn get(&self, key: &str) -> Option<Value> {
+        self.values.get(key).copied()
+    }
+
+    pub fn set(&mut self, key: &str, value: Value) {
+        self.values.insert(key.to_string(), value);
+    }
+
+    pub fn remove(&mut self, key: &str) {
+        self.values.remove(key);
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&String, &Value)> {
+        self.values.iter()
+    }
+
+    // Missing flags count as unset.
+    pub fn flag(&self, key: &str) -> bool {
+        self.get(key) == Some(Value::Bool(true))
+    }
+
+    pub fn number(&self, key: &str) -> Option<f32> {
+        match self.get(key) {
+            Some(Value::Number(number)) => Some(number),
+            _ => None,
+        }
+    }
+
+    pub fn vector(&self, key: &str) -> Option<Vector2<f32>> {
+        match self.get(key) {
+            Some(Value::Vector(x, y)) => Some(Vector2::new(x, y)),
+            _ => None,
+        }
+    }
+
+    pub fn set_vector(&mut self, key: &str, vector: Vector2<f32>) {
+        self.set(key, Value::Vector(vector.x, vector.y));
+    }
+
+    pub fn entity(&self, key: &str) -> Option<Entity> {
+        match self.get(key) {
+            Some(Value::Entity(entity)) => Some(entity),
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,271 @@
+use super::super::components::combat::DamageEvent;
+use super::super::navigation::{LinkKind, NavGraph};
+use super::blackboard::Value;
+use super::tree::{Context, Status};
+use log::error;
+use nalgebra::{Point2, Vector2};
+use rustpython_vm::pyobject::{ItemProtocol, TryFromObject};
+use rustpython_vm::{scope::Scope, Interpreter};
+use serde::Deserialize;
+
+// Horizontal distance at which a path node counts as reached.
+const ARRIVE_DISTANCE: f32 = 0.15;
+
+#[derive(Debug, Clone, Deserialize)]
+pub enum Condition {
+    // A target is in sight and at most this far away.
+    TargetWithin(f32),
+    // Health as a fraction of the maximum.
+    HealthBelow(f32),
+    // A blackboard flag is set.
+    IsSet(String),
+}
+
+// Distances are in world units and times in seconds. Movement goes through
+// the agent's `Steering`.
+#[derive(Debug, Clone, Deserialize)]
+pub enum Action {
+    // Stands still.
+    Idle,
+    Wait(f32),
+    Jump,
+    // Walks back and forth, turning around `distance` either side of "home".
+    Patrol {
+        distance: f32,
+    },
+    // Walks towards the target until horizontally within `stop_distance`.
+    Chase {
+        stop_distance: f32,
+    },
+    // Like `Chase`, but follows a plan from the `NavGraph`, jumping and
+    // dropping between platforms. Fails without a path.
+    Pathfind {
+        stop_distance: f32,
+    },
+    // Walks away from the target until at least `distance` away.
+    Flee {
+        distance: f32,
+    },
+    // Hits the target if it is within `range`.
+    Attack {
+        damage: f32,
+        #[serde(default)]
+        knockback: f32,
+        range: f32,
+    },
+}
+
+pub fn check(condition: &Condition, context: &mut Context) -> Status {
+    let blackboard = &context.blackboard;
+    let passed = match condition {
+        Condition::TargetWithin(distance) => blackboard
+            .number("target_distance")
+            .map_or(false, |target_distance| target_distance <= *distance),
+        Condition::HealthBelow(fraction) => blackboard
+            .number("health")
+            .map_or(false, |health| health < *fraction),
+        Condition::IsSet(key) => blackboard.flag(key),
+    };
+    if passed {
+        Status::Success
+    } else {
+        Status::Failure
+    }
+}
+
+pub fn act(action: &Action, index: usize, context: &mut Context) -> Status {
+    let position = context
+        .blackboard
+        .vector("position")
+        .unwrap_or_else(Vector2::zeros);
+    let target = context.blackboard.vector("target_position");
+    match action {
+        Action::Idle => Status::Success,
+        Action::Wait(seconds) => {
+            let elapsed = context.memory.elapsed.entry(index).or_insert(0.0);
+            *elapsed += context.dt;
+            if *elapsed < *seconds {
+                return Status::Running;
+            }
+            context.memory.elapsed.remove(&index);
+            Status::Success
+        }
+        Action::Jump => {
+            context.steering.jump = true;
+            Status::Success
+        }
+        Action::Patrol { distance } => {
+            let home = context.blackboard.vector("home").unwrap_or(position);
+            let mut direction = context.blackboard.number("patrol_direction").unwrap_or(1.0);
+            let offset = position.x - home.x;
+            if offset >= *distance {
+                direction = -1.0;
+            } else if offset <= -*distance {
+                direction = 1.0;
+            }
+            context
+                .blackboard
+                .set("patrol_direction", Value::Number(direction));
+            context.steering.axis = direction;
+            Status::Running
+        }
+        Action::Chase { stop_distance } => {
+            let target = match target {
+                Some(target) => target,
+                None => return Status::Failure,
+            };
+            let dx = target.x - position.x;
+            if dx.abs() <= *stop_distance {
+                return Status::Success;
+            }
+            context.steering.axis = dx.signum();
+            Status::Running
+        }
+        Action::Pathfind { stop_distance } => match target {
+            Some(target) => pathfind(position, target, *stop_distance, context),
+            None => Status::Failure,
+        },
+        Action::Flee { distance } => {
+            let target = match target {
+                Some(target) => target,
+                None => return Status::Failure,
+            };
+            if (target - position).norm() >= *distance {
+                return Status::Success;
+            }
+            // Straight above or below counts as the target being on the right.
+            context.steering.axis = if target.x >= position.x { -1.0 } else { 1.0 };
+            Status::Running
+        }
+        Action::Attack {
+            damage,
+            knockback,
+            range,
+        } => {
+            let (entity, target) = match (context.blackboard.entity("target"), target) {
+                (Some(entity), Some(target)) if (target - position).norm() <= *range => {
+                    (entity, target)
+                }
+                _ => return Status::Failure,
+            };
+            let side = if target.x >= position.x { 1.0 } else { -1.0 };
+            context.damage.push(DamageEvent {
+                target: entity,
+                source: Some(context.entity),
+                amount: *damage,
+                knockback: Vector2::new(side, 0.5).normalize() * *knockback,
+            });
+            Status::Success
+        }
+    }
+}
+
+fn pathfind(
+    position: Vector2<f32>,
+    target: Vector2<f32>,
+    stop_distance: f32,
+    context: &mut Context,
+) -> Status {
+    if (target - position).norm() <= stop_distance {
+        return Status::Success;
+    }
+    let graph = match context.resources.get::<NavGraph>() {
+        Some(graph) => graph,
+        None => return Status::Failure,
+    };
+    let plan = match graph.plan(Point2::from(position), Point2::from(target)) {
+        Some(plan) => plan,
+        None => return Status::Failure,
+    };
+
+    // Skip the nodes already reached; a jump starts from the last of them.
+    let reached = plan
+        .iter()
+        .take_while(|step| (step.position.x - position.x).abs() <= ARRIVE_DISTANCE)
+        .count();
+    let next = match plan.get(reached) {
+        Some(next) => next,
+        // At the end of the plan, e.g. under a target that's in the air.
+        None => {
+            let dx = target.x - position.x;
+            if dx.abs() <= stop_distance {
+                return Status::Success;
+            }
+            context.steering.axis = dx.signum();
+            return Status::Running;
+        }
+    };
+    context.steering.axis = (next.position.x - position.x).signum();
+    context.steering.jump = next.kind == LinkKind::Jump && reached > 0;
+    Status::Running
+}
+
+// Calls `function(blackboard: dict)`, which returns "success", "failure" or
+// "running". Vectors are passed as (x, y) tuples. Changes to existing numbers
+// and flags are written back, and the "move" (-1 to 1) and "jump" entries
+// steer the agent.
+pub fn script(function: &str, context: &mut Context) -> Status {
+    let runtime = context.resources.get::<Interpreter>();
+    let scope = context.resources.get::<Scope>();
+    let (runtime, scope) = match (runtime, scope) {
+        (Some(runtime), Some(scope)) => (runtime, scope),
+        _ => return Status::Failure,
+    };
+    let blackboard = &mut *context.blackboard;
+    let steering = &mut *context.steering;
+    let result = runtime.enter(|vm| {
+        let function = scope.globals.get_item(function, vm)?;
+        let dict = vm.ctx.new_dict();
+        for (key, value) in blackboard.iter() {
+            let value = match *value {
+                Value::Bool(flag) => vm.ctx.new_bool(flag),
+                Value::Number(number) => vm.ctx.new_float(number as f64),
+                Value::Vector(x, y) => vm
+                    .ctx
+                    .new_tuple(vec![vm.ctx.new_float(x as f64), vm.ctx.new_float(y as f64)]),
+                Value::Entity(_) => continue,
+            };
+            dict.set_item(key.as_str(), value, vm)?;
+        }
+        dict.set_item("move", vm.ctx.new_float(steering.axis as f64), vm)?;
+        dict.set_item("jump", vm.ctx.new_bool(steering.jump), vm)?;
+
+        let ret = vm.invoke(&function, vec![dict.clone().into_object()])?;
+        let status = String::try_from_object(vm, ret)?;
+
+        let written: Vec<(String, Value)> = blackboard
+            .iter()
+            .map(|(key, value)| (key.clone(), *value))
+            .collect();
+        for (key, value) in written {
+            let value = match value {
+                Value::Bool(_) => {
+                    Value::Bool(bool::try_from_object(vm, dict.get_item(key.as_str(), vm)?)?)
+                }
+                Value::Number(_) => {
+                    let number = f64::try_from_object(vm, dict.get_item(key.as_str(), vm)?)?;
+                    Value::Number(number as f32)
+                }
+                _ => continue,
+            };
+            blackboard.set(&key, value);
+        }
+        let axis = f64::try_from_object(vm, dict.get_item("move", vm)?)? as f32;
+        steering.axis = axis.max(-1.0).min(1.0);
+        steering.jump = bool::try_from_object(vm, dict.get_item("jump", vm)?)?;
+        Ok(status)
+    });
+    match result.as_ref().map(String::as_str) {
+        Ok("success") => Status::Success,
+        Ok("failure") => Status::Failure,
+        Ok("running") => Status::Running,
+        Ok(other) => {
+            error!("Script leaf {:?} returned {:?}", function, other);
+            Status::Failure
+        }
+        Err(_) => {
+            error!("Script leaf {:?} failed", function);
+            Status::Failure
+        }
+    }
+}
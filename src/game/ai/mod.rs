@@ -0,0 +1,99 @@
+pub mod blackboard;
+pub mod leaves;
+pub mod tree;
+
+use super::components::script::PyScript;
+use legion::Resources;
+use log::error;
+use ron::de::from_reader;
+use rustpython_vm::{scope::Scope, Interpreter};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fs::{self, File};
+use tree::{BehaviourTree, NodeDesc};
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct AiConfig {
+    // Behaviour tree ticks per second, for every agent.
+    pub tick_rate: f32,
+    // Python files run in the shared scope, defining functions for script leaves.
+    pub scripts: Vec<String>,
+}
+
+impl Default for AiConfig {
+    fn default() -> Self {
+        Self {
+            tick_rate: 10.0,
+            scripts: Vec::new(),
+        }
+    }
+}
+
+impl AiConfig {
+    pub fn load(filename: &str) -> Self {
+        let f = File::open(crate::utils::from_out_dir(filename)).expect("Failed opening file");
+        match from_reader(f) {
+            Ok(x) => x,
+            Err(e) => {
+                error!("Failed to load AI config: {}", e);
+                Self::default()
+            }
+        }
+    }
+
+    // Seconds between ticks.
+    pub fn interval(&self) -> f32 {
+        1.0 / self.tick_rate.max(std::f32::EPSILON)
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct BehaviourTrees {
+    trees: HashMap<String, BehaviourTree>,
+}
+
+impl BehaviourTrees {
+    // Loads every `<name>.ron` in the directory as the tree `name`.
+    pub fn load(dir: &str) -> Self {
+        let mut trees = Self::default();
+        let entries = fs::read_dir(crate::utils::from_out_dir(dir))
+            .unwrap_or_else(|e| panic!("{:?}- Behaviour tree directory: {}", dir, e));
+        for path in entries
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.path())
+        {
+            if path.extension().map_or(true, |ext| ext != "ron") {
+                continue;
+            }
+            let name = path.file_stem().unwrap().to_string_lossy().to_string();
+            let f = File::open(&path).expect("Failed opening file");
+            let desc: NodeDesc = match from_reader(f) {
+                Ok(x) => x,
+                Err(e) => {
+                    error!("Failed to load behaviour tree {:?}: {}", name, e);
+                    continue;
+                }
+            };
+            trees.trees.insert(name, BehaviourTree::new(&desc));
+        }
+        trees
+    }
+
+    pub fn get(&self, name: &str) -> Option<&BehaviourTree> {
+        self.trees.get(name)
+    }
+}
+
+// Runs the configured scripts so their functions are there for script leaves.
+pub fn run_scripts(resources: &Resources) {
+    let config = resources.get::<AiConfig>().unwrap();
+    let mut runtime = resources.get_mut::<Interpreter>().unwrap();
+    let scope = resources.get::<Scope>().unwrap();
+    for script in &config.scripts {
+        let script = PyScript::compile(crate::utils::from_out_dir(script), &mut runtime);
+        if script.run_with_scope(&mut runtime, scope.clone()).is_err() {
+            error!("AI script {:?} failed", script.source_path);
+        }
+    }
+}
@@ -0,0 +1,199 @@
+use super::super::components::combat::DamageEvents;
+use super::blackboard::Blackboard;
+use super::leaves::{self, Action, Condition};
+use legion::{Entity, Resources};
+use serde::Deserialize;
+use std::collections::HashMap;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Status {
+    Success,
+    Failure,
+    Running,
+}
+
+// A tree as authored in RON. Trees are evaluated from the root on every tick,
+// so a running child is interrupted as soon as an earlier branch applies.
+#[derive(Debug, Clone, Deserialize)]
+pub enum NodeDesc {
+    // Runs children in order until one doesn't succeed.
+    Sequence(Vec<NodeDesc>),
+    // Runs children in order until one doesn't fail.
+    Selector(Vec<NodeDesc>),
+    Invert(Box<NodeDesc>),
+    // Turns failure into success.
+    Succeed(Box<NodeDesc>),
+    // Fails for `seconds` after the child succeeded.
+    Cooldown { seconds: f32, child: Box<NodeDesc> },
+    // Keeps running until the child succeeded `times` times in a row.
+    Repeat { times: u32, child: Box<NodeDesc> },
+    Condition(Condition),
+    Action(Action),
+    // A python function in the shared scope, see `leaves::script`.
+    Script(String),
+}
+
+// `NodeDesc` flattened so each node has an index to keep its state under.
+#[derive(Debug)]
+enum Node {
+    Sequence(Vec<usize>),
+    Selector(Vec<usize>),
+    Invert(usize),
+    Succeed(usize),
+    Cooldown { seconds: f32, child: usize },
+    Repeat { times: u32, child: usize },
+    Condition(Condition),
+    Action(Action),
+    Script(String),
+}
+
+#[derive(Debug)]
+pub struct BehaviourTree {
+    nodes: Vec<Node>,
+    root: usize,
+}
+
+// Per-agent node state, keyed by node index.
+#[derive(Debug, Clone, Default)]
+pub struct NodeMemory {
+    // Seconds left, counted down every tick.
+    pub cooldowns: HashMap<usize, f32>,
+    // Seconds spent, counted up by the node itself.
+    pub elapsed: HashMap<usize, f32>,
+    pub counts: HashMap<usize, u32>,
+}
+
+impl NodeMemory {
+    pub fn advance(&mut self, dt: f32) {
+        for remaining in self.cooldowns.values_mut() {
+            *remaining -= dt;
+        }
+        self.cooldowns.retain(|_, remaining| *remaining > 0.0);
+    }
+}
+
+// Movement requested by the tree this tick, applied to the agent's
+// `CharacterController` afterwards. Reset before every tick.
+#[derive(Debug, Copy, Clone, Default, PartialEq)]
+pub struct Steering {
+    // -1 is left, 1 is right.
+    pub axis: f32,
+    pub jump: bool,
+}
+
+pub struct Context<'a> {
+    pub entity: Entity,
+    // Seconds since the agent's previous tick.
+    pub dt: f32,
+    pub blackboard: &'a mut Blackboard,
+    pub memory: &'a mut NodeMemory,
+    pub steering: &'a mut Steering,
+    pub damage: &'a mut DamageEvents,
+    // For script leaves.
+    pub resources: &'a Resources,
+}
+
+impl BehaviourTree {
+    pub fn new(desc: &NodeDesc) -> Self {
+        let mut tree = Self {
+            nodes: Vec::new(),
+            root: 0,
+        };
+        tree.root = tree.add(desc);
+        tree
+    }
+
+    // Children are added before their parent, so the root comes last.
+    fn add(&mut self, desc: &NodeDesc) -> usize {
+        let node = match desc {
+            NodeDesc::Sequence(children) => {
+                Node::Sequence(children.iter().map(|child| self.add(child)).collect())
+            }
+            NodeDesc::Selector(children) => {
+                Node::Selector(children.iter().map(|child| self.add(child)).collect())
+            }
+            NodeDesc::Invert(child) => Node::Invert(self.add(child)),
+            NodeDesc::Succeed(child) => Node::Succeed(self.add(child)),
+            NodeDesc::Cooldown { seconds, child } => Node::Cooldown {
+                seconds: *seconds,
+                child: self.add(child),
+            },
+            NodeDesc::Repeat { times, child } => Node::Repeat {
+                times: *times,
+                child: self.add(child),
+            },
+            NodeDesc::Condition(condition) => Node::Condition(condition.clone()),
+            NodeDesc::Action(action) => Node::Action(action.clone()),
+            NodeDesc::Script(function) => Node::Script(function.clone()),
+        };
+        self.nodes.push(node);
+        self.nodes.len() - 1
+    }
+
+    pub fn tick(&self, context: &mut Context) -> Status {
+        context.memory.advance(context.dt);
+        *context.steering = Steering::default();
+        self.tick_node(self.root, context)
+    }
+
+    fn tick_node(&self, index: usize, context: &mut Context) -> Status {
+        match &self.nodes[index] {
+            Node::Sequence(children) => {
+                for child in children {
+                    match self.tick_node(*child, context) {
+                        Status::Success => continue,
+                        status => return status,
+                    }
+                }
+                Status::Success
+            }
+            Node::Selector(children) => {
+                for child in children {
+                    match self.tick_node(*child, context) {
+                        Status::Failure => continue,
+                        status => return status,
+                    }
+                }
+                Status::Failure
+            }
+            Node::Invert(child) => match self.tick_node(*child, context) {
+                Status::Success => Status::Failure,
+                Status::Failure => Status::Success,
+                Status::Running => Status::Running,
+            },
+            Node::Succeed(child) => match self.tick_node(*child, context) {
+                Status::Running => Status::Running,
+                _ => Status::Success,
+            },
+            Node::Cooldown { seconds, child } => {
+                if context.memory.cooldowns.contains_key(&index) {
+                    return Status::Failure;
+                }
+                let status = self.tick_node(*child, context);
+                if status == Status::Success {
+                    context.memory.cooldowns.insert(index, *seconds);
+                }
+                status
+            }
+            Node::Repeat { times, child } => match self.tick_node(*child, context) {
+                Status::Success => {
+                    let count = context.memory.counts.entry(index).or_insert(0);
+                    *count += 1;
+                    if *count < *times {
+                        return Status::Running;
+                    }
+                    context.memory.counts.remove(&index);
+                    Status::Success
+                }
+                Status::Failure => {
+                    context.memory.counts.remove(&index);
+                    Status::Failure
+                }
+                Status::Running => Status::Running,
+            },
+            Node::Condition(condition) => leaves::check(condition, context),
+            Node::Action(action) => leaves::act(action, index, context),
+            Node::Script(function) => leaves::script(function, context),
+        }
+    }
+}
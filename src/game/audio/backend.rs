@@ -0,0 +1,192 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+use std::sync::Arc;
+
+pub type PlaybackId = u64;
+
+// Encoded WAV or OGG data, decoded by the backend.
+#[derive(Debug, Clone)]
+pub struct SoundData {
+    pub name: String,
+    pub bytes: Arc<[u8]>,
+}
+
+pub trait AudioBackend {
+    fn play(&mut self, sound: &SoundData, volume: f32, looping: bool) -> Option<PlaybackId>;
+    fn set_volume(&mut self, id: PlaybackId, volume: f32);
+    fn stop(&mut self, id: PlaybackId);
+    // Forgets finished one-shots. Returns the ids still playing.
+    fn playing(&mut self) -> Vec<PlaybackId>;
+}
+
+// Plays nothing. Used when there is no output device.
+#[derive(Default)]
+pub struct NullBackend {
+    next_id: PlaybackId,
+    looping: Vec<PlaybackId>,
+}
+
+impl AudioBackend for NullBackend {
+    fn play(&mut self, _sound: &SoundData, _volume: f32, looping: bool) -> Option<PlaybackId> {
+        self.next_id += 1;
+        if looping {
+            self.looping.push(self.next_id);
+        }
+        Some(self.next_id)
+    }
+
+    fn set_volume(&mut self, _id: PlaybackId, _volume: f32) {}
+
+    fn stop(&mut self, id: PlaybackId) {
+        self.looping.retain(|playing| *playing != id);
+    }
+
+    // One-shots finish immediately; loops, e.g. music, play until stopped.
+    fn playing(&mut self) -> Vec<PlaybackId> {
+        self.looping.clone()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum AudioCall {
+    Play {
+        id: PlaybackId,
+        sound: String,
+        volume: f32,
+        looping: bool,
+    },
+    SetVolume {
+        id: PlaybackId,
+        volume: f32,
+    },
+    Stop {
+        id: PlaybackId,
+    },
+}
+
+// Records every call instead of playing it, for checking audio without a device.
+#[derive(Default)]
+pub struct RecordingBackend {
+    next_id: PlaybackId,
+    looping: Vec<PlaybackId>,
+    calls: Rc<RefCell<Vec<AudioCall>>>,
+}
+
+impl RecordingBackend {
+    // The log stays readable after the backend is handed to `Audio`.
+    pub fn calls(&self) -> Rc<RefCell<Vec<AudioCall>>> {
+        self.calls.clone()
+    }
+}
+
+impl AudioBackend for RecordingBackend {
+    fn play(&mut self, sound: &SoundData, volume: f32, looping: bool) -> Option<PlaybackId> {
+        self.next_id += 1;
+        let id = self.next_id;
+        if looping {
+            self.looping.push(id);
+        }
+        self.calls.borrow_mut().push(AudioCall::Play {
+            id,
+            sound: sound.name.clone(),
+            volume,
+            looping,
+        });
+        Some(id)
+    }
+
+    fn set_volume(&mut self, id: PlaybackId, volume: f32) {
+        self.calls
+            .borrow_mut()
+            .push(AudioCall::SetVolume { id, volume });
+    }
+
+    fn stop(&mut self, id: PlaybackId) {
+        self.looping.retain(|playing| *playing != id);
+        self.calls.borrow_mut().push(AudioCall::Stop { id });
+    }
+
+    // One-shots finish immediately; loops play until stopped.
+    fn playing(&mut self) -> Vec<PlaybackId> {
+        self.looping.clone()
+    }
+}
+
+#[cfg(feature = "audio")]
+pub use rodio_backend::RodioBackend;
+
+#[cfg(feature = "audio")]
+mod rodio_backend {
+    use super::{AudioBackend, PlaybackId, SoundData};
+    use log::error;
+    use rodio::{Decoder, OutputStream, OutputStreamHandle, Sink, Source};
+    use std::collections::HashMap;
+    use std::io::Cursor;
+
+    pub struct RodioBackend {
+        // Output stops when the stream is dropped.
+        _stream: OutputStream,
+        handle: OutputStreamHandle,
+        sinks: HashMap<PlaybackId, Sink>,
+        next_id: PlaybackId,
+    }
+
+    impl RodioBackend {
+        // `None` if there is no output device.
+        pub fn new() -> Option<Self> {
+            let (stream, handle) = OutputStream::try_default().ok()?;
+            Some(Self {
+                _stream: stream,
+                handle,
+                sinks: HashMap::new(),
+                next_id: 0,
+            })
+        }
+    }
+
+    impl AudioBackend for RodioBackend {
+        fn play(&mut self, sound: &SoundData, volume: f32, looping: bool) -> Option<PlaybackId> {
+            let decoder = match Decoder::new(Cursor::new(sound.bytes.clone())) {
+                Ok(decoder) => decoder,
+                Err(e) => {
+                    error!("Failed to decode sound {:?}: {}", sound.name, e);
+                    return None;
+                }
+            };
+            let sink = match Sink::try_new(&self.handle) {
+                Ok(sink) => sink,
+                Err(e) => {
+                    error!("Failed to play sound {:?}: {}", sound.name, e);
+                    return None;
+                }
+            };
+            sink.set_volume(volume);
+            if looping {
+                sink.append(decoder.repeat_infinite());
+            } else {
+                sink.append(decoder);
+            }
+
+            self.next_id += 1;
+            self.sinks.insert(self.next_id, sink);
+            Some(self.next_id)
+        }
+
+        fn set_volume(&mut self, id: PlaybackId, volume: f32) {
+            if let Some(sink) = self.sinks.get(&id) {
+                sink.set_volume(volume);
+            }
+        }
+
+        fn stop(&mut self, id: PlaybackId) {
+            if let Some(sink) = self.sinks.remove(&id) {
+                sink.stop();
+            }
+        }
+
+        fn playing(&mut self) -> Vec<PlaybackId> {
+            self.sinks.retain(|_, sink| !sink.empty());
+            self.sinks.keys().copied().collect()
+        }
+    }
+}
@@ -0,0 +1,381 @@
+pub mod backend;
+use backend::{AudioBackend, NullBackend, PlaybackId, SoundData};
+use log::{error, warn};
+use ron::de::from_reader;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::sync::Arc;
+
+pub const MASTER_BUS: &str = "master";
+pub const MUSIC_BUS: &str = "music";
+
+#[derive(Debug, Deserialize)]
+pub struct AudioConfig {
+    // Volume of each bus. Every sound also goes through "master".
+    #[serde(default)]
+    pub buses: HashMap<String, f32>,
+    #[serde(default)]
+    pub sounds: HashMap<String, SoundDesc>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SoundDesc {
+    // A WAV or OGG file under `res/`.
+    pub path: String,
+    #[serde(default = "default_bus")]
+    pub bus: String,
+    #[serde(default = "default_volume")]
+    pub volume: f32,
+}
+
+fn default_bus() -> String {
+    "sfx".to_string()
+}
+
+fn default_volume() -> f32 {
+    1.0
+}
+
+struct Sound {
+    data: SoundData,
+    bus: String,
+    volume: f32,
+}
+
+struct Playback {
+    id: PlaybackId,
+    bus: String,
+    volume: f32,
+}
+
+// Sounds requested by systems, played once the schedule has run.
+#[derive(Default)]
+pub struct AudioEvents {
+    requests: Vec<AudioRequest>,
+}
+
+#[derive(Debug, Clone)]
+pub enum AudioRequest {
+    Play(String),
+    PlayMusic(String),
+    StopMusic,
+}
+
+impl AudioEvents {
+    pub fn play(&mut self, sound: &str) {
+        self.requests.push(AudioRequest::Play(sound.to_string()));
+    }
+
+    pub fn play_music(&mut self, sound: &str) {
+        self.requests
+            .push(AudioRequest::PlayMusic(sound.to_string()));
+    }
+
+    pub fn stop_music(&mut self) {
+        self.requests.push(AudioRequest::StopMusic);
+    }
+
+    pub fn drain(&mut self) -> Vec<AudioRequest> {
+        std::mem::take(&mut self.requests)
+    }
+}
+
+pub struct Audio {
+    backend: Box<dyn AudioBackend>,
+    sounds: HashMap<String, Sound>,
+    buses: HashMap<String, f32>,
+    playing: Vec<Playback>,
+    music: Option<(String, PlaybackId)>,
+}
+
+impl Audio {
+    pub fn new(backend: Box<dyn AudioBackend>) -> Self {
+        Self {
+            backend,
+            sounds: HashMap::new(),
+            buses: HashMap::new(),
+            playing: Vec::new(),
+            music: None,
+        }
+    }
+
+    pub fn load(filename: &str, backend: Box<dyn AudioBackend>) -> Self {
+        let mut audio = Self::new(backend);
+        let f = File::open(crate::utils::from_out_dir(filename)).expect("Failed opening file");
+        let config: AudioConfig = match from_reader(f) {
+            Ok(x) => x,
+            Err(e) => {
+                error!("Failed to load audio config: {}", e);
+                return audio;
+            }
+        };
+
+        audio.buses = config.buses;
+        for (name, desc) in config.sounds {
+            let bytes = match fs::read(crate::utils::from_out_dir(&desc.path)) {
+                Ok(bytes) => bytes,
+                Err(e) => {
+                    error!("Failed to load sound {:?}: {}", name, e);
+                    continue;
+                }
+            };
+            let data = SoundData {
+                name: name.clone(),
+                bytes: Arc::from(bytes),
+            };
+            audio.sounds.insert(
+                name,
+                Sound {
+                    data,
+                    bus: desc.bus,
+                    volume: desc.volume,
+                },
+            );
+        }
+        audio
+    }
+
+    // The output device if there is one, otherwise silence.
+    pub fn default_backend() -> Box<dyn AudioBackend> {
+        #[cfg(feature = "audio")]
+        {
+            if let Some(backend) = backend::RodioBackend::new() {
+                return Box::new(backend);
+            }
+            warn!("No audio output device, sound is disabled");
+        }
+        Box::new(NullBackend::default())
+    }
+
+    pub fn bus_volume(&self, bus: &str) -> f32 {
+        let master = self.buses.get(MASTER_BUS).copied().unwrap_or(1.0);
+        if bus == MASTER_BUS {
+            return master;
+        }
+        master * self.buses.get(bus).copied().unwrap_or(1.0)
+    }
+
+    // The bus's own setting, without master applied.
+    pub fn bus_level(&self, bus: &str) -> f32 {
+        self.buses.get(bus).copied().unwrap_or(1.0)
+    }
+
+    pub fn set_bus_volume(&mut self, bus: &str, volume: f32) {
+        self.buses.insert(bus.to_string(), volume.max(0.0));
+        for playback in &self.playing {
+            let volume = playback.volume * self.bus_volume(&playback.bus);
+            self.backend.set_volume(playback.id, volume);
+        }
+    }
+
+    fn start(&mut self, name: &str, bus: Option<&str>, looping: bool) -> Option<PlaybackId> {
+        let sound = match self.sounds.get(name) {
+            Some(sound) => sound,
+            None => {
+                warn!("Unknown sound {:?}", name);
+                return None;
+            }
+        };
+        let bus = bus.unwrap_or(&sound.bus).to_string();
+        let volume = sound.volume * self.bus_volume(&bus);
+        let id = self.backend.play(&sound.data, volume, looping)?;
+        self.playing.push(Playback {
+            id,
+            bus,
+            volume: sound.volume,
+        });
+        Some(id)
+    }
+
+    // Plays a one-shot effect on its bus.
+    pub fn play(&mut self, name: &str) -> Option<PlaybackId> {
+        self.start(name, None, false)
+    }
+
+    // Loops `name` on the music bus, replacing what was playing. Does nothing if
+    // it is already the current track.
+    pub fn play_music(&mut self, name: &str) {
+        if self
+            .music
+            .as_ref()
+            .map_or(false, |(current, _)| current == name)
+        {
+            return;
+        }
+        self.stop_music();
+        if let Some(id) = self.start(name, Some(MUSIC_BUS), true) {
+            self.music = Some((name.to_string(), id));
+        }
+    }
+
+    pub fn stop_music(&mut self) {
+        if let Some((_, id)) = self.music.take() {
+            self.stop(id);
+        }
+    }
+
+    pub fn stop(&mut self, id: PlaybackId) {
+        self.backend.stop(id);
+        self.playing.retain(|playback| playback.id != id);
+    }
+
+    pub fn handle(&mut self, request: AudioRequest) {
+        match request {
+            AudioRequest::Play(name) => {
+                self.play(&name);
+            }
+            AudioRequest::PlayMusic(name) => self.play_music(&name),
+            AudioRequest::StopMusic => self.stop_music(),
+        }
+    }
+
+    // Drops finished sounds so bus changes only touch live ones.
+    pub fn maintain(&mut self) {
+        let live = self.backend.playing();
+        self.playing.retain(|playback| live.contains(&playback.id));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::components::animate::Animate;
+    use super::super::components::sound::{FrameSound, FrameSounds};
+    use super::super::systems;
+    use super::backend::{AudioCall, RecordingBackend};
+    use super::*;
+    use legion::{IntoQuery, Resources, Schedule, World};
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    // Empty sounds given as (name, bus, volume).
+    fn with_sounds(backend: Box<dyn AudioBackend>, sounds: &[(&str, &str, f32)]) -> Audio {
+        let mut audio = Audio::new(backend);
+        for (name, bus, volume) in sounds {
+            let data = SoundData {
+                name: name.to_string(),
+                bytes: Arc::from(Vec::new()),
+            };
+            let sound = Sound {
+                data,
+                bus: bus.to_string(),
+                volume: *volume,
+            };
+            audio.sounds.insert(name.to_string(), sound);
+        }
+        audio
+    }
+
+    fn recorded(sounds: &[(&str, &str, f32)]) -> (Audio, Rc<RefCell<Vec<AudioCall>>>) {
+        let backend = RecordingBackend::default();
+        let calls = backend.calls();
+        (with_sounds(Box::new(backend), sounds), calls)
+    }
+
+    fn played(calls: &[AudioCall]) -> Vec<&str> {
+        calls
+            .iter()
+            .filter_map(|call| match call {
+                AudioCall::Play { sound, .. } => Some(sound.as_str()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn frame_sounds_play_once_per_frame() {
+        let (mut audio, calls) = recorded(&[("step", "sfx", 1.0)]);
+        let mut world = World::default();
+        let mut resources = Resources::default();
+        resources.insert(AudioEvents::default());
+        let sounds = FrameSounds {
+            triggers: vec![FrameSound {
+                sound: "step".to_string(),
+                states: vec![2],
+                frames: vec![1],
+            }],
+            last_frame: None,
+        };
+        world.push((Animate::new(2, "", ""), sounds));
+        let mut schedule = Schedule::builder()
+            .add_system(systems::frame_sounds_system())
+            .build();
+
+        // Frame 0, frame 1, then frame 1 again.
+        for ticks in &[0, 1, 1] {
+            for anim in <&mut Animate>::query().iter_mut(&mut world) {
+                anim.ticks = *ticks;
+            }
+            schedule.execute(&mut world, &mut resources);
+            for request in resources.get_mut::<AudioEvents>().unwrap().drain() {
+                audio.handle(request);
+            }
+        }
+        assert_eq!(played(&calls.borrow()), vec!["step"]);
+    }
+
+    #[test]
+    fn bus_volume_scales_live_sounds() {
+        let (mut audio, calls) = recorded(&[("hit", "sfx", 0.5), ("theme", "music", 1.0)]);
+        audio.set_bus_volume(MASTER_BUS, 0.5);
+        audio.play("hit");
+        audio.play_music("theme");
+        // The one-shot is over by now, the music isn't.
+        audio.maintain();
+        calls.borrow_mut().clear();
+
+        audio.set_bus_volume(MUSIC_BUS, 0.5);
+        audio.set_bus_volume("sfx", 0.5);
+        assert_eq!(
+            *calls.borrow(),
+            vec![
+                AudioCall::SetVolume {
+                    id: 2,
+                    volume: 0.25
+                },
+                AudioCall::SetVolume {
+                    id: 2,
+                    volume: 0.25
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn first_play_uses_bus_volume() {
+        let (mut audio, calls) = recorded(&[("hit", "sfx", 0.5)]);
+        audio.set_bus_volume(MASTER_BUS, 0.5);
+        audio.play("hit");
+        assert_eq!(
+            *calls.borrow(),
+            vec![AudioCall::Play {
+                id: 1,
+                sound: "hit".to_string(),
+                volume: 0.25,
+                looping: false,
+            }]
+        );
+    }
+
+    #[test]
+    fn music_only_restarts_for_another_track() {
+        let (mut audio, calls) = recorded(&[("theme", "music", 1.0), ("boss", "music", 1.0)]);
+        audio.play_music("theme");
+        audio.maintain();
+        audio.play_music("theme");
+        assert_eq!(played(&calls.borrow()), vec!["theme"]);
+
+        audio.play_music("boss");
+        assert_eq!(played(&calls.borrow()), vec!["theme", "boss"]);
+        assert!(calls.borrow().contains(&AudioCall::Stop { id: 1 }));
+    }
+
+    #[test]
+    fn null_backend_keeps_music_playing() {
+        let backend = Box::new(NullBackend::default());
+        let mut audio = with_sounds(backend, &[("theme", MUSIC_BUS, 1.0)]);
+        audio.play_music("theme");
+        audio.maintain();
+        assert_eq!(audio.playing.len(), 1);
+    }
+}
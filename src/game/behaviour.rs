@@ -0,0 +1,216 @@
+use super::components::animate::{AnimationFunction, DeltaFunction};
+use super::components::input::ProcessFunction;
+use super::components::primitive::DrawFunction as PrimitiveDrawFunction;
+use super::components::sprite::DrawFunction as SpriteDrawFunction;
+use legion::Resources;
+use log::error;
+use ron::de::from_reader;
+use rustpython_vm::pyobject::{ItemProtocol, TryFromObject};
+use rustpython_vm::{scope::Scope, Interpreter};
+use serde::Deserialize;
+use skulpin::winit::event::ElementState;
+use skulpin::winit::event::VirtualKeyCode as Keycode;
+use std::collections::HashMap;
+use std::fs::File;
+
+// A behaviour is either a native function or the name of a function living in
+// the shared python scope.
+pub enum Behaviour<F> {
+    Native(F),
+    Script(String),
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Deserialize)]
+pub enum BehaviourKind {
+    Primitive,
+    Sprite,
+    Animation,
+    Delta,
+    Process,
+}
+
+#[derive(Debug, Deserialize)]
+struct ScriptBehaviourDesc {
+    id: String,
+    kind: BehaviourKind,
+    // A function in the shared python scope.
+    function: String,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct ScriptBehavioursDesc {
+    #[serde(default)]
+    behaviours: Vec<ScriptBehaviourDesc>,
+}
+
+// Maps behaviour ids (e.g. "ball.draw", "character.process") to their
+// implementations so components only have to store the id.
+#[derive(Default)]
+pub struct BehaviourRegistry {
+    primitives: HashMap<String, PrimitiveDrawFunction>,
+    sprites: HashMap<String, SpriteDrawFunction>,
+    animations: HashMap<String, AnimationFunction>,
+    deltas: HashMap<String, Behaviour<DeltaFunction>>,
+    processes: HashMap<String, Behaviour<ProcessFunction>>,
+}
+
+impl BehaviourRegistry {
+    pub fn register_primitive(&mut self, id: &str, draw_fn: PrimitiveDrawFunction) {
+        self.primitives.insert(id.to_string(), draw_fn);
+    }
+
+    pub fn register_sprite(&mut self, id: &str, draw_fn: SpriteDrawFunction) {
+        self.sprites.insert(id.to_string(), draw_fn);
+    }
+
+    pub fn register_animation(&mut self, id: &str, animate_fn: AnimationFunction) {
+        self.animations.insert(id.to_string(), animate_fn);
+    }
+
+    pub fn register_delta(&mut self, id: &str, delta_fn: DeltaFunction) {
+        self.deltas
+            .insert(id.to_string(), Behaviour::Native(delta_fn));
+    }
+
+    pub fn register_process(&mut self, id: &str, process_fn: ProcessFunction) {
+        self.processes
+            .insert(id.to_string(), Behaviour::Native(process_fn));
+    }
+
+    // `function` takes (state: int, input: int) and returns the next state.
+    pub fn register_script_delta(&mut self, id: &str, function: &str) {
+        self.deltas
+            .insert(id.to_string(), Behaviour::Script(function.to_string()));
+    }
+
+    // `function` takes (key: str | None, pressed: bool) and returns an input or None.
+    pub fn register_script_process(&mut self, id: &str, function: &str) {
+        self.processes
+            .insert(id.to_string(), Behaviour::Script(function.to_string()));
+    }
+
+    // Registers the script behaviours listed in the config. Only deltas and
+    // processes can be scripts; draw and animation functions work on the
+    // canvas and the physics world, which scripts can't reach.
+    pub fn load_scripts(&mut self, filename: &str) {
+        let f = File::open(crate::utils::from_out_dir(filename)).expect("Failed opening file");
+        let desc: ScriptBehavioursDesc = match from_reader(f) {
+            Ok(x) => x,
+            Err(e) => {
+                error!("Failed to load script behaviours: {}", e);
+                return;
+            }
+        };
+        for behaviour in desc.behaviours {
+            match behaviour.kind {
+                BehaviourKind::Delta => {
+                    self.register_script_delta(&behaviour.id, &behaviour.function)
+                }
+                BehaviourKind::Process => {
+                    self.register_script_process(&behaviour.id, &behaviour.function)
+                }
+                kind => error!(
+                    "Behaviour {:?} can't be a script; {:?} behaviours must be native",
+                    behaviour.id, kind
+                ),
+            }
+        }
+    }
+
+    // Ids are checked once when their components are spawned, so the lookups
+    // skip unknown ones quietly.
+    pub fn primitive(&self, id: &str) -> Option<PrimitiveDrawFunction> {
+        self.primitives.get(id).copied()
+    }
+
+    pub fn sprite(&self, id: &str) -> Option<SpriteDrawFunction> {
+        self.sprites.get(id).copied()
+    }
+
+    pub fn animation(&self, id: &str) -> Option<AnimationFunction> {
+        self.animations.get(id).copied()
+    }
+
+    pub fn has_delta(&self, id: &str) -> bool {
+        self.deltas.contains_key(id)
+    }
+
+    pub fn has_process(&self, id: &str) -> bool {
+        self.processes.contains_key(id)
+    }
+
+    pub fn delta(&self, id: &str, state: u32, input: u32, resources: &Resources) -> u32 {
+        match self.deltas.get(id) {
+            Some(Behaviour::Native(delta_fn)) => delta_fn(state, input),
+            Some(Behaviour::Script(function)) => {
+                call_script_delta(resources, function, state, input).unwrap_or(state)
+            }
+            None => state,
+        }
+    }
+
+    pub fn process(
+        &self,
+        id: &str,
+        keycode: Option<Keycode>,
+        key_state: &ElementState,
+        resources: &Resources,
+    ) -> Option<u32> {
+        match self.processes.get(id) {
+            Some(Behaviour::Native(process_fn)) => process_fn(keycode, key_state),
+            Some(Behaviour::Script(function)) => {
+                call_script_process(resources, function, keycode, key_state)
+            }
+            None => None,
+        }
+    }
+}
+
+fn call_script_delta(resources: &Resources, function: &str, state: u32, input: u32) -> Option<u32> {
+    let runtime = resources.get::<Interpreter>()?;
+    let scope = resources.get::<Scope>()?;
+    let result = runtime.enter(|vm| {
+        let function = scope.globals.get_item(function, vm)?;
+        let args = vec![vm.ctx.new_int(state), vm.ctx.new_int(input)];
+        let ret = vm.invoke(&function, args)?;
+        u32::try_from_object(vm, ret)
+    });
+    match result {
+        Ok(next) => Some(next),
+        Err(_) => {
+            error!("Script delta {:?} failed", function);
+            None
+        }
+    }
+}
+
+fn call_script_process(
+    resources: &Resources,
+    function: &str,
+    keycode: Option<Keycode>,
+    key_state: &ElementState,
+) -> Option<u32> {
+    let runtime = resources.get::<Interpreter>()?;
+    let scope = resources.get::<Scope>()?;
+    let result = runtime.enter(|vm| {
+        let function = scope.globals.get_item(function, vm)?;
+        let key = match keycode {
+            Some(keycode) => vm.ctx.new_str(format!("{:?}", keycode)),
+            None => vm.ctx.none(),
+        };
+        let pressed = vm.ctx.new_bool(*key_state == ElementState::Pressed);
+        let ret = vm.invoke(&function, vec![key, pressed])?;
+        if vm.is_none(&ret) {
+            Ok(None)
+        } else {
+            u32::try_from_object(vm, ret).map(Some)
+        }
+    });
+    match result {
+        Ok(input) => input,
+        Err(_) => {
+            error!("Script process {:?} failed", function);
+            None
+        }
+    }
+}
@@ -0,0 +1,126 @@
+use super::super::ai::blackboard::{Blackboard, Value};
+use super::super::ai::tree::{NodeMemory, Steering};
+use super::super::query::{PhysicsQuery, QueryFilter};
+use super::combat::Health;
+use legion::Entity;
+use nalgebra::{Point2, Vector2};
+use serde::Deserialize;
+
+// `Animate` inputs sent when an agent starts moving left or right, or stops.
+#[derive(Debug, Copy, Clone, Deserialize)]
+pub struct MoveInputs {
+    pub left: u32,
+    pub right: u32,
+    pub stop: u32,
+}
+
+// Runs a tree from `BehaviourTrees`, which steers the entity's
+// `CharacterController` the way keys steer the adventurer's.
+#[derive(Debug, Clone, Deserialize)]
+pub struct Agent {
+    pub tree: String,
+    // Hurtbox team to go after, e.g. "player".
+    #[serde(default)]
+    pub target_team: Option<String>,
+    // Targets further away than this, or behind anything `blockers` hits, go
+    // unnoticed.
+    #[serde(default = "default_sight")]
+    pub sight: f32,
+    #[serde(default)]
+    pub inputs: Option<MoveInputs>,
+    // Starting entries, e.g. flags checked by the tree.
+    #[serde(default)]
+    pub blackboard: Blackboard,
+    #[serde(skip)]
+    pub memory: NodeMemory,
+    #[serde(skip)]
+    pub steering: Steering,
+    // Seconds since the tree last ticked.
+    #[serde(skip)]
+    pub since_tick: f32,
+}
+
+fn default_sight() -> f32 {
+    4.0
+}
+
+// Something agents can go after, gathered before they tick.
+#[derive(Debug, Clone)]
+pub struct Target {
+    pub entity: Entity,
+    pub team: Option<String>,
+    pub position: Vector2<f32>,
+}
+
+impl Agent {
+    // Writes the agent's own state and its nearest target in sight to the
+    // blackboard.
+    pub fn perceive(
+        &mut self,
+        entity: Entity,
+        position: Vector2<f32>,
+        health: Option<&Health>,
+        targets: &[Target],
+        physics: &PhysicsQuery,
+        blockers: &QueryFilter,
+    ) {
+        let visible = |target: &Target| {
+            physics.line_of_sight(
+                Point2::from(position),
+                Point2::from(target.position),
+                blockers,
+            )
+        };
+        let nearest = targets
+            .iter()
+            .filter(|target| target.entity != entity)
+            .filter(|target| self.target_team.is_some() && target.team == self.target_team)
+            .map(|target| (target, (target.position - position).norm()))
+            .filter(|(target, distance)| *distance <= self.sight && visible(target))
+            .min_by(|(_, a), (_, b)| a.partial_cmp(b).unwrap());
+
+        let blackboard = &mut self.blackboard;
+        blackboard.set_vector("position", position);
+        if let Some(health) = health {
+            let fraction = health.current / health.max.max(std::f32::EPSILON);
+            blackboard.set("health", Value::Number(fraction));
+        }
+        match nearest {
+            Some((target, distance)) => {
+                blackboard.set("target", Value::Entity(target.entity));
+                blackboard.set_vector("target_position", target.position);
+                blackboard.set("target_distance", Value::Number(distance));
+            }
+            None => {
+                blackboard.remove("target");
+                blackboard.remove("target_position");
+                blackboard.remove("target_distance");
+            }
+        }
+    }
+
+    // The `Animate` input for a change of steering, if any.
+    pub fn move_input(&self, previous: &Steering) -> Option<u32> {
+        let inputs = self.inputs?;
+        let now = direction(self.steering.axis);
+        if now == direction(previous.axis) {
+            return None;
+        }
+        let input = match now {
+            -1 => inputs.left,
+            1 => inputs.right,
+            _ => inputs.stop,
+        };
+        Some(input)
+    }
+}
+
+fn direction(axis: f32) -> i8 {
+    if axis < 0.0 {
+        -1
+    } else if axis > 0.0 {
+        1
+    } else {
+        0
+    }
+}
@@ -1,34 +1,65 @@
+use super::super::behaviour::BehaviourRegistry;
+use legion::Resources;
 use nphysics2d::object::{DefaultBodyHandle, DefaultBodySet};
 use num_traits::FromPrimitive;
+use serde::{Deserialize, Serialize};
 
-type AnimationFunction = fn(&mut Animate, &DefaultBodyHandle, &mut DefaultBodySet<f32>) -> ();
+pub type DeltaFunction = fn(u32, u32) -> u32;
+pub type AnimationFunction = fn(&mut Animate, &DefaultBodyHandle, &mut DefaultBodySet<f32>) -> ();
 
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Animate {
-    delta_fn: fn(u32, u32) -> u32,
+    pub delta_id: String,
+    pub animate_id: String,
     current: u32,
-    animate_fn: AnimationFunction,
+    #[serde(default)]
     pub ticks: usize,
+    // Seconds spent on the current frame.
+    #[serde(skip)]
+    pub elapsed: f32,
 }
 
 impl Animate {
-    pub fn new(current: u32, delta_fn: fn(u32, u32) -> u32, animate_fn: AnimationFunction) -> Self {
+    pub fn new(current: u32, delta_id: &str, animate_id: &str) -> Self {
         Self {
             current,
-            delta_fn,
-            animate_fn,
+            delta_id: delta_id.to_string(),
+            animate_id: animate_id.to_string(),
             ticks: 0,
+            elapsed: 0.0,
         }
     }
 
-    pub fn delta(&mut self, input: u32) {
-        self.current = (self.delta_fn)(self.current, input);
+    pub fn delta(&mut self, input: u32, registry: &BehaviourRegistry, resources: &Resources) {
+        let next = registry.delta(&self.delta_id, self.current, input, resources);
+        self.play(next);
+    }
+
+    // Switches to another state, e.g. a named animation's sprite sheet key.
+    pub fn play(&mut self, state: u32) {
+        if state != self.current {
+            self.ticks = 0;
+            self.elapsed = 0.0;
+        }
+        self.current = state;
+    }
+
+    pub fn current(&self) -> u32 {
+        self.current
     }
 
     pub fn state<T: FromPrimitive>(&self) -> T {
         T::from_u32(self.current).unwrap()
     }
 
-    pub fn animate(&mut self, body_handle: &DefaultBodyHandle, bodies: &mut DefaultBodySet<f32>) {
-        (self.animate_fn)(self, body_handle, bodies);
+    pub fn animate(
+        &mut self,
+        body_handle: &DefaultBodyHandle,
+        bodies: &mut DefaultBodySet<f32>,
+        registry: &BehaviourRegistry,
+    ) {
+        if let Some(animate_fn) = registry.animation(&self.animate_id) {
+            animate_fn(self, body_handle, bodies);
+        }
     }
 }
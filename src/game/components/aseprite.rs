@@ -0,0 +1,235 @@
+use super::sprite::{Clip, ClipSlice, Rect};
+use image::DynamicImage;
+use log::error;
+use serde::Deserialize;
+use std::collections::{BTreeMap, HashMap};
+use std::fs::File;
+use std::path::Path;
+
+// The parts of Aseprite's JSON export (File > Export Sprite Sheet) that we use.
+#[derive(Debug, Deserialize)]
+struct AsepriteDesc {
+    frames: FramesDesc,
+    meta: MetaDesc,
+}
+
+// Aseprite writes frames either as an array or as a map keyed by file name.
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum FramesDesc {
+    Array(Vec<FrameDesc>),
+    Hash(BTreeMap<String, FrameDesc>),
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct FrameDesc {
+    #[serde(default)]
+    filename: String,
+    frame: RectDesc,
+    // Where the trimmed frame sits inside the untrimmed one.
+    #[serde(default)]
+    sprite_source_size: Option<RectDesc>,
+    // In milliseconds.
+    duration: u32,
+}
+
+#[derive(Debug, Copy, Clone, Deserialize)]
+struct RectDesc {
+    x: i32,
+    y: i32,
+    w: i32,
+    h: i32,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct MetaDesc {
+    image: String,
+    #[serde(default)]
+    frame_tags: Vec<TagDesc>,
+    #[serde(default)]
+    slices: Vec<SliceDesc>,
+}
+
+#[derive(Debug, Deserialize)]
+struct TagDesc {
+    name: String,
+    from: usize,
+    to: usize,
+    #[serde(default)]
+    direction: Direction,
+}
+
+#[derive(Debug, Copy, Clone, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum Direction {
+    Forward,
+    Reverse,
+    Pingpong,
+}
+
+impl Default for Direction {
+    fn default() -> Self {
+        Self::Forward
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct SliceDesc {
+    name: String,
+    keys: Vec<SliceKeyDesc>,
+}
+
+// A key applies from its frame until the next key.
+#[derive(Debug, Deserialize)]
+struct SliceKeyDesc {
+    frame: usize,
+    bounds: RectDesc,
+    #[serde(default)]
+    pivot: Option<PointDesc>,
+}
+
+#[derive(Debug, Copy, Clone, Deserialize)]
+struct PointDesc {
+    x: i32,
+    y: i32,
+}
+
+pub struct AsepriteSheet {
+    pub clips: HashMap<u32, Vec<Clip>>,
+    pub names: HashMap<String, u32>,
+}
+
+// Tags become animations keyed in the order they appear, and slices become
+// per-frame hitboxes. A slice with a pivot sets the frame's anchor.
+pub fn load(filename: &str) -> AsepriteSheet {
+    let f = File::open(filename).expect("Failed opening file");
+    let desc: AsepriteDesc = match serde_json::from_reader(f) {
+        Ok(x) => x,
+        Err(e) => {
+            error!("Failed to load aseprite sheet {:?}: {}", filename, e);
+
+            std::process::exit(1);
+        }
+    };
+
+    // The image path is relative to the JSON file.
+    let image_path = Path::new(filename)
+        .parent()
+        .unwrap_or_else(|| Path::new(""))
+        .join(&desc.meta.image);
+    let img = image::open(&image_path).unwrap();
+
+    let frames = match desc.frames {
+        FramesDesc::Array(frames) => frames,
+        FramesDesc::Hash(frames) => {
+            let mut frames: Vec<FrameDesc> = frames
+                .into_iter()
+                .map(|(filename, frame)| FrameDesc { filename, ..frame })
+                .collect();
+            frames.sort_by_key(|frame| frame_number(&frame.filename));
+            frames
+        }
+    };
+
+    let mut tags = desc.meta.frame_tags;
+    if tags.is_empty() {
+        tags.push(TagDesc {
+            name: "default".to_string(),
+            from: 0,
+            to: frames.len().saturating_sub(1),
+            direction: Direction::Forward,
+        });
+    }
+
+    let mut sheet = AsepriteSheet {
+        clips: HashMap::new(),
+        names: HashMap::new(),
+    };
+    for (key, tag) in tags.iter().enumerate() {
+        let clips = tag_frames(tag)
+            .into_iter()
+            .filter_map(|i| frames.get(i).map(|frame| (i, frame)))
+            .map(|(i, frame)| make_clip(&img, i, frame, &desc.meta.slices))
+            .collect();
+        sheet.clips.insert(key as u32, clips);
+        sheet.names.insert(tag.name.clone(), key as u32);
+    }
+    sheet
+}
+
+fn tag_frames(tag: &TagDesc) -> Vec<usize> {
+    let forward: Vec<usize> = (tag.from..=tag.to).collect();
+    match tag.direction {
+        Direction::Forward => forward,
+        Direction::Reverse => forward.into_iter().rev().collect(),
+        // Plays back without repeating either end frame.
+        Direction::Pingpong => {
+            let back = forward.iter().rev().skip(1);
+            let back: Vec<usize> = back
+                .take(forward.len().saturating_sub(2))
+                .copied()
+                .collect();
+            forward.into_iter().chain(back).collect()
+        }
+    }
+}
+
+fn make_clip(img: &DynamicImage, index: usize, frame: &FrameDesc, slices: &[SliceDesc]) -> Clip {
+    let rect = Rect {
+        x: frame.frame.x as u32,
+        y: frame.frame.y as u32,
+        w: frame.frame.w as u32,
+        h: frame.frame.h as u32,
+    };
+    let offset = frame
+        .sprite_source_size
+        .map_or((0, 0), |source| (source.x, source.y));
+
+    let keys: Vec<(&str, &SliceKeyDesc)> = slices
+        .iter()
+        .filter_map(|slice| {
+            slice
+                .keys
+                .iter()
+                .filter(|key| key.frame <= index)
+                .max_by_key(|key| key.frame)
+                .map(|key| (slice.name.as_str(), key))
+        })
+        .collect();
+
+    let anchor = keys.iter().find_map(|(_, key)| {
+        key.pivot.map(|pivot| {
+            (
+                (key.bounds.x + pivot.x - offset.0) as f32,
+                (key.bounds.y + pivot.y - offset.1) as f32,
+            )
+        })
+    });
+
+    let mut clip = Clip::new(img, rect, false, false, anchor);
+    clip.duration = frame.duration as f32 / 1000.0;
+    let (w, h) = (rect.w as f32, rect.h as f32);
+    for (name, key) in keys {
+        let x = (key.bounds.x - offset.0) as f32;
+        let y = (key.bounds.y - offset.1) as f32;
+        let (sw, sh) = (key.bounds.w as f32, key.bounds.h as f32);
+        clip.slices.push(ClipSlice {
+            name: name.to_string(),
+            rect: (x / w, 1.0 - (y + sh) / h, sw / w, sh / h),
+        });
+    }
+    clip
+}
+
+// Trailing digits of an exported frame name, e.g. "adventurer 12.aseprite".
+fn frame_number(filename: &str) -> usize {
+    let stem = filename.rfind('.').map_or(filename, |dot| &filename[..dot]);
+    let digits: Vec<char> = stem
+        .chars()
+        .rev()
+        .take_while(|c| c.is_ascii_digit())
+        .collect();
+    digits.iter().rev().collect::<String>().parse().unwrap_or(0)
+}
@@ -0,0 +1,159 @@
+use legion::Entity;
+use nalgebra::Vector2;
+use nphysics2d::object::DefaultColliderHandle;
+use serde::Deserialize;
+use std::collections::{HashMap, HashSet};
+
+#[derive(Debug, Copy, Clone, Deserialize)]
+pub enum DeathAction {
+    Despawn { delay: f32 },
+    // Back to the spawn point with full health.
+    Respawn { delay: f32 },
+    // Pushes the game over state, e.g. for the player.
+    GameOver { delay: f32 },
+}
+
+impl DeathAction {
+    pub fn delay(&self) -> f32 {
+        match *self {
+            Self::Despawn { delay } | Self::Respawn { delay } | Self::GameOver { delay } => delay,
+        }
+    }
+}
+
+impl Default for DeathAction {
+    fn default() -> Self {
+        Self::Despawn { delay: 1.0 }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Health {
+    pub max: f32,
+    // Seconds after a hit during which further hits are ignored.
+    #[serde(default = "default_invulnerability")]
+    pub invulnerability: f32,
+    // Seconds after a hit during which a `CharacterController` ignores input.
+    #[serde(default = "default_stun")]
+    pub stun: f32,
+    // `Animate` state to switch to on death, keyed by the state at the time.
+    #[serde(default)]
+    pub death_states: HashMap<u32, u32>,
+    #[serde(default)]
+    pub on_death: DeathAction,
+    // Mirrors the entity's health into the "health" and "max_health" HUD values.
+    #[serde(default)]
+    pub show_on_hud: bool,
+    #[serde(skip)]
+    pub current: f32,
+    #[serde(skip)]
+    pub invulnerable_for: f32,
+    // Seconds since death, until the death action has run.
+    #[serde(skip)]
+    pub dead_for: Option<f32>,
+    // Where and in which state the entity spawned, for respawning.
+    #[serde(skip)]
+    pub spawn_point: Vector2<f32>,
+    #[serde(skip)]
+    pub spawn_state: Option<u32>,
+}
+
+fn default_invulnerability() -> f32 {
+    1.0
+}
+
+fn default_stun() -> f32 {
+    0.3
+}
+
+impl Health {
+    pub fn reset(&mut self) {
+        self.current = self.max;
+        self.invulnerable_for = 0.0;
+        self.dead_for = None;
+    }
+
+    pub fn is_dead(&self) -> bool {
+        self.current <= 0.0
+    }
+
+    // Returns whether the damage was taken. Dead or invulnerable entities
+    // ignore it.
+    pub fn damage(&mut self, amount: f32) -> bool {
+        if self.is_dead() || self.invulnerable_for > 0.0 {
+            return false;
+        }
+        self.current -= amount;
+        self.invulnerable_for = self.invulnerability;
+        if self.is_dead() {
+            self.dead_for = Some(0.0);
+        }
+        true
+    }
+
+    pub fn heal(&mut self, amount: f32) {
+        if !self.is_dead() {
+            self.current = (self.current + amount).min(self.max);
+        }
+    }
+}
+
+// Deals damage to overlapping `Hurtbox`es of other teams.
+#[derive(Debug, Clone, Deserialize)]
+pub struct Hitbox {
+    pub damage: f32,
+    // Speed given to the target, away from the hitbox and slightly up.
+    #[serde(default)]
+    pub knockback: f32,
+    #[serde(default)]
+    pub team: Option<String>,
+    // Index into the entity's `PhysicsBody` colliders, or any of them.
+    #[serde(default)]
+    pub collider: Option<usize>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Hurtbox {
+    #[serde(default)]
+    pub team: Option<String>,
+    #[serde(default)]
+    pub collider: Option<usize>,
+}
+
+impl Hitbox {
+    pub fn hits(&self, hurtbox: &Hurtbox) -> bool {
+        self.team.is_none() || self.team != hurtbox.team
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct DamageEvent {
+    pub target: Entity,
+    pub source: Option<Entity>,
+    pub amount: f32,
+    pub knockback: Vector2<f32>,
+}
+
+// Damage waiting to be applied by `apply_damage`. Anything may push to it.
+#[derive(Debug, Default)]
+pub struct DamageEvents {
+    events: Vec<DamageEvent>,
+}
+
+impl DamageEvents {
+    pub fn push(&mut self, event: DamageEvent) {
+        self.events.push(event);
+    }
+
+    pub fn drain(&mut self) -> Vec<DamageEvent> {
+        std::mem::take(&mut self.events)
+    }
+}
+
+// Hitbox and hurtbox colliders currently overlapping, as (hitbox, hurtbox).
+// Kept across frames so standing in a hazard hurts again once invulnerability
+// wears off.
+#[derive(Debug, Default)]
+pub struct CombatContacts {
+    pub pairs: HashSet<(DefaultColliderHandle, DefaultColliderHandle)>,
+}
@@ -0,0 +1,257 @@
+use super::super::physics::Physics;
+use super::physics_body::PhysicsBody;
+use nalgebra::Vector2;
+use nphysics2d::object::{DefaultBodySet, DefaultColliderSet};
+use serde::{Deserialize, Serialize};
+use skulpin::winit::event::ElementState;
+use skulpin::winit::event::VirtualKeyCode as Keycode;
+
+// Key names as printed by `{:?}` on a `VirtualKeyCode`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ControllerBindings {
+    pub left: String,
+    pub right: String,
+    pub jump: String,
+}
+
+impl Default for ControllerBindings {
+    fn default() -> Self {
+        Self {
+            left: "Left".to_string(),
+            right: "Right".to_string(),
+            jump: "Space".to_string(),
+        }
+    }
+}
+
+// Platformer movement, stepped together with the physics world. Speeds are in
+// units per second, times in seconds and `max_slope` in radians. The body
+// should have `lock_rotation` set so it stays upright.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CharacterController {
+    pub bindings: ControllerBindings,
+    pub max_speed: f32,
+    pub acceleration: f32,
+    pub deceleration: f32,
+    pub air_control: f32,
+    pub jump_speed: f32,
+    // Upward velocity is scaled by this when jump is released early.
+    pub jump_cut: f32,
+    pub coyote_time: f32,
+    pub jump_buffer: f32,
+    pub max_slope: f32,
+    #[serde(skip)]
+    state: ControllerState,
+}
+
+#[derive(Debug, Clone, Default)]
+struct ControllerState {
+    left: bool,
+    right: bool,
+    jump_held: bool,
+    jump_released: bool,
+    jump_buffered: f32,
+    since_grounded: f32,
+    jumping: bool,
+    grounded: bool,
+    ground_normal: Option<Vector2<f32>>,
+    // Seconds left during which input is ignored, e.g. after being hit.
+    stunned: f32,
+}
+
+impl Default for CharacterController {
+    fn default() -> Self {
+        Self {
+            bindings: ControllerBindings::default(),
+            max_speed: 2.0,
+            acceleration: 20.0,
+            deceleration: 30.0,
+            air_control: 0.5,
+            jump_speed: 5.0,
+            jump_cut: 0.5,
+            coyote_time: 0.1,
+            jump_buffer: 0.1,
+            max_slope: std::f32::consts::FRAC_PI_4,
+            state: ControllerState::default(),
+        }
+    }
+}
+
+impl CharacterController {
+    pub fn is_grounded(&self) -> bool {
+        self.state.grounded
+    }
+
+    pub fn move_axis(&self) -> f32 {
+        match (self.state.left, self.state.right) {
+            (true, false) => -1.0,
+            (false, true) => 1.0,
+            _ => 0.0,
+        }
+    }
+
+    pub fn stun(&mut self, seconds: f32) {
+        self.state.stunned = self.state.stunned.max(seconds);
+        self.state.left = false;
+        self.state.right = false;
+    }
+
+    pub fn recover(&mut self) {
+        self.state.stunned = 0.0;
+    }
+
+    // Steers the controller without keys, e.g. from AI. Negative `axis` moves
+    // left and positive right; `jump` taps jump, which always goes full height.
+    pub fn drive(&mut self, axis: f32, jump: bool) {
+        self.state.left = axis < 0.0;
+        self.state.right = axis > 0.0;
+        if jump {
+            self.state.jump_buffered = self.jump_buffer;
+        }
+    }
+
+    pub fn send(&mut self, keycode: Option<Keycode>, key_state: &ElementState) {
+        let key = match keycode {
+            Some(keycode) => format!("{:?}", keycode),
+            None => return,
+        };
+        let pressed = *key_state == ElementState::Pressed;
+        if key == self.bindings.left {
+            self.state.left = pressed;
+        }
+        if key == self.bindings.right {
+            self.state.right = pressed;
+        }
+        if key == self.bindings.jump {
+            // Ignore key repeat so holding jump doesn't keep refreshing the buffer.
+            if pressed && !self.state.jump_held {
+                self.state.jump_buffered = self.jump_buffer;
+            }
+            if !pressed && self.state.jump_held {
+                self.state.jump_released = true;
+            }
+            self.state.jump_held = pressed;
+        }
+    }
+
+    pub fn step(
+        &mut self,
+        physics_body: &PhysicsBody,
+        dt: f32,
+        physics: &Physics,
+        bodies: &mut DefaultBodySet<f32>,
+        colliders: &DefaultColliderSet<f32>,
+    ) {
+        self.detect_ground(physics_body, physics, colliders);
+
+        let body = match bodies.rigid_body_mut(physics_body.body) {
+            Some(body) => body,
+            None => return,
+        };
+        // Leave the velocity to knockback and gravity.
+        if self.state.stunned > 0.0 {
+            self.state.stunned -= dt;
+            return;
+        }
+
+        let target = self.move_axis() * self.max_speed;
+        let mut velocity = body.velocity().linear;
+        let state = &mut self.state;
+        if state.grounded {
+            state.since_grounded = 0.0;
+            // The ground contact can outlive the first step of a jump.
+            if velocity.y <= 0.0 {
+                state.jumping = false;
+            }
+        } else {
+            state.since_grounded += dt;
+        }
+        state.jump_buffered = (state.jump_buffered - dt).max(0.0);
+
+        // Move along the ground so slopes are walked rather than bumped into.
+        let tangent = match state.ground_normal {
+            Some(n) if state.grounded => Vector2::new(n.y, -n.x),
+            _ => Vector2::x(),
+        };
+        let along = velocity.dot(&tangent);
+        let rate = if target != 0.0 {
+            self.acceleration
+        } else {
+            self.deceleration
+        };
+        let rate = if state.grounded {
+            rate
+        } else {
+            rate * self.air_control
+        };
+        let along = move_towards(along, target, rate * dt);
+
+        if state.grounded {
+            velocity = tangent * along;
+        } else {
+            velocity.x = along;
+        }
+
+        let can_jump = !state.jumping && state.since_grounded <= self.coyote_time;
+        if state.jump_buffered > 0.0 && can_jump {
+            velocity.y = self.jump_speed;
+            state.jump_buffered = 0.0;
+            state.jumping = true;
+            state.grounded = false;
+        }
+        if state.jump_released {
+            if state.jumping && velocity.y > 0.0 {
+                velocity.y *= self.jump_cut;
+            }
+            state.jump_released = false;
+        }
+
+        body.set_linear_velocity(velocity);
+    }
+
+    fn detect_ground(
+        &mut self,
+        physics_body: &PhysicsBody,
+        physics: &Physics,
+        colliders: &DefaultColliderSet<f32>,
+    ) {
+        let min_normal_y = self.max_slope.cos();
+        let mut ground_normal: Option<Vector2<f32>> = None;
+        for &collider_handle in physics_body.colliders.iter().filter(|handle| {
+            colliders
+                .get(**handle)
+                .map_or(false, |collider| !collider.is_sensor())
+        }) {
+            let contacts =
+                physics
+                    .geometrical_world
+                    .contacts_with(colliders, collider_handle, true);
+            for (handle1, _, _, _, _, manifold) in contacts.into_iter().flatten() {
+                for contact in manifold.contacts() {
+                    // Contact normals point from the first collider to the second.
+                    let normal = if handle1 == collider_handle {
+                        -contact.contact.normal.into_inner()
+                    } else {
+                        contact.contact.normal.into_inner()
+                    };
+                    if normal.y >= min_normal_y
+                        && ground_normal.map_or(true, |best| normal.y > best.y)
+                    {
+                        ground_normal = Some(normal);
+                    }
+                }
+            }
+        }
+        self.state.grounded = ground_normal.is_some();
+        self.state.ground_normal = ground_normal;
+    }
+}
+
+fn move_towards(current: f32, target: f32, max_delta: f32) -> f32 {
+    if (target - current).abs() <= max_delta {
+        target
+    } else {
+        current + (target - current).signum() * max_delta
+    }
+}
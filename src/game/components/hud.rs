@@ -0,0 +1,166 @@
+use super::particles::Rgba;
+use serde::Deserialize;
+use std::collections::HashMap;
+
+// Named numbers shown by HUD widgets, e.g. "score" or "health". Systems write
+// them, `update_hud` turns them into text and bar fills.
+#[derive(Debug, Default)]
+pub struct HudValues {
+    values: HashMap<String, f32>,
+}
+
+impl HudValues {
+    pub fn get(&self, name: &str) -> Option<f32> {
+        self.values.get(name).copied()
+    }
+
+    pub fn set(&mut self, name: &str, value: f32) {
+        self.values.insert(name.to_string(), value);
+    }
+
+    pub fn add(&mut self, name: &str, amount: f32) {
+        *self.values.entry(name.to_string()).or_insert(0.0) += amount;
+    }
+}
+
+// Which point of the screen, and of the widget, line up.
+#[derive(Debug, Copy, Clone, PartialEq, Deserialize)]
+pub enum Anchor {
+    TopLeft,
+    Top,
+    TopRight,
+    Left,
+    Center,
+    Right,
+    BottomLeft,
+    Bottom,
+    BottomRight,
+}
+
+impl Default for Anchor {
+    fn default() -> Self {
+        Self::TopLeft
+    }
+}
+
+impl Anchor {
+    // Fractions of the width and height, from the top left.
+    fn fractions(self) -> (f32, f32) {
+        match self {
+            Self::TopLeft => (0.0, 0.0),
+            Self::Top => (0.5, 0.0),
+            Self::TopRight => (1.0, 0.0),
+            Self::Left => (0.0, 0.5),
+            Self::Center => (0.5, 0.5),
+            Self::Right => (1.0, 0.5),
+            Self::BottomLeft => (0.0, 1.0),
+            Self::Bottom => (0.5, 1.0),
+            Self::BottomRight => (1.0, 1.0),
+        }
+    }
+
+    // Top left corner of a `size` box anchored in `screen`. `offset` moves it
+    // towards the middle of the screen, so margins read the same from any edge.
+    pub fn place(self, size: (f32, f32), screen: (f32, f32), offset: (f32, f32)) -> (f32, f32) {
+        let (fx, fy) = self.fractions();
+        let inward = |fraction: f32, offset: f32| (1.0 - fraction * 2.0) * offset;
+        (
+            (screen.0 - size.0) * fx + inward(fx, offset.0),
+            (screen.1 - size.1) * fy + inward(fy, offset.1),
+        )
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub enum WidgetKind {
+    Label(String),
+    // `format` with "{}" replaced by the value, rounded to `precision` decimals.
+    Value {
+        value: String,
+        format: String,
+        #[serde(default)]
+        precision: usize,
+    },
+    // Frames per second from `FrameTiming`.
+    Fps,
+    // Filled by `value` over `max`, in logical pixels.
+    Bar {
+        value: String,
+        max: String,
+        size: (f32, f32),
+        #[serde(default = "default_background")]
+        background: Rgba,
+    },
+    // A row of the player's `Inventory` slots, `slot_size` logical pixels
+    // square. The selected one is outlined in the widget color.
+    Inventory {
+        slot_size: f32,
+        #[serde(default = "default_background")]
+        background: Rgba,
+    },
+}
+
+fn default_background() -> Rgba {
+    (0.0, 0.0, 0.0, 0.5)
+}
+
+// A piece of screen-space UI, drawn after the world in logical pixels.
+#[derive(Debug, Clone, Deserialize)]
+pub struct HudWidget {
+    pub kind: WidgetKind,
+    #[serde(default)]
+    pub anchor: Anchor,
+    #[serde(default)]
+    pub offset: (f32, f32),
+    // A font from the renderer's font config.
+    #[serde(default = "default_font")]
+    pub font: String,
+    #[serde(default = "default_color")]
+    pub color: Rgba,
+    // What to show, or `None` while a value it needs is missing.
+    #[serde(skip)]
+    pub text: Option<String>,
+    #[serde(skip)]
+    pub fill: Option<f32>,
+}
+
+fn default_font() -> String {
+    "default".to_string()
+}
+
+fn default_color() -> Rgba {
+    (1.0, 1.0, 1.0, 1.0)
+}
+
+impl HudWidget {
+    // Updates `text` and `fill` from the values. Returns whether they changed.
+    pub fn refresh(&mut self, values: &HudValues, fps: f32) -> bool {
+        let (text, fill) = match &self.kind {
+            WidgetKind::Label(label) => (Some(label.clone()), None),
+            WidgetKind::Value {
+                value,
+                format,
+                precision,
+            } => {
+                let text = values
+                    .get(value)
+                    .map(|value| format.replacen("{}", &format!("{:.*}", *precision, value), 1));
+                (text, None)
+            }
+            WidgetKind::Fps => (Some(format!("FPS {:.0}", fps)), None),
+            WidgetKind::Bar { value, max, .. } => {
+                let fill = match (values.get(value), values.get(max)) {
+                    (Some(value), Some(max)) if max > 0.0 => Some((value / max).max(0.0).min(1.0)),
+                    _ => None,
+                };
+                (None, fill)
+            }
+            // Drawn straight from the inventory.
+            WidgetKind::Inventory { .. } => (None, None),
+        };
+        let changed = text != self.text || fill != self.fill;
+        self.text = text;
+        self.fill = fill;
+        changed
+    }
+}
@@ -1,24 +1,31 @@
-use super::animate::Animate;
+use super::super::behaviour::BehaviourRegistry;
+use legion::Resources;
+use serde::{Deserialize, Serialize};
 use skulpin::winit::event::ElementState;
 use skulpin::winit::event::VirtualKeyCode as Keycode;
 
-type ProcessFunction = fn(Option<Keycode>, &ElementState, &mut Animate);
+// Maps a key event to an input for the entity's `Animate`, if any.
+pub type ProcessFunction = fn(Option<Keycode>, &ElementState) -> Option<u32>;
 
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct KeyInputHandler {
-    process_fn: ProcessFunction,
+    pub process_id: String,
 }
 
 impl KeyInputHandler {
-    pub fn new(process_fn: ProcessFunction) -> Self {
-        Self { process_fn }
+    pub fn new(process_id: &str) -> Self {
+        Self {
+            process_id: process_id.to_string(),
+        }
     }
 
     pub fn process(
         &self,
         keycode: Option<Keycode>,
         key_state: &ElementState,
-        controlled_character: &mut Animate,
-    ) {
-        (self.process_fn)(keycode, key_state, controlled_character);
+        registry: &BehaviourRegistry,
+        resources: &Resources,
+    ) -> Option<u32> {
+        registry.process(&self.process_id, keycode, key_state, resources)
     }
 }
@@ -0,0 +1,202 @@
+use serde::Deserialize;
+use skulpin::winit::event::ElementState;
+use skulpin::winit::event::VirtualKeyCode as Keycode;
+
+// Key names as printed by `{:?}` on a `VirtualKeyCode`.
+#[derive(Debug, Clone, Deserialize)]
+pub struct InventoryBindings {
+    // Selects the next slot.
+    pub next: String,
+    pub use_item: String,
+}
+
+impl Default for InventoryBindings {
+    fn default() -> Self {
+        Self {
+            next: "Tab".to_string(),
+            use_item: "Q".to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ItemStack {
+    pub item: String,
+    pub count: u32,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Inventory {
+    #[serde(default = "default_capacity")]
+    pub capacity: usize,
+    #[serde(default)]
+    pub bindings: InventoryBindings,
+    // Starting items.
+    #[serde(default)]
+    pub slots: Vec<ItemStack>,
+    #[serde(skip)]
+    pub selected: usize,
+}
+
+fn default_capacity() -> usize {
+    8
+}
+
+impl Inventory {
+    pub fn count(&self, item: &str) -> u32 {
+        self.slots
+            .iter()
+            .filter(|stack| stack.item == item)
+            .map(|stack| stack.count)
+            .sum()
+    }
+
+    // Tops up existing stacks before taking free slots. Returns how many
+    // didn't fit.
+    pub fn add(&mut self, item: &str, count: u32, stack_size: u32) -> u32 {
+        let stack_size = stack_size.max(1);
+        let mut left = count;
+        for stack in self.slots.iter_mut().filter(|stack| stack.item == item) {
+            let added = left.min(stack_size.saturating_sub(stack.count));
+            stack.count += added;
+            left -= added;
+        }
+        while left > 0 && self.slots.len() < self.capacity {
+            let added = left.min(stack_size);
+            self.slots.push(ItemStack {
+                item: item.to_string(),
+                count: added,
+            });
+            left -= added;
+        }
+        left
+    }
+
+    // Takes `count` of the item, or nothing if there aren't that many.
+    pub fn remove(&mut self, item: &str, count: u32) -> bool {
+        if self.count(item) < count {
+            return false;
+        }
+        let mut left = count;
+        for stack in self
+            .slots
+            .iter_mut()
+            .rev()
+            .filter(|stack| stack.item == item)
+        {
+            let removed = left.min(stack.count);
+            stack.count -= removed;
+            left -= removed;
+        }
+        self.slots.retain(|stack| stack.count > 0);
+        self.selected = self.selected.min(self.slots.len().saturating_sub(1));
+        true
+    }
+
+    pub fn selected_item(&self) -> Option<&str> {
+        self.slots
+            .get(self.selected)
+            .map(|stack| stack.item.as_str())
+    }
+
+    // Handles the inventory keys. Returns the item to use, if any; taking it
+    // out is up to the caller, as not every item can be used.
+    pub fn send(&mut self, keycode: Option<Keycode>, key_state: &ElementState) -> Option<String> {
+        let key = match (keycode, key_state) {
+            (Some(keycode), ElementState::Pressed) => format!("{:?}", keycode),
+            _ => return None,
+        };
+        if key == self.bindings.next && !self.slots.is_empty() {
+            self.selected = (self.selected + 1) % self.slots.len();
+        }
+        if key == self.bindings.use_item {
+            return self.selected_item().map(str::to_string);
+        }
+        None
+    }
+}
+
+// Goes into the `Inventory` of whatever overlaps its sensor, then despawns.
+#[derive(Debug, Clone, Deserialize)]
+pub struct Pickup {
+    pub item: String,
+    #[serde(default = "default_count")]
+    pub count: u32,
+}
+
+fn default_count() -> u32 {
+    1
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn inventory(capacity: usize) -> Inventory {
+        Inventory {
+            capacity,
+            bindings: InventoryBindings::default(),
+            slots: Vec::new(),
+            selected: 0,
+        }
+    }
+
+    fn counts(inventory: &Inventory) -> Vec<(&str, u32)> {
+        inventory
+            .slots
+            .iter()
+            .map(|stack| (stack.item.as_str(), stack.count))
+            .collect()
+    }
+
+    #[test]
+    fn tops_up_partial_stacks_first() {
+        let mut inventory = inventory(4);
+        assert_eq!(inventory.add("gem", 3, 5), 0);
+        assert_eq!(inventory.add("key", 1, 1), 0);
+        assert_eq!(inventory.add("gem", 4, 5), 0);
+        assert_eq!(counts(&inventory), vec![("gem", 5), ("key", 1), ("gem", 2)]);
+        assert_eq!(inventory.count("gem"), 7);
+    }
+
+    #[test]
+    fn overflow_is_returned_when_full() {
+        let mut inventory = inventory(2);
+        assert_eq!(inventory.add("gem", 12, 5), 2);
+        assert_eq!(counts(&inventory), vec![("gem", 5), ("gem", 5)]);
+        // Nothing fits once every slot is a full stack.
+        assert_eq!(inventory.add("gem", 1, 5), 1);
+        assert_eq!(inventory.add("key", 1, 1), 1);
+        assert_eq!(inventory.count("key"), 0);
+    }
+
+    #[test]
+    fn zero_stack_size_holds_one() {
+        let mut inventory = inventory(1);
+        assert_eq!(inventory.add("heart", 2, 0), 1);
+        assert_eq!(counts(&inventory), vec![("heart", 1)]);
+    }
+
+    #[test]
+    fn removes_from_the_last_stacks() {
+        let mut inventory = inventory(4);
+        inventory.add("gem", 7, 5);
+        inventory.add("key", 1, 1);
+        inventory.selected = 2;
+        assert!(inventory.remove("gem", 3));
+        assert_eq!(counts(&inventory), vec![("gem", 4), ("key", 1)]);
+        // The selection stays on a slot that exists.
+        assert_eq!(inventory.selected, 1);
+    }
+
+    #[test]
+    fn removing_more_than_held_takes_nothing() {
+        let mut inventory = inventory(4);
+        inventory.add("gem", 3, 5);
+        assert!(!inventory.remove("gem", 4));
+        assert!(!inventory.remove("key", 1));
+        assert_eq!(counts(&inventory), vec![("gem", 3)]);
+        assert!(inventory.remove("gem", 3));
+        assert!(inventory.slots.is_empty());
+    }
+}
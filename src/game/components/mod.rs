@@ -1,5 +1,17 @@
+pub mod ai;
 pub mod animate;
+mod aseprite;
+pub mod combat;
+pub mod controller;
+pub mod hud;
 pub mod input;
+pub mod inventory;
+pub mod morph;
+pub mod npc;
+pub mod particles;
+pub mod physics_body;
 pub mod primitive;
 pub mod script;
+pub mod sound;
 pub mod sprite;
+pub mod tilemap;
@@ -0,0 +1,56 @@
+use super::sprite::{Clip, SpriteSheet};
+use nalgebra::Point2;
+use ncollide2d::shape::{ConvexPolygon, ShapeHandle};
+use serde::Deserialize;
+
+// Reshapes an entity's colliders to follow its sprite's current frame.
+#[derive(Debug, Clone, Deserialize)]
+pub struct ColliderMorph {
+    // One entry per collider of the entity's `PhysicsBody`, in order. `None`
+    // uses the frame's trimmed bounds, a name uses the clip slice of that name.
+    #[serde(default = "default_slices")]
+    pub slices: Vec<Option<String>>,
+    // The (state, frame) the colliders were last shaped for.
+    #[serde(skip)]
+    pub shaped_for: Option<(u32, usize)>,
+}
+
+fn default_slices() -> Vec<Option<String>> {
+    vec![None]
+}
+
+impl Default for ColliderMorph {
+    fn default() -> Self {
+        Self {
+            slices: default_slices(),
+            shaped_for: None,
+        }
+    }
+}
+
+impl ColliderMorph {
+    // The shape for collider `index` on `clip`, relative to the body. `None` if
+    // the clip doesn't have the slice.
+    pub fn shape(
+        &self,
+        index: usize,
+        sheet: &SpriteSheet,
+        clip: &Clip,
+    ) -> Option<ShapeHandle<f32>> {
+        let (x, y, w, h) = match self.slices.get(index)? {
+            Some(name) => clip.slice(name)?.rect,
+            None => clip.bounds,
+        };
+        let (width, height) = sheet.clip_size(clip);
+        let left = (x - clip.pivot.0) * width;
+        let bottom = (y - clip.pivot.1) * height;
+        let (right, top) = (left + w * width, bottom + h * height);
+        let corners = [
+            Point2::new(left, bottom),
+            Point2::new(right, bottom),
+            Point2::new(right, top),
+            Point2::new(left, top),
+        ];
+        ConvexPolygon::try_from_points(&corners).map(ShapeHandle::new)
+    }
+}
@@ -0,0 +1,37 @@
+use serde::Deserialize;
+use skulpin::winit::event::ElementState;
+use skulpin::winit::event::VirtualKeyCode as Keycode;
+
+// Someone to talk to. The player starts the dialogue with `key` while
+// overlapping the NPC's sensor, which shows the prompt.
+#[derive(Debug, Clone, Deserialize)]
+pub struct Npc {
+    pub dialogue: String,
+    #[serde(default = "default_prompt")]
+    pub prompt: String,
+    // Key name as printed by `{:?}` on a `VirtualKeyCode`.
+    #[serde(default = "default_key")]
+    pub key: String,
+    #[serde(skip)]
+    pub in_range: bool,
+}
+
+fn default_prompt() -> String {
+    "Talk".to_string()
+}
+
+fn default_key() -> String {
+    "E".to_string()
+}
+
+impl Npc {
+    // Whether the event starts the conversation.
+    pub fn interacts(&self, keycode: Option<Keycode>, key_state: &ElementState) -> bool {
+        match (keycode, key_state) {
+            (Some(keycode), ElementState::Pressed) => {
+                self.in_range && format!("{:?}", keycode) == self.key
+            }
+            _ => false,
+        }
+    }
+}
@@ -0,0 +1,237 @@
+use super::sprite::SpriteSheet;
+use nalgebra::{Point2, Vector2};
+use serde::Deserialize;
+
+// Values over a particle's life as `(t, value)` keys, t going from 0 to 1.
+pub type Curve<T> = Vec<(f32, T)>;
+pub type Rgba = (f32, f32, f32, f32);
+
+#[derive(Debug, Copy, Clone, PartialEq, Deserialize)]
+pub enum ParticleLayer {
+    // Under sprites and primitives.
+    Behind,
+    InFront,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub enum ParticleShape {
+    Circle,
+    Square,
+    // A frame of a sprite sheet, tinted by the colour curve's alpha.
+    Sprite {
+        source: String,
+        key: u32,
+        #[serde(default)]
+        frame: usize,
+    },
+}
+
+// Extra particles emitted at once when something happens to the entity.
+#[derive(Debug, Clone, Deserialize)]
+pub enum EmitTrigger {
+    // The entity's `Animate` switched into one of the states.
+    StateEntered { states: Vec<u32>, count: u32 },
+    // Something started overlapping one of the entity's sensors.
+    SensorTouched { count: u32 },
+}
+
+#[derive(Debug, Clone)]
+pub struct Particle {
+    pub position: Point2<f32>,
+    pub velocity: Vector2<f32>,
+    pub age: f32,
+    pub lifetime: f32,
+}
+
+impl Particle {
+    pub fn life(&self) -> f32 {
+        (self.age / self.lifetime).min(1.0)
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct ParticleEmitter {
+    // Particles per second while `emitting`.
+    pub rate: f32,
+    pub emitting: bool,
+    pub triggers: Vec<EmitTrigger>,
+    pub max_particles: usize,
+    // Seconds, picked between the two.
+    pub lifetime: (f32, f32),
+    pub speed: (f32, f32),
+    // Radians from +x, and how far either side of it particles may go.
+    pub direction: f32,
+    pub spread: f32,
+    pub gravity: (f32, f32),
+    // Where particles start, relative to the body.
+    pub offset: (f32, f32),
+    pub colors: Curve<Rgba>,
+    pub sizes: Curve<f32>,
+    pub shape: ParticleShape,
+    pub layer: ParticleLayer,
+    #[serde(skip)]
+    pub particles: Vec<Particle>,
+    #[serde(skip)]
+    pub sheet: Option<SpriteSheet>,
+    #[serde(skip)]
+    pub last_state: Option<u32>,
+    #[serde(skip)]
+    pending: u32,
+    #[serde(skip)]
+    accumulator: f32,
+    #[serde(skip)]
+    seed: u32,
+}
+
+impl Default for ParticleEmitter {
+    fn default() -> Self {
+        Self {
+            rate: 0.0,
+            emitting: false,
+            triggers: Vec::new(),
+            max_particles: 128,
+            lifetime: (0.5, 1.0),
+            speed: (0.5, 1.0),
+            direction: std::f32::consts::FRAC_PI_2,
+            spread: std::f32::consts::PI,
+            gravity: (0.0, 0.0),
+            offset: (0.0, 0.0),
+            colors: vec![(0.0, (1.0, 1.0, 1.0, 1.0)), (1.0, (1.0, 1.0, 1.0, 0.0))],
+            sizes: vec![(0.0, 0.05)],
+            shape: ParticleShape::Circle,
+            layer: ParticleLayer::InFront,
+            particles: Vec::new(),
+            sheet: None,
+            last_state: None,
+            pending: 0,
+            accumulator: 0.0,
+            seed: 0x9e37_79b9,
+        }
+    }
+}
+
+impl ParticleEmitter {
+    pub fn burst(&mut self, count: u32) {
+        self.pending += count;
+    }
+
+    pub fn sensor_touched(&mut self) {
+        let count: u32 = self
+            .triggers
+            .iter()
+            .map(|trigger| match trigger {
+                EmitTrigger::SensorTouched { count } => *count,
+                _ => 0,
+            })
+            .sum();
+        self.burst(count);
+    }
+
+    pub fn state_changed(&mut self, state: u32) {
+        if self.last_state == Some(state) {
+            return;
+        }
+        // The state an entity spawns in doesn't count as entering it.
+        if self.last_state.is_some() {
+            let count: u32 = self
+                .triggers
+                .iter()
+                .map(|trigger| match trigger {
+                    EmitTrigger::StateEntered { states, count } if states.contains(&state) => {
+                        *count
+                    }
+                    _ => 0,
+                })
+                .sum();
+            self.burst(count);
+        }
+        self.last_state = Some(state);
+    }
+
+    // Advances particles by `dt` and emits new ones at `origin`.
+    pub fn step(&mut self, origin: Point2<f32>, dt: f32) {
+        let gravity = Vector2::new(self.gravity.0, self.gravity.1);
+        for particle in &mut self.particles {
+            particle.age += dt;
+            particle.velocity += gravity * dt;
+            particle.position += particle.velocity * dt;
+        }
+        self.particles
+            .retain(|particle| particle.age < particle.lifetime);
+
+        let mut count = std::mem::replace(&mut self.pending, 0);
+        if self.emitting {
+            self.accumulator += self.rate * dt;
+            count += self.accumulator as u32;
+            self.accumulator = self.accumulator.fract();
+        }
+        let origin = origin + Vector2::new(self.offset.0, self.offset.1);
+        for _ in 0..count {
+            if self.particles.len() >= self.max_particles {
+                break;
+            }
+            let angle = self.direction + self.spread * (self.random() * 2.0 - 1.0);
+            let speed = lerp(self.speed.0, self.speed.1, self.random());
+            let lifetime = lerp(self.lifetime.0, self.lifetime.1, self.random());
+            self.particles.push(Particle {
+                position: origin,
+                velocity: Vector2::new(angle.cos(), angle.sin()) * speed,
+                age: 0.0,
+                lifetime: lifetime.max(0.001),
+            });
+        }
+    }
+
+    pub fn color(&self, particle: &Particle) -> Rgba {
+        sample(
+            &self.colors,
+            particle.life(),
+            (1.0, 1.0, 1.0, 1.0),
+            |a, b, t| {
+                (
+                    lerp(a.0, b.0, t),
+                    lerp(a.1, b.1, t),
+                    lerp(a.2, b.2, t),
+                    lerp(a.3, b.3, t),
+                )
+            },
+        )
+    }
+
+    pub fn size(&self, particle: &Particle) -> f32 {
+        sample(&self.sizes, particle.life(), 0.05, lerp)
+    }
+
+    // xorshift, so emitters don't need a random number crate.
+    fn random(&mut self) -> f32 {
+        let mut x = self.seed;
+        x ^= x << 13;
+        x ^= x >> 17;
+        x ^= x << 5;
+        self.seed = x;
+        (x >> 8) as f32 / (1 << 24) as f32
+    }
+}
+
+fn lerp(a: f32, b: f32, t: f32) -> f32 {
+    a + (b - a) * t
+}
+
+fn sample<T: Copy>(curve: &[(f32, T)], t: f32, default: T, mix: impl Fn(T, T, f32) -> T) -> T {
+    let first = match curve.first() {
+        Some(first) => first,
+        None => return default,
+    };
+    if t <= first.0 {
+        return first.1;
+    }
+    for pair in curve.windows(2) {
+        let ((t0, a), (t1, b)) = (pair[0], pair[1]);
+        if t <= t1 {
+            let span = (t1 - t0).max(std::f32::EPSILON);
+            return mix(a, b, (t - t0) / span);
+        }
+    }
+    curve.last().unwrap().1
+}
@@ -0,0 +1,129 @@
+use legion::systems::CommandBuffer;
+use legion::{Entity, EntityStore, Resources, World};
+use nphysics2d::object::{
+    DefaultBodyHandle, DefaultBodySet, DefaultColliderHandle, DefaultColliderSet,
+};
+use std::collections::HashMap;
+
+// The physics objects owned by an entity. They are removed with the entity.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PhysicsBody {
+    pub body: DefaultBodyHandle,
+    pub colliders: Vec<DefaultColliderHandle>,
+}
+
+impl PhysicsBody {
+    pub fn new(body: DefaultBodyHandle) -> Self {
+        Self {
+            body,
+            colliders: Vec::new(),
+        }
+    }
+
+    pub fn with_collider(mut self, collider: DefaultColliderHandle) -> Self {
+        self.colliders.push(collider);
+        self
+    }
+}
+
+// Maps bodies and colliders back to the entity that owns them.
+#[derive(Default)]
+pub struct BodyEntities {
+    bodies: HashMap<DefaultBodyHandle, Entity>,
+    colliders: HashMap<DefaultColliderHandle, Entity>,
+}
+
+impl BodyEntities {
+    pub fn insert(&mut self, entity: Entity, physics_body: &PhysicsBody) {
+        self.bodies.insert(physics_body.body, entity);
+        for collider in &physics_body.colliders {
+            self.colliders.insert(*collider, entity);
+        }
+    }
+
+    pub fn remove(&mut self, physics_body: &PhysicsBody) {
+        self.bodies.remove(&physics_body.body);
+        for collider in &physics_body.colliders {
+            self.colliders.remove(collider);
+        }
+    }
+
+    pub fn body_entity(&self, body: DefaultBodyHandle) -> Option<Entity> {
+        self.bodies.get(&body).copied()
+    }
+
+    pub fn collider_entity(&self, collider: DefaultColliderHandle) -> Option<Entity> {
+        self.colliders.get(&collider).copied()
+    }
+}
+
+// Creates an entity owning the physics objects and records it in the reverse lookup.
+pub fn spawn(world: &mut World, resources: &Resources, physics_body: PhysicsBody) -> Entity {
+    let mut entities = resources
+        .get_mut::<BodyEntities>()
+        .unwrap_or_else(|| panic!("{:?}- BodyEntities", physics_body.body));
+    let entity = world.push((physics_body.clone(),));
+    entities.insert(entity, &physics_body);
+    entity
+}
+
+// Removes the entity together with its body and colliders.
+pub fn despawn(world: &mut World, resources: &Resources, entity: Entity) -> bool {
+    let physics_body = world
+        .entry_ref(entity)
+        .ok()
+        .and_then(|entry| entry.get_component::<PhysicsBody>().ok().cloned());
+    if let Some(physics_body) = physics_body {
+        remove_physics_body(resources, &physics_body);
+    }
+    world.remove(entity)
+}
+
+// Despawns the entity when the command buffer is flushed, for systems that
+// only have a `CommandBuffer`.
+pub fn despawn_later(commands: &mut CommandBuffer, entity: Entity) {
+    commands.exec_mut(move |world, resources| {
+        despawn(world, resources, entity);
+    });
+}
+
+// Cleans up after entities that were removed from the world directly, e.g.
+// with `World::remove`. Runs once a frame; `despawn` is the quicker way.
+pub fn remove_orphans(world: &World, resources: &Resources) {
+    let orphans: Vec<PhysicsBody> = {
+        let entities = resources.get::<BodyEntities>().unwrap();
+        let orphans = entities
+            .bodies
+            .iter()
+            .filter(|(_, entity)| !world.contains(**entity))
+            .map(|(body, entity)| {
+                let colliders = entities
+                    .colliders
+                    .iter()
+                    .filter(|(_, owner)| *owner == entity)
+                    .map(|(collider, _)| *collider)
+                    .collect();
+                PhysicsBody {
+                    body: *body,
+                    colliders,
+                }
+            })
+            .collect();
+        orphans
+    };
+    for physics_body in &orphans {
+        remove_physics_body(resources, physics_body);
+    }
+}
+
+fn remove_physics_body(resources: &Resources, physics_body: &PhysicsBody) {
+    let mut bodies = resources.get_mut::<DefaultBodySet<f32>>().unwrap();
+    let mut colliders = resources.get_mut::<DefaultColliderSet<f32>>().unwrap();
+    let mut entities = resources.get_mut::<BodyEntities>().unwrap();
+    for collider in &physics_body.colliders {
+        colliders.remove(*collider);
+    }
+    // Joints attached to the body are dropped by the mechanical world on its next step.
+    bodies.remove(physics_body.body);
+    entities.remove(physics_body);
+}
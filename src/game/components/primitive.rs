@@ -1,14 +1,26 @@
 use nphysics2d::math::Isometry;
+use serde::{Deserialize, Serialize};
 use skulpin::skia_safe::Canvas;
 
-type DrawFunction = fn(&mut Canvas, &Isometry<f32>) -> ();
+pub type DrawFunction = fn(&mut Canvas, &Isometry<f32>, &Primitive) -> ();
 
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Primitive {
-    pub draw_fn: DrawFunction,
+    pub draw_id: String,
+    // Characteristic size of the shape, e.g. the radius of a ball.
+    #[serde(default = "default_size")]
+    pub size: f32,
+}
+
+fn default_size() -> f32 {
+    1.0
 }
 
 impl Primitive {
-    pub fn new(draw_fn: DrawFunction) -> Self {
-        Self { draw_fn }
+    pub fn new(draw_id: &str) -> Self {
+        Self {
+            draw_id: draw_id.to_string(),
+            size: default_size(),
+        }
     }
 }
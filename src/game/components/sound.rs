@@ -0,0 +1,36 @@
+use serde::Deserialize;
+
+// One-shot sounds played when an animation reaches a frame, e.g. footsteps.
+#[derive(Debug, Clone, Deserialize)]
+pub struct FrameSounds {
+    pub triggers: Vec<FrameSound>,
+    // The (state, frame) seen last update, so each frame triggers once.
+    #[serde(skip)]
+    pub last_frame: Option<(u32, usize)>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct FrameSound {
+    pub sound: String,
+    pub states: Vec<u32>,
+    pub frames: Vec<usize>,
+}
+
+impl FrameSounds {
+    pub fn sounds_for(&self, state: u32, frame: usize) -> impl Iterator<Item = &str> {
+        self.triggers
+            .iter()
+            .filter(move |trigger| {
+                trigger.states.contains(&state) && trigger.frames.contains(&frame)
+            })
+            .map(|trigger| trigger.sound.as_str())
+    }
+}
+
+// Played when the entity starts touching something while moving at least `min_speed`.
+#[derive(Debug, Clone, Deserialize)]
+pub struct ImpactSound {
+    pub sound: String,
+    #[serde(default)]
+    pub min_speed: f32,
+}
@@ -1,40 +1,84 @@
 use super::animate::Animate;
+use super::aseprite;
 use image::{DynamicImage, GenericImageView, Rgba};
+use log::error;
 use nphysics2d::math::Isometry;
+use nphysics2d::math::Vector;
 use num_traits::AsPrimitive;
 use skulpin::skia_safe::{
-    AlphaType, Canvas, ColorInfo, ColorSpace, ColorType, Data, ISize, Image, ImageInfo,
+    AlphaType, Canvas, ColorInfo, ColorSpace, ColorType, Data, FilterQuality, ISize, Image,
+    ImageInfo, Paint, Rect as SkiaRect,
 };
 use std::collections::HashMap;
 
-type DrawFunction = fn(&mut Canvas, &Isometry<f32>, &SpriteSheet, &Animate) -> ();
+// The rate animations used to run at before frames had their own durations.
+pub const DEFAULT_FRAME_DURATION: f32 = 1.0 / 7.5;
+
+pub type DrawFunction = fn(&mut Canvas, &Isometry<f32>, &SpriteSheet, &Animate) -> ();
 
 pub struct Sprite {
-    pub draw_fn: DrawFunction,
+    pub draw_id: String,
     pub source: SpriteSheet,
 }
 
 impl Sprite {
-    pub fn new(draw_fn: DrawFunction, source: SpriteSheet) -> Self {
-        Self { draw_fn, source }
+    pub fn new(draw_id: &str, source: SpriteSheet) -> Self {
+        Self {
+            draw_id: draw_id.to_string(),
+            source,
+        }
     }
 }
 
+#[derive(Debug, Copy, Clone, PartialEq, Deserialize)]
+pub enum Sampling {
+    // Keeps pixel art crisp.
+    Nearest,
+    Linear,
+}
+
+impl Default for Sampling {
+    fn default() -> Self {
+        Self::Linear
+    }
+}
+
+#[derive(Debug, Clone)]
 pub struct SpriteSheet {
     clips: HashMap<u32, Vec<Clip>>,
+    names: HashMap<String, u32>,
+    pub sampling: Sampling,
+    // Without it every clip is drawn one unit high.
+    pub pixels_per_unit: Option<f32>,
+    // Rounds the drawn position to whole source pixels. Needs `pixels_per_unit`.
+    pub snap_to_pixel: bool,
 }
 
 impl SpriteSheet {
     pub fn new(clips: HashMap<u32, Vec<Clip>>) -> Self {
-        Self { clips }
+        Self {
+            clips,
+            names: HashMap::new(),
+            sampling: Sampling::default(),
+            pixels_per_unit: None,
+            snap_to_pixel: false,
+        }
     }
 
     pub fn from_config(filename: &str) -> Self {
+        if filename.ends_with(".json") {
+            let sheet = aseprite::load(filename);
+            return Self {
+                names: sheet.names,
+                ..Self::new(sheet.clips)
+            };
+        }
+
         let f = File::open(filename).expect("Failed opening file");
         let desc: SpriteSheetDesc = match from_reader(f) {
             Ok(x) => x,
             Err(e) => {
-                println!("Failed to load config: {}", e);
+                error!("Failed to load sprite sheet {:?}: {}", filename, e);
 
                 std::process::exit(1);
             }
@@ -43,23 +87,131 @@ impl SpriteSheet {
         let source_path = crate::utils::from_out_dir(&desc.source_path);
         let img = image::open(&source_path).unwrap();
 
+        let mut clip_descs = desc.clip_map;
+        let mut names = HashMap::new();
+        for animation in &desc.animations {
+            let rects = match &animation.frames {
+                FramesDesc::Grid { row, first, count } => match &desc.grid {
+                    Some(grid) => (*first..first + count)
+                        .map(|column| grid.cell(column, *row))
+                        .collect(),
+                    None => {
+                        error!(
+                            "Animation {:?} uses a grid but none is defined",
+                            animation.name
+                        );
+
+                        std::process::exit(1);
+                    }
+                },
+                FramesDesc::Rects(rects) => rects.clone(),
+            };
+            let clips = |is_flipped| {
+                rects
+                    .iter()
+                    .map(|rect| ClipDesc {
+                        rect: *rect,
+                        is_flipped,
+                        squeeze: animation.squeeze,
+                        anchor: animation.anchor,
+                        duration: animation.frame_duration,
+                    })
+                    .collect::<Vec<_>>()
+            };
+            clip_descs.insert(animation.key, clips(animation.is_flipped));
+            names.insert(animation.name.clone(), animation.key);
+            if let Some(mirror) = &animation.mirror {
+                clip_descs.insert(mirror.key, clips(!animation.is_flipped));
+                names.insert(mirror.name.clone(), mirror.key);
+            }
+        }
+
         let mut clip_map = HashMap::new();
-        for (key, clip_descs) in desc.clip_map {
+        for (key, clip_descs) in clip_descs {
             let mut clips = Vec::new();
             for cd in clip_descs {
-                let clip = Clip::new(&img, cd.rect, cd.is_flipped, cd.squeeze);
+                let anchor = cd.anchor.or(desc.anchor);
+                let mut clip = Clip::new(&img, cd.rect, cd.is_flipped, cd.squeeze, anchor);
+                clip.duration = cd.duration.unwrap_or(desc.frame_duration);
                 clips.push(clip);
             }
             clip_map.insert(key, clips);
         }
 
-        Self { clips: clip_map }
+        Self {
+            clips: clip_map,
+            names,
+            sampling: desc.sampling,
+            pixels_per_unit: desc.pixels_per_unit,
+            snap_to_pixel: desc.snap_to_pixel,
+        }
+    }
+
+    // The `pixels_per_unit` of a RON sheet config, without loading its image.
+    pub fn config_pixels_per_unit(filename: &str) -> Option<f32> {
+        let f = File::open(filename).ok()?;
+        match from_reader::<_, SpriteSheetDesc>(f) {
+            Ok(desc) => desc.pixels_per_unit,
+            Err(e) => {
+                error!("Failed to load sprite sheet {:?}: {}", filename, e);
+                None
+            }
+        }
     }
 
     #[inline]
     pub fn get_clip<T: AsPrimitive<u32>>(&self, key: T, it: usize) -> &Clip {
         &self.clips.get(&(key.as_())).unwrap()[it]
     }
+
+    // The clip key of a named animation.
+    pub fn key(&self, name: &str) -> Option<u32> {
+        self.names.get(name).copied()
+    }
+
+    pub fn get_named_clip(&self, name: &str, it: usize) -> Option<&Clip> {
+        self.clips.get(&self.key(name)?)?.get(it)
+    }
+
+    pub fn frame_count<T: AsPrimitive<u32>>(&self, key: T) -> usize {
+        self.clips.get(&key.as_()).map_or(0, |clips| clips.len())
+    }
+
+    pub fn frame_duration<T: AsPrimitive<u32>>(&self, key: T, it: usize) -> f32 {
+        self.clips
+            .get(&key.as_())
+            .and_then(|clips| clips.get(it))
+            .map_or(DEFAULT_FRAME_DURATION, |clip| clip.duration)
+    }
+
+    pub fn paint(&self) -> Paint {
+        let mut paint = Paint::default();
+        paint.set_filter_quality(match self.sampling {
+            Sampling::Nearest => FilterQuality::None,
+            Sampling::Linear => FilterQuality::Low,
+        });
+        paint
+    }
+
+    // World size of `clip`.
+    pub fn clip_size(&self, clip: &Clip) -> (f32, f32) {
+        let height = self
+            .pixels_per_unit
+            .map_or(1.0, |ppu| clip.height as f32 / ppu);
+        (clip.width_over_height * height, height)
+    }
+
+    // Where `clip` lands in the world when its pivot is at `position`.
+    pub fn clip_rect(&self, clip: &Clip, position: &Vector<f32>) -> SkiaRect {
+        let (width, height) = self.clip_size(clip);
+        let mut left = position.x - clip.pivot.0 * width;
+        let mut bottom = position.y - clip.pivot.1 * height;
+        if let (true, Some(ppu)) = (self.snap_to_pixel, self.pixels_per_unit) {
+            left = (left * ppu).round() / ppu;
+            bottom = (bottom * ppu).round() / ppu;
+        }
+        SkiaRect::from_xywh(left, bottom, width, height)
+    }
 }
 
 use ron::de::from_reader;
@@ -69,7 +221,29 @@ use std::fs::File;
 #[derive(Debug, Deserialize)]
 struct SpriteSheetDesc {
     source_path: String,
+    #[serde(default)]
     clip_map: HashMap<u32, Vec<ClipDesc>>,
+    #[serde(default)]
+    grid: Option<GridDesc>,
+    // Expanded into `clip_map` entries under their keys.
+    #[serde(default)]
+    animations: Vec<AnimationDesc>,
+    #[serde(default)]
+    sampling: Sampling,
+    #[serde(default)]
+    pixels_per_unit: Option<f32>,
+    #[serde(default)]
+    snap_to_pixel: bool,
+    // Default pivot for clips that don't set their own.
+    #[serde(default)]
+    anchor: Option<(f32, f32)>,
+    // Seconds each frame is shown for, unless a clip or animation says otherwise.
+    #[serde(default = "default_frame_duration")]
+    frame_duration: f32,
+}
+
+fn default_frame_duration() -> f32 {
+    DEFAULT_FRAME_DURATION
 }
 
 #[derive(Debug, Deserialize)]
@@ -77,9 +251,65 @@ struct ClipDesc {
     rect: Rect,
     is_flipped: bool,
     squeeze: bool,
+    // Pivot in pixels from the top left of `rect`, before flipping. Defaults to the centre.
+    #[serde(default)]
+    anchor: Option<(f32, f32)>,
+    #[serde(default)]
+    duration: Option<f32>,
+}
+
+// Uniform cells, counted in columns and rows from the top left of the sheet.
+#[derive(Debug, Deserialize)]
+struct GridDesc {
+    cell: (u32, u32),
+    #[serde(default)]
+    offset: (u32, u32),
+    #[serde(default)]
+    spacing: (u32, u32),
+}
+
+impl GridDesc {
+    fn cell(&self, column: u32, row: u32) -> Rect {
+        Rect {
+            x: self.offset.0 + column * (self.cell.0 + self.spacing.0),
+            y: self.offset.1 + row * (self.cell.1 + self.spacing.1),
+            w: self.cell.0,
+            h: self.cell.1,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct AnimationDesc {
+    name: String,
+    key: u32,
+    frames: FramesDesc,
+    #[serde(default)]
+    is_flipped: bool,
+    #[serde(default)]
+    squeeze: bool,
+    #[serde(default)]
+    anchor: Option<(f32, f32)>,
+    #[serde(default)]
+    frame_duration: Option<f32>,
+    // The same frames flipped the other way, under another key and name.
+    #[serde(default)]
+    mirror: Option<MirrorDesc>,
 }
 
 #[derive(Debug, Deserialize)]
+enum FramesDesc {
+    Grid { row: u32, first: u32, count: u32 },
+    Rects(Vec<Rect>),
+}
+
+#[derive(Debug, Deserialize)]
+struct MirrorDesc {
+    name: String,
+    key: u32,
+}
+
+#[derive(Debug, Copy, Clone, Deserialize)]
 pub struct Rect {
     pub x: u32,
     pub y: u32,
@@ -87,44 +317,112 @@ pub struct Rect {
     pub h: u32,
 }
 
-#[derive(Clone)]
+#[derive(Debug, Clone)]
 pub struct Clip {
     pub image: DynamicImage,
     pub width_over_height: f32,
+    pub height: u32,
+    // Fraction of the clip size from the bottom left, y up.
+    pub pivot: (f32, f32),
+    // The non-transparent part of the clip, as `(x, y, w, h)` in the same space.
+    pub bounds: (f32, f32, f32, f32),
+    // Seconds this frame is shown for.
+    pub duration: f32,
+    pub slices: Vec<ClipSlice>,
+}
+
+// A named rect on a clip, e.g. a hitbox. `rect` is `(x, y, w, h)` in fractions
+// of the clip size from the bottom left, y up.
+#[derive(Debug, Clone)]
+pub struct ClipSlice {
+    pub name: String,
+    pub rect: (f32, f32, f32, f32),
 }
 
 impl Clip {
-    pub fn new(source: &DynamicImage, rect: Rect, is_flipped: bool, squeeze: bool) -> Self {
+    pub fn new(
+        source: &DynamicImage,
+        rect: Rect,
+        is_flipped: bool,
+        squeeze: bool,
+        anchor: Option<(f32, f32)>,
+    ) -> Self {
         let mut cropped = source.crop_imm(rect.x, rect.y, rect.w, rect.h);
+        // An unset anchor stays centred on whatever is left after squeezing.
+        let centred = anchor.is_none();
+        let mut anchor = anchor.unwrap_or((rect.w as f32 / 2.0, rect.h as f32 / 2.0));
 
         if is_flipped {
             cropped = cropped.fliph();
+            anchor.0 = rect.w as f32 - anchor.0;
         }
 
         if squeeze {
-            Clip::squeeze(&mut cropped);
+            let (x, y) = Clip::squeeze(&mut cropped);
+            anchor = (anchor.0 - x as f32, anchor.1 - y as f32);
         }
 
+        let (width, height) = cropped.dimensions();
+        let (w, h) = (width as f32, height as f32);
+        let bounds = match opaque_bounds(&cropped) {
+            Some((left, top, right, bottom)) => (
+                left as f32 / w,
+                1.0 - bottom as f32 / h,
+                (right - left) as f32 / w,
+                (bottom - top) as f32 / h,
+            ),
+            None => (0.0, 0.0, 1.0, 1.0),
+        };
         let image = cropped.flipv();
-        let width_over_height = image.width() as f32 / image.height() as f32;
+        let pivot = if centred {
+            (0.5, 0.5)
+        } else {
+            (anchor.0 / width as f32, 1.0 - anchor.1 / height as f32)
+        };
         Self {
             image,
-            width_over_height,
+            width_over_height: width as f32 / height as f32,
+            height,
+            pivot,
+            bounds,
+            duration: DEFAULT_FRAME_DURATION,
+            slices: Vec::new(),
         }
     }
 
-    fn squeeze(source: &mut DynamicImage) {
-        for _ in 0..4 {
-            let rgba_img = source.as_rgba8().unwrap();
-            for (i, mut row) in rgba_img.enumerate_rows() {
-                if row.any(|p| p.2 != &Rgba::from([0, 0, 0, 0])) {
-                    *source = source.crop_imm(0, i, source.width(), source.height() - i);
-                    break;
-                }
+    pub fn slice(&self, name: &str) -> Option<&ClipSlice> {
+        self.slices.iter().find(|slice| slice.name == name)
+    }
+
+    // Crops away fully transparent borders. Returns the offset of the new top left.
+    fn squeeze(source: &mut DynamicImage) -> (u32, u32) {
+        match opaque_bounds(source) {
+            Some((left, top, right, bottom)) => {
+                *source = source.crop_imm(left, top, right - left, bottom - top);
+                (left, top)
             }
-            *source = source.rotate90();
+            None => (0, 0),
+        }
+    }
+}
+
+// Left, top, right and bottom of the non-transparent pixels, if there are any.
+fn opaque_bounds(source: &DynamicImage) -> Option<(u32, u32, u32, u32)> {
+    let (width, height) = source.dimensions();
+    let (mut left, mut top, mut right, mut bottom) = (width, height, 0, 0);
+    for (x, y, p) in source.pixels() {
+        if p != Rgba::from([0, 0, 0, 0]) {
+            left = left.min(x);
+            top = top.min(y);
+            right = right.max(x + 1);
+            bottom = bottom.max(y + 1);
         }
     }
+    if left >= right || top >= bottom {
+        None
+    } else {
+        Some((left, top, right, bottom))
+    }
 }
 
 pub fn make_skia_image(img: &DynamicImage) -> Image {
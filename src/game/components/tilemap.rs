@@ -0,0 +1,801 @@
+use image::{DynamicImage, GenericImageView};
+use log::error;
+use nalgebra::Vector2;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+const GID_MASK: u32 = 0x1fff_ffff;
+
+// A Tiled map. Positions are in world units with y up; `origin` is the world
+// position of the map's top-left corner.
+pub struct Tilemap {
+    pub width: u32,
+    pub height: u32,
+    pub tile_size: f32,
+    pub origin: Vector2<f32>,
+    pub tilesets: Vec<Tileset>,
+    pub layers: Vec<TileLayer>,
+    pub objects: Vec<TiledObject>,
+}
+
+pub struct Tileset {
+    pub first_gid: u32,
+    pub columns: u32,
+    pub tile_count: u32,
+    pub tile_width: u32,
+    pub tile_height: u32,
+    pub margin: u32,
+    pub spacing: u32,
+    pub image_path: String,
+    // Flipped vertically, like sprite clips, so it draws upright in world space.
+    pub image: DynamicImage,
+}
+
+pub struct TileLayer {
+    pub name: String,
+    pub visible: bool,
+    pub data: Vec<u32>,
+}
+
+pub struct TiledObject {
+    pub name: String,
+    pub kind: String,
+    pub layer: String,
+    pub center: Vector2<f32>,
+    pub half_extents: Vector2<f32>,
+    pub properties: HashMap<String, String>,
+}
+
+// A rectangle of tiles, in tile coordinates.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct TileRect {
+    pub col: u32,
+    pub row: u32,
+    pub cols: u32,
+    pub rows: u32,
+}
+
+impl Tilemap {
+    pub fn from_file(path: &str, tile_size: f32, origin: Vector2<f32>) -> Option<Self> {
+        let contents = match fs::read_to_string(path) {
+            Ok(contents) => contents,
+            Err(e) => {
+                error!("Failed to read tilemap {:?}: {}", path, e);
+                return None;
+            }
+        };
+        let raw = if path.ends_with(".tmx") {
+            tmx::parse(&contents, path)
+        } else {
+            json::parse(&contents, path)
+        };
+        let raw = match raw {
+            Ok(raw) => raw,
+            Err(e) => {
+                error!("Failed to load tilemap {:?}: {}", path, e);
+                return None;
+            }
+        };
+        Some(Self::from_raw(raw, tile_size, origin))
+    }
+
+    fn from_raw(raw: RawMap, tile_size: f32, origin: Vector2<f32>) -> Self {
+        let mut tilesets = Vec::new();
+        for ts in raw.tilesets {
+            match image::open(&ts.image_path) {
+                Ok(img) => tilesets.push(Tileset {
+                    first_gid: ts.first_gid,
+                    columns: ts.columns.max(1),
+                    tile_count: ts.tile_count,
+                    tile_width: ts.tile_width,
+                    tile_height: ts.tile_height,
+                    margin: ts.margin,
+                    spacing: ts.spacing,
+                    image_path: ts.image_path,
+                    image: img.flipv(),
+                }),
+                Err(e) => error!("Failed to open tileset image {:?}: {}", ts.image_path, e),
+            }
+        }
+        // Gids are looked up from the highest first gid down.
+        tilesets.sort_by_key(|ts| std::cmp::Reverse(ts.first_gid));
+
+        let (tile_width, tile_height) = (raw.tile_width as f32, raw.tile_height as f32);
+        let scale = Vector2::new(tile_size / tile_width, tile_size / tile_height);
+        let objects = raw
+            .objects
+            .into_iter()
+            .map(|obj| {
+                // Tile objects are placed by their bottom-left corner, the
+                // rest by their top-left. Old maps leave out a tile object's
+                // size when it's the map's tile size.
+                let (w, h) = if obj.gid != 0 && obj.w == 0.0 && obj.h == 0.0 {
+                    (tile_width, tile_height)
+                } else {
+                    (obj.w, obj.h)
+                };
+                let half_extents = Vector2::new(w * scale.x, h * scale.y) / 2.0;
+                let top = if obj.gid == 0 {
+                    obj.y * scale.y
+                } else {
+                    (obj.y - h) * scale.y
+                };
+                let center = Vector2::new(
+                    origin.x + obj.x * scale.x + half_extents.x,
+                    origin.y - top - half_extents.y,
+                );
+                TiledObject {
+                    name: obj.name,
+                    kind: obj.kind,
+                    layer: obj.layer,
+                    center,
+                    half_extents,
+                    properties: obj.properties,
+                }
+            })
+            .collect();
+
+        Self {
+            width: raw.width,
+            height: raw.height,
+            tile_size,
+            origin,
+            tilesets,
+            layers: raw.layers,
+            objects,
+        }
+    }
+
+    pub fn layer(&self, name: &str) -> Option<&TileLayer> {
+        self.layers.iter().find(|layer| layer.name == name)
+    }
+
+    #[inline]
+    pub fn gid(&self, layer: &TileLayer, col: u32, row: u32) -> u32 {
+        layer
+            .data
+            .get((row * self.width + col) as usize)
+            .map_or(0, |gid| gid & GID_MASK)
+    }
+
+    pub fn tileset(&self, gid: u32) -> Option<&Tileset> {
+        if gid == 0 {
+            return None;
+        }
+        self.tilesets.iter().find(|ts| ts.first_gid <= gid)
+    }
+
+    // World-space (left, top, right, bottom) of a rectangle of tiles.
+    pub fn world_rect(&self, rect: TileRect) -> (f32, f32, f32, f32) {
+        let left = self.origin.x + rect.col as f32 * self.tile_size;
+        let top = self.origin.y - rect.row as f32 * self.tile_size;
+        (
+            left,
+            top,
+            left + rect.cols as f32 * self.tile_size,
+            top - rect.rows as f32 * self.tile_size,
+        )
+    }
+
+    // The tiles overlapping the given world-space bounds, clamped to the map.
+    pub fn visible_tiles(&self, left: f32, top: f32, right: f32, bottom: f32) -> TileRect {
+        let to_col = |x: f32| ((x - self.origin.x) / self.tile_size).max(0.0) as u32;
+        let to_row = |y: f32| ((self.origin.y - y) / self.tile_size).max(0.0) as u32;
+        let col = to_col(left).min(self.width);
+        let row = to_row(top).min(self.height);
+        let end_col = (to_col(right) + 1).min(self.width);
+        let end_row = (to_row(bottom) + 1).min(self.height);
+        TileRect {
+            col,
+            row,
+            cols: end_col.saturating_sub(col),
+            rows: end_row.saturating_sub(row),
+        }
+    }
+
+    // Merges the non-empty tiles of a layer into as few rectangles as is cheap:
+    // horizontal runs per row, then identical runs stacked across rows.
+    pub fn merged_rects(&self, layer: &TileLayer) -> Vec<TileRect> {
+        let mut open: Vec<TileRect> = Vec::new();
+        let mut closed = Vec::new();
+        for row in 0..self.height {
+            let mut runs = Vec::new();
+            let mut col = 0;
+            while col < self.width {
+                if self.gid(layer, col, row) == 0 {
+                    col += 1;
+                    continue;
+                }
+                let start = col;
+                while col < self.width && self.gid(layer, col, row) != 0 {
+                    col += 1;
+                }
+                runs.push((start, col - start));
+            }
+
+            let mut next_open = Vec::new();
+            for (start, cols) in runs {
+                match open
+                    .iter()
+                    .position(|rect| rect.col == start && rect.cols == cols)
+                {
+                    Some(i) => {
+                        let mut rect = open.swap_remove(i);
+                        rect.rows += 1;
+                        next_open.push(rect);
+                    }
+                    None => next_open.push(TileRect {
+                        col: start,
+                        row,
+                        cols,
+                        rows: 1,
+                    }),
+                }
+            }
+            closed.append(&mut open);
+            open = next_open;
+        }
+        closed.append(&mut open);
+        closed
+    }
+}
+
+impl Tileset {
+    // Pixel (x, y, w, h) of a tile in the vertically flipped image.
+    pub fn source_rect(&self, gid: u32) -> Option<(u32, u32, u32, u32)> {
+        let id = gid - self.first_gid;
+        if self.tile_count != 0 && id >= self.tile_count {
+            return None;
+        }
+        let x = self.margin + (id % self.columns) * (self.tile_width + self.spacing);
+        let y = self.margin + (id / self.columns) * (self.tile_height + self.spacing);
+        let y = self.image.height().checked_sub(y + self.tile_height)?;
+        Some((x, y, self.tile_width, self.tile_height))
+    }
+}
+
+struct RawMap {
+    width: u32,
+    height: u32,
+    tile_width: u32,
+    tile_height: u32,
+    tilesets: Vec<RawTileset>,
+    layers: Vec<TileLayer>,
+    objects: Vec<RawObject>,
+}
+
+#[derive(Default)]
+struct RawTileset {
+    first_gid: u32,
+    columns: u32,
+    tile_count: u32,
+    tile_width: u32,
+    tile_height: u32,
+    margin: u32,
+    spacing: u32,
+    image_path: String,
+}
+
+struct RawObject {
+    name: String,
+    kind: String,
+    layer: String,
+    // Set for tile objects.
+    gid: u32,
+    x: f32,
+    y: f32,
+    w: f32,
+    h: f32,
+    properties: HashMap<String, String>,
+}
+
+fn relative_to(file: &str, path: &str) -> String {
+    let mut resolved = Path::new(file)
+        .parent()
+        .map_or_else(PathBuf::new, Path::to_path_buf);
+    resolved.push(path);
+    resolved.to_string_lossy().to_string()
+}
+
+// Gids from a layer's base64 data, four little-endian bytes each.
+fn decode_base64(layer: &str, text: &str) -> Result<Vec<u32>, String> {
+    let invalid = || format!("Layer {:?} has invalid base64 data", layer);
+    let mut bytes = Vec::new();
+    let mut bits = 0u32;
+    let mut nbits = 0;
+    for c in text
+        .bytes()
+        .filter(|c| !c.is_ascii_whitespace() && *c != b'=')
+    {
+        let value = match c {
+            b'A'..=b'Z' => c - b'A',
+            b'a'..=b'z' => c - b'a' + 26,
+            b'0'..=b'9' => c - b'0' + 52,
+            b'+' => 62,
+            b'/' => 63,
+            _ => return Err(invalid()),
+        };
+        bits = (bits << 6) | value as u32;
+        nbits += 6;
+        if nbits >= 8 {
+            nbits -= 8;
+            bytes.push((bits >> nbits) as u8);
+            bits &= (1 << nbits) - 1;
+        }
+    }
+    if bytes.len() % 4 != 0 {
+        return Err(invalid());
+    }
+    Ok(bytes
+        .chunks(4)
+        .map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+        .collect())
+}
+
+// Tile data as Tiled writes it: csv (or a plain array in JSON), or base64.
+// Compressed data isn't supported; Tiled can save it uncompressed.
+fn decode_data(
+    layer: &str,
+    encoding: Option<&str>,
+    compression: Option<&str>,
+    text: &str,
+) -> Result<Vec<u32>, String> {
+    if let Some(compression) = compression.filter(|c| !c.is_empty()) {
+        return Err(format!(
+            "Layer {:?} uses unsupported {} compression",
+            layer, compression
+        ));
+    }
+    match encoding {
+        Some("csv") => Ok(text
+            .split(',')
+            .filter_map(|gid| gid.trim().parse().ok())
+            .collect()),
+        Some("base64") => decode_base64(layer, text),
+        Some(encoding) => Err(format!(
+            "Layer {:?} uses unsupported {} encoding",
+            layer, encoding
+        )),
+        None => Err(format!("Layer {:?} has no encoding", layer)),
+    }
+}
+
+mod json {
+    use super::*;
+    use serde_json::Value;
+
+    #[derive(Deserialize)]
+    struct JsonMap {
+        width: u32,
+        height: u32,
+        tilewidth: u32,
+        tileheight: u32,
+        layers: Vec<JsonLayer>,
+        tilesets: Vec<JsonTileset>,
+    }
+
+    #[derive(Deserialize)]
+    struct JsonLayer {
+        name: String,
+        #[serde(rename = "type")]
+        kind: String,
+        #[serde(default = "default_true")]
+        visible: bool,
+        #[serde(default)]
+        data: JsonData,
+        encoding: Option<String>,
+        compression: Option<String>,
+        #[serde(default)]
+        objects: Vec<JsonObject>,
+        #[serde(default)]
+        layers: Vec<JsonLayer>,
+    }
+
+    // A plain array, or an encoded string.
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum JsonData {
+        Gids(Vec<u32>),
+        Encoded(String),
+    }
+
+    impl Default for JsonData {
+        fn default() -> Self {
+            JsonData::Gids(Vec::new())
+        }
+    }
+
+    #[derive(Deserialize)]
+    struct JsonTileset {
+        #[serde(default)]
+        firstgid: u32,
+        source: Option<String>,
+        image: Option<String>,
+        #[serde(default)]
+        columns: u32,
+        #[serde(default)]
+        tilecount: u32,
+        #[serde(default)]
+        tilewidth: u32,
+        #[serde(default)]
+        tileheight: u32,
+        #[serde(default)]
+        margin: u32,
+        #[serde(default)]
+        spacing: u32,
+    }
+
+    #[derive(Deserialize)]
+    struct JsonObject {
+        #[serde(default)]
+        name: String,
+        #[serde(rename = "type", default)]
+        kind: String,
+        #[serde(default)]
+        class: String,
+        #[serde(default)]
+        gid: u32,
+        x: f32,
+        y: f32,
+        #[serde(default)]
+        width: f32,
+        #[serde(default)]
+        height: f32,
+        #[serde(default)]
+        properties: Vec<JsonProperty>,
+    }
+
+    #[derive(Deserialize)]
+    struct JsonProperty {
+        name: String,
+        value: Value,
+    }
+
+    fn default_true() -> bool {
+        true
+    }
+
+    pub(super) fn parse(contents: &str, path: &str) -> Result<RawMap, String> {
+        let map: JsonMap = serde_json::from_str(contents).map_err(|e| e.to_string())?;
+
+        let mut tilesets = Vec::new();
+        for ts in map.tilesets {
+            let (ts, file) = match &ts.source {
+                Some(source) => {
+                    let file = relative_to(path, source);
+                    let contents = fs::read_to_string(&file).map_err(|e| e.to_string())?;
+                    let mut external: JsonTileset =
+                        serde_json::from_str(&contents).map_err(|e| e.to_string())?;
+                    external.firstgid = ts.firstgid;
+                    (external, file)
+                }
+                None => (ts, path.to_string()),
+            };
+            let image = ts
+                .image
+                .ok_or_else(|| format!("Tileset {} has no image", ts.firstgid))?;
+            tilesets.push(RawTileset {
+                first_gid: ts.firstgid,
+                columns: ts.columns,
+                tile_count: ts.tilecount,
+                tile_width: ts.tilewidth,
+                tile_height: ts.tileheight,
+                margin: ts.margin,
+                spacing: ts.spacing,
+                image_path: relative_to(&file, &image),
+            });
+        }
+
+        let mut layers = Vec::new();
+        let mut objects = Vec::new();
+        flatten(map.layers, true, &mut layers, &mut objects)?;
+
+        Ok(RawMap {
+            width: map.width,
+            height: map.height,
+            tile_width: map.tilewidth,
+            tile_height: map.tileheight,
+            tilesets,
+            layers,
+            objects,
+        })
+    }
+
+    // Layers in groups are taken as if they were at the top level, hidden
+    // along with their group.
+    fn flatten(
+        json: Vec<JsonLayer>,
+        visible: bool,
+        layers: &mut Vec<TileLayer>,
+        objects: &mut Vec<RawObject>,
+    ) -> Result<(), String> {
+        for layer in json {
+            match layer.kind.as_str() {
+                "tilelayer" => {
+                    let data = match layer.data {
+                        JsonData::Gids(gids) => gids,
+                        JsonData::Encoded(text) => decode_data(
+                            &layer.name,
+                            layer.encoding.as_deref(),
+                            layer.compression.as_deref(),
+                            &text,
+                        )?,
+                    };
+                    layers.push(TileLayer {
+                        name: layer.name,
+                        visible: visible && layer.visible,
+                        data,
+                    })
+                }
+                "objectgroup" => {
+                    for obj in layer.objects {
+                        let properties = obj
+                            .properties
+                            .into_iter()
+                            .map(|p| {
+                                let value = match p.value {
+                                    Value::String(s) => s,
+                                    value => value.to_string(),
+                                };
+                                (p.name, value)
+                            })
+                            .collect();
+                        objects.push(RawObject {
+                            name: obj.name,
+                            kind: if obj.kind.is_empty() {
+                                obj.class
+                            } else {
+                                obj.kind
+                            },
+                            layer: layer.name.clone(),
+                            gid: obj.gid,
+                            x: obj.x,
+                            y: obj.y,
+                            w: obj.width,
+                            h: obj.height,
+                            properties,
+                        });
+                    }
+                }
+                "group" => flatten(layer.layers, visible && layer.visible, layers, objects)?,
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+}
+
+mod tmx {
+    use super::*;
+    use roxmltree::{Document, Node};
+
+    fn attr<T: std::str::FromStr + Default>(node: &Node, name: &str) -> T {
+        node.attribute(name)
+            .and_then(|value| value.parse().ok())
+            .unwrap_or_default()
+    }
+
+    pub(super) fn parse(contents: &str, path: &str) -> Result<RawMap, String> {
+        let doc = Document::parse(contents).map_err(|e| e.to_string())?;
+        let map = doc.root_element();
+        let width: u32 = attr(&map, "width");
+        let height: u32 = attr(&map, "height");
+
+        let tilesets = map
+            .children()
+            .filter(|n| n.has_tag_name("tileset"))
+            .map(|n| tileset(&n, path))
+            .collect::<Result<_, _>>()?;
+        let mut layers = Vec::new();
+        let mut objects = Vec::new();
+        flatten(&map, true, &mut layers, &mut objects)?;
+
+        Ok(RawMap {
+            width,
+            height,
+            tile_width: attr(&map, "tilewidth"),
+            tile_height: attr(&map, "tileheight"),
+            tilesets,
+            layers,
+            objects,
+        })
+    }
+
+    // Layers in groups are taken as if they were at the top level, hidden
+    // along with their group.
+    fn flatten(
+        parent: &Node,
+        visible: bool,
+        layers: &mut Vec<TileLayer>,
+        objects: &mut Vec<RawObject>,
+    ) -> Result<(), String> {
+        for node in parent.children().filter(Node::is_element) {
+            let visible = visible && node.attribute("visible") != Some("0");
+            match node.tag_name().name() {
+                "layer" => layers.push(layer(&node, visible)?),
+                "objectgroup" => {
+                    let layer_name: String = attr(&node, "name");
+                    for obj in node.children().filter(|n| n.has_tag_name("object")) {
+                        objects.push(object(&obj, &layer_name));
+                    }
+                }
+                "group" => flatten(&node, visible, layers, objects)?,
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    fn tileset(node: &Node, path: &str) -> Result<RawTileset, String> {
+        let first_gid = attr(node, "firstgid");
+        if let Some(source) = node.attribute("source") {
+            let file = relative_to(path, source);
+            let contents = fs::read_to_string(&file).map_err(|e| e.to_string())?;
+            let doc = Document::parse(&contents).map_err(|e| e.to_string())?;
+            let mut ts = tileset(&doc.root_element(), &file)?;
+            ts.first_gid = first_gid;
+            return Ok(ts);
+        }
+
+        let image = node
+            .children()
+            .find(|n| n.has_tag_name("image"))
+            .and_then(|n| n.attribute("source"))
+            .ok_or_else(|| format!("Tileset {} has no image", first_gid))?;
+        Ok(RawTileset {
+            first_gid,
+            columns: attr(node, "columns"),
+            tile_count: attr(node, "tilecount"),
+            tile_width: attr(node, "tilewidth"),
+            tile_height: attr(node, "tileheight"),
+            margin: attr(node, "margin"),
+            spacing: attr(node, "spacing"),
+            image_path: relative_to(path, image),
+        })
+    }
+
+    fn layer(node: &Node, visible: bool) -> Result<TileLayer, String> {
+        let name: String = attr(node, "name");
+        let data = node
+            .children()
+            .find(|n| n.has_tag_name("data"))
+            .ok_or_else(|| format!("Layer {:?} has no data", name))?;
+        let gids = match data.attribute("encoding") {
+            None => data
+                .children()
+                .filter(|n| n.has_tag_name("tile"))
+                .map(|n| attr(&n, "gid"))
+                .collect(),
+            encoding => decode_data(
+                &name,
+                encoding,
+                data.attribute("compression"),
+                data.text().unwrap_or_default(),
+            )?,
+        };
+        Ok(TileLayer {
+            name,
+            visible,
+            data: gids,
+        })
+    }
+
+    fn object(node: &Node, layer: &str) -> RawObject {
+        let kind = node
+            .attribute("type")
+            .or_else(|| node.attribute("class"))
+            .unwrap_or_default();
+        let properties = node
+            .children()
+            .filter(|n| n.has_tag_name("properties"))
+            .flat_map(|n| n.children().filter(|n| n.has_tag_name("property")))
+            .map(|p| (attr(&p, "name"), attr(&p, "value")))
+            .collect();
+        RawObject {
+            name: attr(node, "name"),
+            kind: kind.to_string(),
+            layer: layer.to_string(),
+            gid: attr(node, "gid"),
+            x: attr(node, "x"),
+            y: attr(node, "y"),
+            w: attr(node, "width"),
+            h: attr(node, "height"),
+            properties,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A 4x3 map of unit tiles with its top-left corner at (0, 3).
+    fn map() -> Tilemap {
+        Tilemap {
+            width: 4,
+            height: 3,
+            tile_size: 1.0,
+            origin: Vector2::new(0.0, 3.0),
+            tilesets: Vec::new(),
+            layers: Vec::new(),
+            objects: Vec::new(),
+        }
+    }
+
+    fn layer(data: &[u32]) -> TileLayer {
+        TileLayer {
+            name: "terrain".to_string(),
+            visible: true,
+            data: data.to_vec(),
+        }
+    }
+
+    fn rect(col: u32, row: u32, cols: u32, rows: u32) -> TileRect {
+        TileRect {
+            col,
+            row,
+            cols,
+            rows,
+        }
+    }
+
+    #[test]
+    fn stacks_identical_runs() {
+        #[rustfmt::skip]
+        let tiles = layer(&[
+            1, 1, 0, 0,
+            1, 1, 0, 0,
+            0, 0, 0, 1,
+        ]);
+        assert_eq!(
+            map().merged_rects(&tiles),
+            vec![rect(0, 0, 2, 2), rect(3, 2, 1, 1)]
+        );
+    }
+
+    #[test]
+    fn splits_l_shapes() {
+        #[rustfmt::skip]
+        let tiles = layer(&[
+            1, 0, 0, 0,
+            1, 0, 0, 0,
+            1, 1, 1, 0,
+        ]);
+        assert_eq!(
+            map().merged_rects(&tiles),
+            vec![rect(0, 0, 1, 2), rect(0, 2, 3, 1)]
+        );
+    }
+
+    #[test]
+    fn ignores_flip_flags() {
+        let flipped = 0x8000_0000 | 1;
+        #[rustfmt::skip]
+        let tiles = layer(&[
+            1, flipped, 0, 0,
+            0, 0,       0, 0,
+            0, 0,       0, 0,
+        ]);
+        assert_eq!(map().merged_rects(&tiles), vec![rect(0, 0, 2, 1)]);
+    }
+
+    #[test]
+    fn visible_tiles_inside_the_map() {
+        assert_eq!(map().visible_tiles(1.5, 2.5, 2.5, 1.5), rect(1, 0, 2, 2));
+    }
+
+    #[test]
+    fn visible_tiles_clamp_to_the_map() {
+        assert_eq!(
+            map().visible_tiles(-5.0, 10.0, 20.0, -10.0),
+            rect(0, 0, 4, 3)
+        );
+    }
+
+    #[test]
+    fn nothing_is_visible_off_the_map() {
+        assert_eq!(map().visible_tiles(10.0, 2.0, 12.0, 1.0), rect(4, 1, 0, 2));
+    }
+}
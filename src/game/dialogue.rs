@@ -0,0 +1,377 @@
+use super::components::hud::HudValues;
+use super::components::input::KeyInputHandler;
+use super::components::inventory::Inventory;
+use super::components::script;
+use super::items::Items;
+use super::state::{GameState, StateRequests};
+use legion::{IntoQuery, Resources, World};
+use log::error;
+use ron::de::from_reader;
+use rustpython_vm::function::FuncArgs;
+use rustpython_vm::pyobject::{ItemProtocol, PyObjectRef, PyResult, TryFromObject};
+use rustpython_vm::{scope::Scope, Interpreter, VirtualMachine};
+use serde::Deserialize;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fs::{self, File};
+
+// Game variables are `HudValues` entries; items are the player's inventory.
+#[derive(Debug, Clone, Deserialize)]
+pub enum DialogueCondition {
+    AtLeast(String, f32),
+    Below(String, f32),
+    HasItem(String),
+    Not(Box<DialogueCondition>),
+    // A python function taking no arguments and returning a bool.
+    Script(String),
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub enum DialogueAction {
+    SetValue(String, f32),
+    AddValue(String, f32),
+    // Whatever doesn't fit in the inventory is lost.
+    GiveItem {
+        item: String,
+        #[serde(default = "default_count")]
+        count: u32,
+    },
+    TakeItem {
+        item: String,
+        #[serde(default = "default_count")]
+        count: u32,
+    },
+    // A python function taking no arguments.
+    Script(String),
+}
+
+fn default_count() -> u32 {
+    1
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct DialogueChoice {
+    pub text: String,
+    // Hidden unless it holds when the line is shown.
+    #[serde(default)]
+    pub condition: Option<DialogueCondition>,
+    #[serde(default)]
+    pub actions: Vec<DialogueAction>,
+    // The conversation ends without one.
+    #[serde(default)]
+    pub next: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct DialogueNode {
+    #[serde(default)]
+    pub speaker: Option<String>,
+    pub text: String,
+    // Run when the line is shown.
+    #[serde(default)]
+    pub actions: Vec<DialogueAction>,
+    #[serde(default)]
+    pub choices: Vec<DialogueChoice>,
+    // Where a line without visible choices continues to; the conversation
+    // ends without one.
+    #[serde(default)]
+    pub next: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct DialogueDesc {
+    pub start: String,
+    pub nodes: HashMap<String, DialogueNode>,
+}
+
+#[derive(Debug, Default)]
+pub struct Dialogues {
+    descs: HashMap<String, DialogueDesc>,
+}
+
+impl Dialogues {
+    // Loads every `<name>.ron` in the directory as the dialogue `name`.
+    pub fn load(dir: &str) -> Self {
+        let mut dialogues = Self::default();
+        let entries = fs::read_dir(crate::utils::from_out_dir(dir))
+            .unwrap_or_else(|e| panic!("{:?}- Dialogue directory: {}", dir, e));
+        for path in entries
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.path())
+        {
+            if path.extension().map_or(true, |ext| ext != "ron") {
+                continue;
+            }
+            let name = path.file_stem().unwrap().to_string_lossy().to_string();
+            let f = File::open(&path).expect("Failed opening file");
+            let desc: DialogueDesc = match from_reader(f) {
+                Ok(x) => x,
+                Err(e) => {
+                    error!("Failed to load dialogue {:?}: {}", name, e);
+                    continue;
+                }
+            };
+            if !desc.nodes.contains_key(&desc.start) {
+                error!("Dialogue {:?} has no start node {:?}", name, desc.start);
+            }
+            dialogues.descs.insert(name, desc);
+        }
+        dialogues
+    }
+
+    pub fn get(&self, name: &str) -> Option<&DialogueDesc> {
+        self.descs.get(name)
+    }
+}
+
+// The line being shown, with the choices that passed their conditions.
+#[derive(Debug, Clone)]
+pub struct Line {
+    pub dialogue: String,
+    pub node: String,
+    pub speaker: Option<String>,
+    pub text: String,
+    // Indices into the node's choices, with their text.
+    pub choices: Vec<(usize, String)>,
+}
+
+#[derive(Debug, Default)]
+pub struct Conversation {
+    line: Option<Line>,
+}
+
+impl Conversation {
+    pub fn line(&self) -> Option<&Line> {
+        self.line.as_ref()
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.line.is_some()
+    }
+}
+
+// Shows the start of the dialogue and pushes the dialogue state. Returns
+// false for an unknown dialogue or when already talking.
+pub fn start(world: &mut World, resources: &Resources, name: &str) -> bool {
+    if resources.get::<Conversation>().unwrap().is_active() {
+        return false;
+    }
+    let node = match resources.get::<Dialogues>().unwrap().get(name) {
+        Some(desc) => desc.start.clone(),
+        None => {
+            error!("Unknown dialogue {:?}", name);
+            return false;
+        }
+    };
+    if !enter(world, resources, name, &node) {
+        return false;
+    }
+    resources
+        .get_mut::<StateRequests>()
+        .unwrap()
+        .push(GameState::Dialogue);
+    true
+}
+
+// Moves past the current line, taking the visible choice at `choice` if it
+// has any. Ending the conversation pops the dialogue state.
+pub fn advance(world: &mut World, resources: &Resources, choice: Option<usize>) {
+    let line = match resources.get::<Conversation>().unwrap().line.clone() {
+        Some(line) => line,
+        None => return,
+    };
+    let node = resources
+        .get::<Dialogues>()
+        .unwrap()
+        .get(&line.dialogue)
+        .and_then(|desc| desc.nodes.get(&line.node))
+        .cloned();
+    let node = match node {
+        Some(node) => node,
+        None => {
+            end(resources);
+            return;
+        }
+    };
+
+    let next = if line.choices.is_empty() {
+        node.next
+    } else {
+        let index = match choice.and_then(|i| line.choices.get(i)) {
+            Some((index, _)) => *index,
+            // Lines with choices wait for one.
+            None => return,
+        };
+        let choice = &node.choices[index];
+        for action in &choice.actions {
+            run_action(action, world, resources);
+        }
+        choice.next.clone()
+    };
+    let entered = match next {
+        Some(next) => enter(world, resources, &line.dialogue, &next),
+        None => false,
+    };
+    if !entered {
+        end(resources);
+    }
+}
+
+// Closes the dialogue box and hands control back to the player.
+pub fn end(resources: &Resources) {
+    if resources
+        .get_mut::<Conversation>()
+        .unwrap()
+        .line
+        .take()
+        .is_some()
+    {
+        resources.get_mut::<StateRequests>().unwrap().pop();
+    }
+}
+
+// Runs the node's actions and makes it the current line. Returns false if
+// there is no such node.
+fn enter(world: &mut World, resources: &Resources, dialogue: &str, id: &str) -> bool {
+    let node = resources
+        .get::<Dialogues>()
+        .unwrap()
+        .get(dialogue)
+        .and_then(|desc| desc.nodes.get(id))
+        .cloned();
+    let node = match node {
+        Some(node) => node,
+        None => {
+            error!("Dialogue {:?} has no node {:?}", dialogue, id);
+            return false;
+        }
+    };
+    for action in &node.actions {
+        run_action(action, world, resources);
+    }
+    let choices = node
+        .choices
+        .iter()
+        .enumerate()
+        .filter(|(_, choice)| {
+            choice
+                .condition
+                .as_ref()
+                .map_or(true, |condition| check(condition, world, resources))
+        })
+        .map(|(i, choice)| (i, choice.text.clone()))
+        .collect();
+    resources.get_mut::<Conversation>().unwrap().line = Some(Line {
+        dialogue: dialogue.to_string(),
+        node: id.to_string(),
+        speaker: node.speaker,
+        text: node.text,
+        choices,
+    });
+    true
+}
+
+// Calls `f` with the player's inventory, i.e. the first one on an entity
+// taking key input.
+fn with_inventory<R>(world: &mut World, f: impl FnOnce(&mut Inventory) -> R) -> Option<R> {
+    let mut query = <(&KeyInputHandler, &mut Inventory)>::query();
+    let found = query.iter_mut(world).next();
+    found.map(|(_, inventory)| f(inventory))
+}
+
+fn check(condition: &DialogueCondition, world: &mut World, resources: &Resources) -> bool {
+    let value = |name: &str| {
+        resources
+            .get::<HudValues>()
+            .unwrap()
+            .get(name)
+            .unwrap_or(0.0)
+    };
+    match condition {
+        DialogueCondition::AtLeast(name, amount) => value(name) >= *amount,
+        DialogueCondition::Below(name, amount) => value(name) < *amount,
+        DialogueCondition::HasItem(item) => {
+            with_inventory(world, |inventory| inventory.count(item) > 0).unwrap_or(false)
+        }
+        DialogueCondition::Not(condition) => !check(condition, world, resources),
+        DialogueCondition::Script(function) => {
+            call_script(function, resources).flatten().unwrap_or(false)
+        }
+    }
+}
+
+fn run_action(action: &DialogueAction, world: &mut World, resources: &Resources) {
+    match action {
+        DialogueAction::SetValue(name, value) => {
+            resources.get_mut::<HudValues>().unwrap().set(name, *value)
+        }
+        DialogueAction::AddValue(name, amount) => {
+            resources.get_mut::<HudValues>().unwrap().add(name, *amount)
+        }
+        DialogueAction::GiveItem { item, count } => {
+            let stack_size = match resources.get::<Items>().unwrap().get(item) {
+                Some(desc) => desc.stack_size,
+                None => {
+                    error!("Unknown item {:?}", item);
+                    return;
+                }
+            };
+            with_inventory(world, |inventory| inventory.add(item, *count, stack_size));
+        }
+        DialogueAction::TakeItem { item, count } => {
+            with_inventory(world, |inventory| inventory.remove(item, *count));
+        }
+        DialogueAction::Script(function) => {
+            call_script(function, resources);
+        }
+    }
+}
+
+// Calls a python function from the shared scope. Returns None if it failed,
+// and its result if that was a bool.
+fn call_script(function: &str, resources: &Resources) -> Option<Option<bool>> {
+    let runtime = resources.get::<Interpreter>().unwrap();
+    let scope = resources.get::<Scope>().unwrap();
+    let result = runtime.enter(|vm| {
+        let function = scope.globals.get_item(function, vm)?;
+        let ret = vm.invoke(&function, Vec::<PyObjectRef>::new())?;
+        Ok(bool::try_from_object(vm, ret).ok())
+    });
+    match result {
+        Ok(ret) => Some(ret),
+        Err(_) => {
+            error!("Dialogue script {:?} failed", function);
+            None
+        }
+    }
+}
+
+thread_local! {
+    // Conversations started from scripts, picked up by `start_requested`.
+    static START_REQUESTS: RefCell<Vec<String>> = RefCell::new(Vec::new());
+}
+
+pub fn start_requested(world: &mut World, resources: &Resources) {
+    let requests = START_REQUESTS.with(|requests| requests.replace(Vec::new()));
+    for name in requests {
+        start(world, resources, &name);
+    }
+}
+
+// start_dialogue(name: str)
+fn py_start_dialogue(args: FuncArgs, vm: &VirtualMachine) -> PyResult {
+    let (name,): (String,) = args.bind(vm)?;
+    START_REQUESTS.with(|requests| requests.borrow_mut().push(name));
+    Ok(vm.ctx.none())
+}
+
+pub fn inject_script_functions(resources: &mut Resources) {
+    let mut runtime = resources.get_mut::<Interpreter>().unwrap();
+    let mut scope = resources.get_mut::<Scope>().unwrap();
+    script::inject_function(
+        &mut runtime,
+        &mut scope,
+        "start_dialogue",
+        py_start_dialogue,
+    );
+}
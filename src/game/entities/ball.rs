@@ -1,15 +1,19 @@
+use super::super::behaviour::BehaviourRegistry;
+use super::super::components::primitive::Primitive;
 use nphysics2d::math::Isometry;
 use skulpin::skia_safe::{colors, Canvas, Paint};
 
-const BALL_RADIUS: f32 = 0.5;
-
-pub fn draw(canvas: &mut Canvas, isometry: &Isometry<f32>) {
+pub fn draw(canvas: &mut Canvas, isometry: &Isometry<f32>, primitive: &Primitive) {
     let position = isometry.translation;
     let paint = Paint::new(colors::GREEN, None);
 
     canvas.draw_circle(
         skulpin::skia_safe::Point::new(position.x, position.y),
-        BALL_RADIUS,
+        primitive.size,
         &paint,
     );
 }
+
+pub fn register(registry: &mut BehaviourRegistry) {
+    registry.register_primitive("ball.draw", draw);
+}
@@ -1,9 +1,11 @@
+use super::super::behaviour::BehaviourRegistry;
 use super::super::components::animate::*;
 use super::super::components::sprite::*;
-use nphysics2d::math::{Isometry, Velocity};
+use log::error;
+use nphysics2d::math::Isometry;
 use nphysics2d::object::{DefaultBodyHandle, DefaultBodySet};
 use num_traits::{AsPrimitive, FromPrimitive};
-use skulpin::skia_safe::{colors, Canvas, Paint, Rect as SkiaRect};
+use skulpin::skia_safe::Canvas;
 use skulpin::winit::event::ElementState;
 use skulpin::winit::event::VirtualKeyCode as Keycode;
 
@@ -13,6 +15,9 @@ pub enum CharacterState {
     IdleRight = 1,
     RunningLeft = 2,
     RunningRight = 3,
+    // Knocked down, e.g. on death. Ignores input.
+    DownLeft = 4,
+    DownRight = 5,
 }
 
 impl FromPrimitive for CharacterState {
@@ -22,6 +27,8 @@ impl FromPrimitive for CharacterState {
             1 => Some(Self::IdleRight),
             2 => Some(Self::RunningLeft),
             3 => Some(Self::RunningRight),
+            4 => Some(Self::DownLeft),
+            5 => Some(Self::DownRight),
             _ => None,
         }
     }
@@ -32,6 +39,8 @@ impl FromPrimitive for CharacterState {
             1 => Some(Self::IdleRight),
             2 => Some(Self::RunningLeft),
             3 => Some(Self::RunningRight),
+            4 => Some(Self::DownLeft),
+            5 => Some(Self::DownRight),
             _ => None,
         }
     }
@@ -42,6 +51,8 @@ impl FromPrimitive for CharacterState {
             1 => Some(Self::IdleRight),
             2 => Some(Self::RunningLeft),
             3 => Some(Self::RunningRight),
+            4 => Some(Self::DownLeft),
+            5 => Some(Self::DownRight),
             _ => None,
         }
     }
@@ -102,32 +113,26 @@ pub fn draw(canvas: &mut Canvas, isometry: &Isometry<f32>, source: &SpriteSheet,
 
     let img = make_skia_image(&clip.image);
 
-    let position = isometry.translation;
-    let paint = Paint::new(colors::RED, None);
-    let ratio = clip.width_over_height;
-
-    let rect = SkiaRect::from_xywh(position.x - ratio / 2.0, position.y - 0.5, ratio, 1.0);
-
-    #[cfg(feature = "bounds")]
-    {
-        use skulpin::skia_safe::Point;
-        let p1 = Point::new(position.x - ratio / 2.0, position.y - 0.5);
-        let p2 = Point::new(position.x - ratio / 2.0, position.y + 0.5);
-        let p3 = Point::new(position.x + ratio / 2.0, position.y + 0.5);
-        let p4 = Point::new(position.x + ratio / 2.0, position.y - 0.5);
-        canvas.draw_line(p1, p2, &paint);
-        canvas.draw_line(p2, p3, &paint);
-        canvas.draw_line(p3, p4, &paint);
-        canvas.draw_line(p4, p1, &paint);
-    }
+    let paint = source.paint();
+    let rect = source.clip_rect(clip, &isometry.translation.vector);
 
     canvas.draw_image_rect(img, None, rect, &paint);
 }
 
 pub fn delta(state: u32, input: u32) -> u32 {
-    let state = CharacterState::from_u32(state).unwrap();
-    let input = CharacterInput::from_u32(input).unwrap();
+    // Script processes and deltas can hand over anything.
+    let (state, input) = match (
+        CharacterState::from_u32(state),
+        CharacterInput::from_u32(input),
+    ) {
+        (Some(state), Some(input)) => (state, input),
+        _ => {
+            error!("Unknown character state {} or input {}", state, input);
+            return state;
+        }
+    };
     match (state, input) {
+        (CharacterState::DownLeft, _) | (CharacterState::DownRight, _) => state as u32,
         (_, CharacterInput::Left) => CharacterState::RunningLeft as u32,
         (_, CharacterInput::Right) => CharacterState::RunningRight as u32,
         (CharacterState::RunningLeft, CharacterInput::Interrupt) => CharacterState::IdleLeft as u32,
@@ -139,41 +144,29 @@ pub fn delta(state: u32, input: u32) -> u32 {
     }
 }
 
+// Movement is driven by the `CharacterController`; this only advances frames.
+// They wrap around at the sheet's frame count in `animate_entities`.
 pub fn animate(
     anim: &mut Animate,
-    body_handle: &DefaultBodyHandle,
-    bodies: &mut DefaultBodySet<f32>,
+    _body_handle: &DefaultBodyHandle,
+    _bodies: &mut DefaultBodySet<f32>,
 ) {
-    let speed = 2.0;
-    let body = bodies.rigid_body_mut(*body_handle).unwrap();
-    let (num_states, velocity) = match anim.state() {
-        CharacterState::IdleLeft | CharacterState::IdleRight => {
-            (4, Velocity::<f32>::linear(0.0, 0.0))
-        }
-        CharacterState::RunningLeft => (6, Velocity::<f32>::linear(-speed, 0.0)),
-        CharacterState::RunningRight => (6, Velocity::<f32>::linear(speed, 0.0)),
-    };
-    body.set_velocity(velocity);
-    anim.ticks = (anim.ticks + 1) % num_states;
+    anim.ticks += 1;
 }
 
-pub fn source() -> SpriteSheet {
-    let config_path = crate::utils::from_out_dir("res/assets/adventurer_sprite.ron");
-    SpriteSheet::from_config(&config_path)
-}
-
-pub fn process(
-    keycode: Option<Keycode>,
-    key_state: &ElementState,
-    controlled_character: &mut Animate,
-) {
+pub fn process(keycode: Option<Keycode>, key_state: &ElementState) -> Option<u32> {
     match (keycode, key_state) {
-        (Some(_), ElementState::Released) => {
-            controlled_character.delta(CharacterInput::Interrupt as u32);
-            controlled_character.ticks = 0;
-        }
-        (Some(Keycode::Left), _) => controlled_character.delta(CharacterInput::Left as u32),
-        (Some(Keycode::Right), _) => controlled_character.delta(CharacterInput::Right as u32),
-        _ => {}
+        (Some(Keycode::Left), ElementState::Released)
+        | (Some(Keycode::Right), ElementState::Released) => Some(CharacterInput::Interrupt as u32),
+        (Some(Keycode::Left), _) => Some(CharacterInput::Left as u32),
+        (Some(Keycode::Right), _) => Some(CharacterInput::Right as u32),
+        _ => None,
     }
 }
+
+pub fn register(registry: &mut BehaviourRegistry) {
+    registry.register_sprite("character.draw", draw);
+    registry.register_delta("character.delta", delta);
+    registry.register_animation("character.animate", animate);
+    registry.register_process("character.process", process);
+}
@@ -0,0 +1,35 @@
+use super::super::behaviour::BehaviourRegistry;
+use super::super::components::animate::Animate;
+use super::super::components::sprite::{make_skia_image, SpriteSheet};
+use nphysics2d::math::Isometry;
+use nphysics2d::object::{DefaultBodyHandle, DefaultBodySet};
+use skulpin::skia_safe::Canvas;
+
+// Items lying around in the world, e.g. pickups. The `Animate` state is the
+// sprite sheet key of the item's animation.
+pub fn draw(canvas: &mut Canvas, isometry: &Isometry<f32>, source: &SpriteSheet, anim: &Animate) {
+    let clip = source.get_clip(anim.current(), anim.ticks);
+    let img = make_skia_image(&clip.image);
+    let rect = source.clip_rect(clip, &isometry.translation.vector);
+    canvas.draw_image_rect(img, None, rect, &source.paint());
+}
+
+// Items don't change state.
+pub fn delta(state: u32, _input: u32) -> u32 {
+    state
+}
+
+// Frames wrap around in `animate_entities`.
+pub fn animate(
+    anim: &mut Animate,
+    _body_handle: &DefaultBodyHandle,
+    _bodies: &mut DefaultBodySet<f32>,
+) {
+    anim.ticks += 1;
+}
+
+pub fn register(registry: &mut BehaviourRegistry) {
+    registry.register_sprite("item.draw", draw);
+    registry.register_delta("item.delta", delta);
+    registry.register_animation("item.animate", animate);
+}
@@ -1,2 +1,17 @@
 pub mod ball;
 pub mod character;
+pub mod item;
+pub mod npc;
+pub mod slime;
+pub mod spikes;
+
+use super::behaviour::BehaviourRegistry;
+
+pub fn register_behaviours(registry: &mut BehaviourRegistry) {
+    ball::register(registry);
+    character::register(registry);
+    item::register(registry);
+    npc::register(registry);
+    slime::register(registry);
+    spikes::register(registry);
+}
@@ -0,0 +1,31 @@
+use super::super::behaviour::BehaviourRegistry;
+use super::super::components::primitive::Primitive;
+use nphysics2d::math::Isometry;
+use skulpin::skia_safe::{Canvas, Color4f, Paint, Path, Point};
+
+// A robed figure 1.2 times `size` tall, centered on the body position. The
+// size is the radius of the talking range.
+pub fn draw(canvas: &mut Canvas, isometry: &Isometry<f32>, primitive: &Primitive) {
+    let position = isometry.translation;
+    let height = primitive.size * 1.2;
+    let bottom = position.y - height / 2.0;
+    let head_radius = height * 0.15;
+
+    let mut robe = Path::new();
+    robe.move_to(Point::new(position.x - height * 0.25, bottom));
+    robe.line_to(Point::new(position.x + height * 0.25, bottom));
+    robe.line_to(Point::new(position.x, bottom + height - head_radius));
+    robe.close();
+    canvas.draw_path(&robe, &Paint::new(Color4f::new(0.35, 0.3, 0.6, 1.0), None));
+
+    let head = Point::new(position.x, bottom + height - head_radius * 1.5);
+    canvas.draw_circle(
+        head,
+        head_radius,
+        &Paint::new(Color4f::new(0.9, 0.75, 0.6, 1.0), None),
+    );
+}
+
+pub fn register(registry: &mut BehaviourRegistry) {
+    registry.register_primitive("npc.draw", draw);
+}
@@ -0,0 +1,30 @@
+use super::super::behaviour::BehaviourRegistry;
+use super::super::components::primitive::Primitive;
+use nphysics2d::math::Isometry;
+use skulpin::skia_safe::{Canvas, Color4f, Paint, Point, RRect, Rect};
+
+// A blob `size` wide and 0.6 times as high, centered on the body position.
+pub fn draw(canvas: &mut Canvas, isometry: &Isometry<f32>, primitive: &Primitive) {
+    let position = isometry.translation;
+    let width = primitive.size;
+    let height = primitive.size * 0.6;
+    let rect = Rect::from_xywh(
+        position.x - width / 2.0,
+        position.y - height / 2.0,
+        width,
+        height,
+    );
+    let body = RRect::new_rect_xy(&rect, height / 2.0, height / 2.0);
+    canvas.draw_rrect(body, &Paint::new(Color4f::new(0.3, 0.7, 0.3, 1.0), None));
+
+    // Eyes in the upper half.
+    let eye = Paint::new(Color4f::new(0.05, 0.1, 0.05, 1.0), None);
+    for side in [-1.0, 1.0].iter() {
+        let center = Point::new(position.x + side * width * 0.2, position.y + height * 0.1);
+        canvas.draw_circle(center, height * 0.12, &eye);
+    }
+}
+
+pub fn register(registry: &mut BehaviourRegistry) {
+    registry.register_primitive("slime.draw", draw);
+}
@@ -0,0 +1,29 @@
+use super::super::behaviour::BehaviourRegistry;
+use super::super::components::primitive::Primitive;
+use nphysics2d::math::Isometry;
+use skulpin::skia_safe::{Canvas, Color4f, Paint, Path, Point};
+
+const SPIKE_COUNT: usize = 3;
+
+// A row of spikes `size` wide and half as high, resting on the body position.
+pub fn draw(canvas: &mut Canvas, isometry: &Isometry<f32>, primitive: &Primitive) {
+    let position = isometry.translation;
+    let paint = Paint::new(Color4f::new(0.75, 0.75, 0.8, 1.0), None);
+
+    let width = primitive.size / SPIKE_COUNT as f32;
+    let left = position.x - primitive.size / 2.0;
+    let bottom = position.y - primitive.size / 4.0;
+    let mut path = Path::new();
+    for i in 0..SPIKE_COUNT {
+        let x = left + width * i as f32;
+        path.move_to(Point::new(x, bottom));
+        path.line_to(Point::new(x + width / 2.0, bottom + primitive.size / 2.0));
+        path.line_to(Point::new(x + width, bottom));
+        path.close();
+    }
+    canvas.draw_path(&path, &paint);
+}
+
+pub fn register(registry: &mut BehaviourRegistry) {
+    registry.register_primitive("spikes.draw", draw);
+}
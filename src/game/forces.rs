@@ -0,0 +1,230 @@
+use super::physics::{Physics, GRAVITY};
+use nalgebra::{Point2, Vector2};
+use nphysics2d::algebra::{Force2, ForceType};
+use nphysics2d::force_generator::{DefaultForceGeneratorHandle, ForceGenerator};
+use nphysics2d::object::{Body, BodySet, BodyStatus, DefaultBodyHandle};
+use nphysics2d::solver::IntegrationParameters;
+use serde::Deserialize;
+
+// Zones are axis-aligned boxes given by their `min` and `max` corners. Zones
+// and "ground" anchors are offset by the `offset` given to `build`.
+#[derive(Debug, Clone, Deserialize)]
+pub enum ForceDesc {
+    Wind {
+        min: (f32, f32),
+        max: (f32, f32),
+        force: (f32, f32),
+    },
+    // Either end may be "ground", which attaches it to `ground_anchor`.
+    Spring {
+        body1: String,
+        body2: String,
+        rest_length: f32,
+        stiffness: f32,
+        #[serde(default)]
+        damping: f32,
+        #[serde(default)]
+        ground_anchor: (f32, f32),
+    },
+    Buoyancy {
+        min: (f32, f32),
+        max: (f32, f32),
+        // Upward acceleration when fully submerged, in multiples of gravity.
+        density: f32,
+        #[serde(default)]
+        drag: f32,
+    },
+}
+
+impl ForceDesc {
+    pub fn build<F>(
+        &self,
+        physics: &mut Physics,
+        offset: Vector2<f32>,
+        resolve: F,
+    ) -> Option<DefaultForceGeneratorHandle>
+    where
+        F: Fn(&str) -> Option<DefaultBodyHandle>,
+    {
+        let point = |(x, y): (f32, f32)| Point2::new(x, y) + offset;
+        let generator: Box<dyn ForceGenerator<f32, DefaultBodyHandle>> = match self {
+            ForceDesc::Wind { min, max, force } => Box::new(WindZone {
+                min: point(*min),
+                max: point(*max),
+                force: Vector2::new(force.0, force.1),
+            }),
+            ForceDesc::Spring {
+                body1,
+                body2,
+                rest_length,
+                stiffness,
+                damping,
+                ground_anchor,
+            } => {
+                let end = |name: &str| {
+                    if name == "ground" {
+                        Some(SpringEnd::Point(point(*ground_anchor)))
+                    } else {
+                        resolve(name).map(SpringEnd::Body)
+                    }
+                };
+                let (end1, end2) = match (end(body1), end(body2)) {
+                    (Some(end1), Some(end2)) => (end1, end2),
+                    _ => {
+                        log::error!("Spring references unknown body {:?} or {:?}", body1, body2);
+                        return None;
+                    }
+                };
+                Box::new(Spring {
+                    end1,
+                    end2,
+                    rest_length: *rest_length,
+                    stiffness: *stiffness,
+                    damping: *damping,
+                })
+            }
+            ForceDesc::Buoyancy {
+                min,
+                max,
+                density,
+                drag,
+            } => Box::new(Buoyancy {
+                min: point(*min),
+                max: point(*max),
+                density: *density,
+                drag: *drag,
+            }),
+        };
+        Some(physics.force_generators.insert(generator))
+    }
+}
+
+fn contains(min: &Point2<f32>, max: &Point2<f32>, p: &Point2<f32>) -> bool {
+    p.x >= min.x && p.x <= max.x && p.y >= min.y && p.y <= max.y
+}
+
+// Pushes every dynamic body whose center of mass lies in the zone.
+pub struct WindZone {
+    pub min: Point2<f32>,
+    pub max: Point2<f32>,
+    pub force: Vector2<f32>,
+}
+
+impl ForceGenerator<f32, DefaultBodyHandle> for WindZone {
+    fn apply(
+        &mut self,
+        _: &IntegrationParameters<f32>,
+        bodies: &mut dyn BodySet<f32, Handle = DefaultBodyHandle>,
+    ) {
+        let force = Force2::linear(self.force);
+        bodies.foreach_mut(&mut |_, body: &mut dyn Body<f32>| {
+            if body.status() != BodyStatus::Dynamic {
+                return;
+            }
+            let inside = body.part(0).map_or(false, |part| {
+                contains(&self.min, &self.max, &part.center_of_mass())
+            });
+            if inside {
+                body.apply_force(0, &force, ForceType::Force, true);
+            }
+        });
+    }
+}
+
+#[derive(Debug, Copy, Clone)]
+pub enum SpringEnd {
+    // The body's center of mass.
+    Body(DefaultBodyHandle),
+    // A fixed point in the world.
+    Point(Point2<f32>),
+}
+
+// A damped spring between two bodies, or a body and a point.
+pub struct Spring {
+    pub end1: SpringEnd,
+    pub end2: SpringEnd,
+    pub rest_length: f32,
+    pub stiffness: f32,
+    pub damping: f32,
+}
+
+impl ForceGenerator<f32, DefaultBodyHandle> for Spring {
+    fn apply(
+        &mut self,
+        _: &IntegrationParameters<f32>,
+        bodies: &mut dyn BodySet<f32, Handle = DefaultBodyHandle>,
+    ) {
+        let state = |bodies: &dyn BodySet<f32, Handle = DefaultBodyHandle>, end| match end {
+            SpringEnd::Body(handle) => bodies
+                .get(handle)
+                .and_then(|body| body.part(0))
+                .map(|part| (part.center_of_mass(), part.velocity().linear)),
+            SpringEnd::Point(point) => Some((point, Vector2::zeros())),
+        };
+        let ((p1, v1), (p2, v2)) = match (state(bodies, self.end1), state(bodies, self.end2)) {
+            (Some(a), Some(b)) => (a, b),
+            _ => return,
+        };
+
+        let delta = p2 - p1;
+        let length = delta.norm();
+        if length <= std::f32::EPSILON {
+            return;
+        }
+        let direction = delta / length;
+        let stretch = length - self.rest_length;
+        let relative_speed = (v2 - v1).dot(&direction);
+        let force = direction * (self.stiffness * stretch + self.damping * relative_speed);
+
+        for (end, force) in [(self.end1, force), (self.end2, -force)].iter() {
+            if let SpringEnd::Body(handle) = end {
+                if let Some(body) = bodies.get_mut(*handle) {
+                    body.apply_force(0, &Force2::linear(*force), ForceType::Force, true);
+                }
+            }
+        }
+    }
+}
+
+// Lifts bodies below the zone's top edge, proportionally to how deep they are
+// (fully submerged one unit below the surface), and slows them down.
+pub struct Buoyancy {
+    pub min: Point2<f32>,
+    pub max: Point2<f32>,
+    pub density: f32,
+    pub drag: f32,
+}
+
+impl ForceGenerator<f32, DefaultBodyHandle> for Buoyancy {
+    fn apply(
+        &mut self,
+        _: &IntegrationParameters<f32>,
+        bodies: &mut dyn BodySet<f32, Handle = DefaultBodyHandle>,
+    ) {
+        bodies.foreach_mut(&mut |_, body: &mut dyn Body<f32>| {
+            if body.status() != BodyStatus::Dynamic {
+                return;
+            }
+            let (center, velocity) = match body.part(0) {
+                Some(part) => (part.center_of_mass(), part.velocity().linear),
+                None => return,
+            };
+            if !contains(&self.min, &self.max, &center) {
+                return;
+            }
+            let submerged = (self.max.y - center.y).min(1.0);
+            let lift = Vector2::y() * -GRAVITY * self.density * submerged;
+            let acceleration = lift - velocity * self.drag;
+            body.apply_force(
+                0,
+                &Force2::linear(acceleration),
+                ForceType::AccelerationChange,
+                true,
+            );
+        });
+    }
+}
+
+pub fn remove_force_generator(physics: &mut Physics, handle: DefaultForceGeneratorHandle) {
+    physics.force_generators.remove(handle);
+}
@@ -0,0 +1,106 @@
+use super::components::combat::Health;
+use super::components::hud::HudValues;
+use super::components::sprite::{Clip, SpriteSheet};
+use log::error;
+use ron::de::from_reader;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fs::File;
+
+#[derive(Debug, Clone, Deserialize)]
+pub enum ItemEffect {
+    Heal(f32),
+    // Adds to a `HudValues` entry, e.g. the score.
+    AddValue { value: String, amount: f32 },
+}
+
+impl ItemEffect {
+    pub fn apply(&self, health: Option<&mut Health>, values: &mut HudValues) {
+        match self {
+            Self::Heal(amount) => {
+                if let Some(health) = health {
+                    health.heal(*amount);
+                }
+            }
+            Self::AddValue { value, amount } => values.add(value, *amount),
+        }
+    }
+}
+
+// A frame of a sprite sheet animation.
+#[derive(Debug, Clone, Deserialize)]
+pub struct ItemIcon {
+    pub source: String,
+    pub animation: String,
+    #[serde(default)]
+    pub frame: usize,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ItemDesc {
+    pub name: String,
+    #[serde(default)]
+    pub icon: Option<ItemIcon>,
+    // How many fit in one inventory slot.
+    #[serde(default = "default_stack_size")]
+    pub stack_size: u32,
+    // Applied when the item is used, or on pickup if it's `instant`.
+    #[serde(default)]
+    pub effects: Vec<ItemEffect>,
+    // Used up on pickup instead of going into the inventory, e.g. coins.
+    #[serde(default)]
+    pub instant: bool,
+}
+
+fn default_stack_size() -> u32 {
+    99
+}
+
+// Item definitions keyed by id.
+#[derive(Debug, Default, Deserialize)]
+pub struct ItemConfig {
+    #[serde(default)]
+    pub items: HashMap<String, ItemDesc>,
+}
+
+#[derive(Default)]
+pub struct Items {
+    descs: HashMap<String, ItemDesc>,
+    sheets: HashMap<String, SpriteSheet>,
+}
+
+impl Items {
+    pub fn load(filename: &str) -> Self {
+        let f = File::open(crate::utils::from_out_dir(filename)).expect("Failed opening file");
+        let config: ItemConfig = match from_reader(f) {
+            Ok(x) => x,
+            Err(e) => {
+                error!("Failed to load item config: {}", e);
+                ItemConfig::default()
+            }
+        };
+
+        let mut sheets = HashMap::new();
+        for icon in config.items.values().filter_map(|desc| desc.icon.as_ref()) {
+            if !sheets.contains_key(&icon.source) {
+                let path = crate::utils::from_out_dir(&icon.source);
+                sheets.insert(icon.source.clone(), SpriteSheet::from_config(&path));
+            }
+        }
+        Self {
+            descs: config.items,
+            sheets,
+        }
+    }
+
+    pub fn get(&self, id: &str) -> Option<&ItemDesc> {
+        self.descs.get(id)
+    }
+
+    pub fn icon(&self, id: &str) -> Option<&Clip> {
+        let icon = self.get(id)?.icon.as_ref()?;
+        self.sheets
+            .get(&icon.source)?
+            .get_named_clip(&icon.animation, icon.frame)
+    }
+}
@@ -0,0 +1,263 @@
+use super::physics::Physics;
+use nalgebra::{Point2, Unit, UnitComplex, Vector2};
+use nphysics2d::joint::{
+    DefaultJointConstraintHandle, FixedConstraint, MouseConstraint, PrismaticConstraint,
+    RevoluteConstraint,
+};
+use nphysics2d::object::{BodyPartHandle, DefaultBodyHandle};
+use serde::Deserialize;
+
+// Anchors are local to their body; "ground" names the static world body, whose
+// anchors are offset by the `ground_offset` given to `build`.
+#[derive(Debug, Clone, Deserialize)]
+pub enum JointDesc {
+    Revolute {
+        body1: String,
+        anchor1: (f32, f32),
+        body2: String,
+        anchor2: (f32, f32),
+    },
+    Prismatic {
+        body1: String,
+        anchor1: (f32, f32),
+        axis: (f32, f32),
+        body2: String,
+        anchor2: (f32, f32),
+        #[serde(default)]
+        limits: Option<(f32, f32)>,
+    },
+    Fixed {
+        body1: String,
+        anchor1: (f32, f32),
+        body2: String,
+        anchor2: (f32, f32),
+    },
+    // Pulls `anchor` on `body` towards the world-space `target`.
+    Mouse {
+        body: String,
+        anchor: (f32, f32),
+        target: (f32, f32),
+        max_force: f32,
+    },
+}
+
+// Where a joint attaches to each body, local to the body, as nphysics
+// constraints don't expose their anchors. The ground's local space is the
+// world's.
+#[derive(Debug, Copy, Clone)]
+pub struct JointAnchors {
+    pub body1: DefaultBodyHandle,
+    pub anchor1: Point2<f32>,
+    pub body2: DefaultBodyHandle,
+    pub anchor2: Point2<f32>,
+}
+
+fn point((x, y): (f32, f32)) -> Point2<f32> {
+    Point2::new(x, y)
+}
+
+impl JointDesc {
+    pub fn build<F>(
+        &self,
+        physics: &mut Physics,
+        ground_offset: Vector2<f32>,
+        resolve: F,
+    ) -> Option<DefaultJointConstraintHandle>
+    where
+        F: Fn(&str) -> Option<DefaultBodyHandle>,
+    {
+        let ground = physics.ground;
+        let resolve = |name: &str| {
+            if name == "ground" {
+                Some(ground)
+            } else {
+                let handle = resolve(name);
+                if handle.is_none() {
+                    log::error!("Joint references unknown body {:?}", name);
+                }
+                handle
+            }
+        };
+        let anchor_point = |name: &str, anchor: (f32, f32)| {
+            if name == "ground" {
+                point(anchor) + ground_offset
+            } else {
+                point(anchor)
+            }
+        };
+        let handle = match self {
+            JointDesc::Revolute {
+                body1,
+                anchor1,
+                body2,
+                anchor2,
+            } => add_revolute(
+                physics,
+                resolve(body1)?,
+                anchor_point(body1, *anchor1),
+                resolve(body2)?,
+                anchor_point(body2, *anchor2),
+            ),
+            JointDesc::Prismatic {
+                body1,
+                anchor1,
+                axis,
+                body2,
+                anchor2,
+                limits,
+            } => add_prismatic(
+                physics,
+                resolve(body1)?,
+                anchor_point(body1, *anchor1),
+                Vector2::new(axis.0, axis.1),
+                resolve(body2)?,
+                anchor_point(body2, *anchor2),
+                *limits,
+            ),
+            JointDesc::Fixed {
+                body1,
+                anchor1,
+                body2,
+                anchor2,
+            } => add_fixed(
+                physics,
+                resolve(body1)?,
+                anchor_point(body1, *anchor1),
+                resolve(body2)?,
+                anchor_point(body2, *anchor2),
+            ),
+            JointDesc::Mouse {
+                body,
+                anchor,
+                target,
+                max_force,
+            } => add_mouse(
+                physics,
+                resolve(body)?,
+                anchor_point(body, *anchor),
+                anchor_point("ground", *target),
+                *max_force,
+            ),
+        };
+        Some(handle)
+    }
+}
+
+pub fn add_revolute(
+    physics: &mut Physics,
+    body1: DefaultBodyHandle,
+    anchor1: Point2<f32>,
+    body2: DefaultBodyHandle,
+    anchor2: Point2<f32>,
+) -> DefaultJointConstraintHandle {
+    let constraint = RevoluteConstraint::new(
+        BodyPartHandle(body1, 0),
+        BodyPartHandle(body2, 0),
+        anchor1,
+        anchor2,
+    );
+    let handle = physics.joint_constraints.insert(constraint);
+    record(physics, handle, body1, anchor1, body2, anchor2)
+}
+
+pub fn add_prismatic(
+    physics: &mut Physics,
+    body1: DefaultBodyHandle,
+    anchor1: Point2<f32>,
+    axis: Vector2<f32>,
+    body2: DefaultBodyHandle,
+    anchor2: Point2<f32>,
+    limits: Option<(f32, f32)>,
+) -> DefaultJointConstraintHandle {
+    let mut constraint = PrismaticConstraint::new(
+        BodyPartHandle(body1, 0),
+        BodyPartHandle(body2, 0),
+        anchor1,
+        Unit::new_normalize(axis),
+        anchor2,
+    );
+    if let Some((min, max)) = limits {
+        constraint.enable_min_offset(min);
+        constraint.enable_max_offset(max);
+    }
+    let handle = physics.joint_constraints.insert(constraint);
+    record(physics, handle, body1, anchor1, body2, anchor2)
+}
+
+pub fn add_fixed(
+    physics: &mut Physics,
+    body1: DefaultBodyHandle,
+    anchor1: Point2<f32>,
+    body2: DefaultBodyHandle,
+    anchor2: Point2<f32>,
+) -> DefaultJointConstraintHandle {
+    let constraint = FixedConstraint::new(
+        BodyPartHandle(body1, 0),
+        BodyPartHandle(body2, 0),
+        anchor1,
+        UnitComplex::identity(),
+        anchor2,
+        UnitComplex::identity(),
+    );
+    let handle = physics.joint_constraints.insert(constraint);
+    record(physics, handle, body1, anchor1, body2, anchor2)
+}
+
+pub fn add_mouse(
+    physics: &mut Physics,
+    body: DefaultBodyHandle,
+    anchor: Point2<f32>,
+    target: Point2<f32>,
+    max_force: f32,
+) -> DefaultJointConstraintHandle {
+    let constraint = MouseConstraint::new(
+        BodyPartHandle(physics.ground, 0),
+        BodyPartHandle(body, 0),
+        target,
+        anchor,
+        max_force,
+    );
+    let handle = physics.joint_constraints.insert(constraint);
+    let ground = physics.ground;
+    record(physics, handle, ground, target, body, anchor)
+}
+
+fn record(
+    physics: &mut Physics,
+    handle: DefaultJointConstraintHandle,
+    body1: DefaultBodyHandle,
+    anchor1: Point2<f32>,
+    body2: DefaultBodyHandle,
+    anchor2: Point2<f32>,
+) -> DefaultJointConstraintHandle {
+    let anchors = JointAnchors {
+        body1,
+        anchor1,
+        body2,
+        anchor2,
+    };
+    physics.joint_anchors.insert(handle, anchors);
+    handle
+}
+
+pub fn set_mouse_target(
+    physics: &mut Physics,
+    handle: DefaultJointConstraintHandle,
+    target: Point2<f32>,
+) {
+    if let Some(constraint) = physics
+        .joint_constraints
+        .get_mut(handle)
+        .and_then(|c| c.downcast_mut::<MouseConstraint<f32, DefaultBodyHandle>>())
+    {
+        constraint.set_anchor_1(target);
+        if let Some(anchors) = physics.joint_anchors.get_mut(&handle) {
+            anchors.anchor1 = target;
+        }
+    }
+}
+
+pub fn remove_joint(physics: &mut Physics, handle: DefaultJointConstraintHandle) {
+    physics.joint_constraints.remove(handle);
+    physics.joint_anchors.remove(&handle);
+}
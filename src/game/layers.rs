@@ -0,0 +1,124 @@
+use log::{error, warn};
+use ncollide2d::pipeline::CollisionGroups;
+use ron::de::from_reader;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fs::File;
+
+// Every layer accepts this group, so queries can pick which layers they see.
+const QUERY_GROUP: usize = 29;
+
+#[derive(Debug, Deserialize)]
+struct CollisionLayersDesc {
+    layers: Vec<LayerDesc>,
+}
+
+// Interactions only need to be listed on one of the two layers. Pairs that
+// should only produce proximity events need a sensor layer on one side.
+#[derive(Debug, Deserialize)]
+struct LayerDesc {
+    name: String,
+    #[serde(default)]
+    contacts: Vec<String>,
+    #[serde(default)]
+    proximity: Vec<String>,
+    #[serde(default)]
+    sensor: bool,
+}
+
+#[derive(Default)]
+pub struct CollisionLayers {
+    indices: HashMap<String, usize>,
+    groups: HashMap<String, CollisionGroups>,
+    sensors: Vec<String>,
+}
+
+impl CollisionLayers {
+    pub fn load(filename: &str) -> Self {
+        let f = File::open(crate::utils::from_out_dir(filename)).expect("Failed opening file");
+        let desc: CollisionLayersDesc = match from_reader(f) {
+            Ok(x) => x,
+            Err(e) => {
+                error!("Failed to load collision layers: {}", e);
+                return Self::default();
+            }
+        };
+        Self::from_desc(desc)
+    }
+
+    fn from_desc(desc: CollisionLayersDesc) -> Self {
+        let mut layers = Self::default();
+        if desc.layers.len() > QUERY_GROUP {
+            error!("At most {} collision layers are supported", QUERY_GROUP);
+        }
+        for (i, layer) in desc.layers.iter().take(QUERY_GROUP).enumerate() {
+            layers.indices.insert(layer.name.clone(), i);
+            if layer.sensor {
+                layers.sensors.push(layer.name.clone());
+            }
+        }
+
+        let mut whitelists: HashMap<usize, Vec<usize>> = HashMap::new();
+        for layer in &desc.layers {
+            let index = match layers.indices.get(&layer.name) {
+                Some(index) => *index,
+                None => continue,
+            };
+            for other in layer.contacts.iter().chain(&layer.proximity) {
+                let other_index = match layers.indices.get(other) {
+                    Some(other_index) => *other_index,
+                    None => {
+                        warn!(
+                            "Layer {:?} interacts with unknown layer {:?}",
+                            layer.name, other
+                        );
+                        continue;
+                    }
+                };
+                whitelists.entry(index).or_default().push(other_index);
+                whitelists.entry(other_index).or_default().push(index);
+            }
+            for other in &layer.proximity {
+                if !layer.sensor && !layers.is_sensor(other) {
+                    warn!(
+                        "Layers {:?} and {:?} will generate contacts, neither is a sensor",
+                        layer.name, other
+                    );
+                }
+            }
+        }
+
+        for (name, index) in &layers.indices {
+            let mut whitelist = whitelists.remove(index).unwrap_or_default();
+            whitelist.push(QUERY_GROUP);
+            let groups = CollisionGroups::new()
+                .with_membership(&[*index])
+                .with_whitelist(&whitelist);
+            layers.groups.insert(name.clone(), groups);
+        }
+        layers
+    }
+
+    pub fn groups(&self, layer: &str) -> Option<CollisionGroups> {
+        let groups = self.groups.get(layer).copied();
+        if groups.is_none() {
+            error!("Unknown collision layer {:?}", layer);
+        }
+        groups
+    }
+
+    pub fn is_sensor(&self, layer: &str) -> bool {
+        self.sensors.iter().any(|sensor| sensor == layer)
+    }
+
+    // Groups for a physics query that only sees the given layers.
+    pub fn query_groups(&self, layers: &[&str]) -> CollisionGroups {
+        let whitelist: Vec<usize> = layers
+            .iter()
+            .filter_map(|layer| self.indices.get(*layer).copied())
+            .collect();
+        CollisionGroups::new()
+            .with_membership(&[QUERY_GROUP])
+            .with_whitelist(&whitelist)
+    }
+}
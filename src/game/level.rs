@@ -1,110 +1,282 @@
-use super::components::animate::Animate;
-use super::components::input::KeyInputHandler;
-use super::components::primitive::Primitive;
-use super::components::sprite::Sprite;
-use super::entities::ball;
-use super::entities::character;
+use super::audio::AudioEvents;
+use super::components::hud::{HudValues, HudWidget};
+use super::components::physics_body::PhysicsBody;
+use super::components::tilemap::Tilemap;
+use super::forces::ForceDesc;
+use super::joints::JointDesc;
+use super::layers::CollisionLayers;
+use super::navigation::{NavConfig, NavGraph};
+use super::physics::Physics;
+use super::prefab::{self, Prefabs};
 use legion::{Resources, World};
+use log::error;
 use nalgebra::Vector2;
-use ncollide2d::shape::{Ball, Cuboid, ShapeHandle};
-use nphysics2d::object::{
-    BodyPartHandle, BodyStatus, ColliderDesc, DefaultBodySet, DefaultColliderSet, Ground,
-    RigidBodyDesc,
-};
+use ncollide2d::pipeline::CollisionGroups;
+use ncollide2d::shape::{Cuboid, ShapeHandle};
+use nphysics2d::object::{BodyPartHandle, ColliderDesc, DefaultBodySet, DefaultColliderSet};
+use ron::de::from_reader;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fs::File;
 
 pub struct Level {
     name: String,
+    desc: LevelDesc,
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct LevelDesc {
+    // Collision layer of the ground and tilemap colliders.
+    #[serde(default = "default_terrain_layer")]
+    pub terrain_layer: String,
+    #[serde(default)]
+    pub tilemap: Option<TilemapDesc>,
+    #[serde(default)]
+    pub spawns: Vec<Spawn>,
+    // Bodies are referred to by spawn name, or "ground" for the level itself.
+    #[serde(default)]
+    pub joints: Vec<JointDesc>,
+    #[serde(default)]
+    pub forces: Vec<ForceDesc>,
+    // A sound from the audio config, looped while the level is loaded.
+    #[serde(default)]
+    pub music: Option<String>,
+    #[serde(default)]
+    pub hud: HudDesc,
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct HudDesc {
+    // Spawned as entities when the level loads.
+    #[serde(default)]
+    pub widgets: Vec<HudWidget>,
+    // Starting `HudValues`, e.g. a score of 0.
+    #[serde(default)]
+    pub values: HashMap<String, f32>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct TilemapDesc {
+    pub path: String,
+    #[serde(default = "default_tile_size")]
+    pub tile_size: f32,
+    #[serde(default)]
+    pub origin: (f32, f32),
+    #[serde(default = "default_collision_layer")]
+    pub collision_layer: String,
+}
+
+// The loaded level's `terrain_layer`, for queries that only see level geometry.
+#[derive(Debug, Clone)]
+pub struct TerrainLayer(pub String);
+
+impl Default for TerrainLayer {
+    fn default() -> Self {
+        Self(default_terrain_layer())
+    }
+}
+
+fn default_terrain_layer() -> String {
+    "terrain".to_string()
+}
+
+fn default_tile_size() -> f32 {
+    1.0
+}
+
+fn default_collision_layer() -> String {
+    "collision".to_string()
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Spawn {
+    pub prefab: String,
+    pub position: (f32, f32),
+    // Lets joints and forces refer to the spawned body.
+    #[serde(default)]
+    pub name: Option<String>,
 }
 
 pub const GROUND_THICKNESS: f32 = 0.2;
 pub const GROUND_HALF_EXTENTS_WIDTH: f32 = 3.0;
-pub const BALL_RADIUS: f32 = 0.5;
-pub const BALL_COUNT: usize = 5;
 
 impl Level {
     pub fn new() -> Self {
+        Self::from_config("test")
+    }
+
+    pub fn from_config(name: &str) -> Self {
+        let path = crate::utils::from_out_dir(&format!("res/levels/{}.ron", name));
+        let f = File::open(&path).expect("Failed opening file");
+        let desc: LevelDesc = match from_reader(f) {
+            Ok(x) => x,
+            Err(e) => {
+                error!("Failed to load level {:?}: {}", name, e);
+
+                std::process::exit(1);
+            }
+        };
+
         Self {
-            name: "test".to_string(),
+            name: name.to_string(),
+            desc,
         }
     }
 
     pub fn init(&self, world: &mut World, resources: &mut Resources) {
         println!("Loading level {:?}", self.name);
+        resources.insert(TerrainLayer(self.desc.terrain_layer.clone()));
+        if let Some(music) = &self.desc.music {
+            resources
+                .get_mut::<AudioEvents>()
+                .unwrap()
+                .play_music(music);
+        }
+        {
+            let mut values = resources.get_mut::<HudValues>().unwrap();
+            for (name, value) in &self.desc.hud.values {
+                values.set(name, *value);
+            }
+        }
+        for widget in &self.desc.hud.widgets {
+            world.push((widget.clone(),));
+        }
+
         // A rectangle that the balls will fall on
         let ground_shape = ShapeHandle::new(Cuboid::new(Vector2::new(
             GROUND_HALF_EXTENTS_WIDTH,
             GROUND_THICKNESS,
         )));
 
-        let mut bodies = resources
-            .get_mut::<DefaultBodySet<f32>>()
-            .unwrap_or_else(|| panic!("{:?}- Bodyset", self.name));
-        let mut colliders = resources
-            .get_mut::<DefaultColliderSet<f32>>()
-            .unwrap_or_else(|| panic!("{:?}- Colliderset", self.name));
-
-        // Build a static ground body and add it to the body set.
-        let ground_body_handle = bodies.insert(Ground::new());
-
-        // Build the collider.
-        let ground_collider = ColliderDesc::new(ground_shape)
-            .translation(Vector2::y() * -GROUND_THICKNESS)
-            .build(BodyPartHandle(ground_body_handle, 0));
-
-        // Add the collider to the collider set.
-        colliders.insert(ground_collider);
-
-        let ball_shape_handle = ShapeHandle::new(Ball::new(BALL_RADIUS));
+        {
+            let ground_body_handle = resources.get::<Physics>().unwrap().ground;
+            let mut colliders = resources
+                .get_mut::<DefaultColliderSet<f32>>()
+                .unwrap_or_else(|| panic!("{:?}- Colliderset", self.name));
+            let terrain_groups = self.terrain_groups(resources);
 
-        let shift = BALL_RADIUS + ColliderDesc::<f32>::default_margin();
-        let centerx = shift * (BALL_COUNT as f32) / 2.0;
+            // Build the collider.
+            let ground_collider = ColliderDesc::new(ground_shape)
+                .translation(Vector2::y() * -GROUND_THICKNESS)
+                .collision_groups(terrain_groups)
+                .build(BodyPartHandle(ground_body_handle, 0));
 
-        let x = shift - centerx;
-        let y = shift;
+            // Add the collider to the collider set.
+            colliders.insert(ground_collider);
+        }
 
-        // Build the rigid body.
-        let rigid_body = RigidBodyDesc::new()
-            .translation(Vector2::new(x, y))
-            .status(BodyStatus::Static)
-            .build();
+        if let Some(desc) = &self.desc.tilemap {
+            self.init_tilemap(desc, world, resources);
+        }
 
-        // Insert the rigid body to the body set.
-        let rigid_body_handle = bodies.insert(rigid_body);
+        let mut named_bodies = HashMap::new();
+        for spawn in &self.desc.spawns {
+            let position = Vector2::new(spawn.position.0, spawn.position.1);
+            let entity = prefab::spawn_prefab(world, resources, &spawn.prefab, position);
+            let body_handle = entity
+                .and_then(|entity| world.entry(entity))
+                .and_then(|entry| {
+                    entry
+                        .get_component::<PhysicsBody>()
+                        .ok()
+                        .map(|physics_body| physics_body.body)
+                });
+            if let (Some(name), Some(body_handle)) = (&spawn.name, body_handle) {
+                named_bodies.insert(name.clone(), body_handle);
+            }
+        }
 
-        // Build the collider.
-        let ball_collider = ColliderDesc::new(ball_shape_handle.clone())
-            .density(1.0)
-            .sensor(true)
-            .build(BodyPartHandle(rigid_body_handle, 0));
+        let navigation = self.build_navigation(resources);
+        resources.insert(navigation);
 
-        // Insert the collider to the body set.
-        colliders.insert(ball_collider);
-        let primitive = Primitive::new(ball::draw);
-        world.push((rigid_body_handle, primitive));
+        let mut physics = resources.get_mut::<Physics>().unwrap();
+        let resolve = |name: &str| named_bodies.get(name).copied();
+        for joint in &self.desc.joints {
+            joint.build(&mut physics, Vector2::zeros(), resolve);
+        }
+        for force in &self.desc.forces {
+            force.build(&mut physics, Vector2::zeros(), resolve);
+        }
+    }
 
-        let source = character::source();
-        let clip = source.get_clip(character::CharacterState::IdleLeft, 0);
-        let ratio = clip.width_over_height;
-        let sprite = Sprite::new(character::draw, source);
-        let animate = Animate::new(0, character::delta, character::animate);
-        // Build the rigid body.
-        let rigid_body = RigidBodyDesc::new().translation(Vector2::y()).build();
+    fn init_tilemap(&self, desc: &TilemapDesc, world: &mut World, resources: &mut Resources) {
+        let path = crate::utils::from_out_dir(&desc.path);
+        let origin = Vector2::new(desc.origin.0, desc.origin.1);
+        let tilemap = match Tilemap::from_file(&path, desc.tile_size, origin) {
+            Some(tilemap) => tilemap,
+            None => return,
+        };
 
-        // Insert the rigid body to the body set.
-        let rigid_body_handle = bodies.insert(rigid_body);
+        let mut spawns = Vec::new();
+        {
+            let prefabs = resources.get::<Prefabs>().unwrap();
+            let ground_body_handle = resources.get::<Physics>().unwrap().ground;
+            let mut colliders = resources
+                .get_mut::<DefaultColliderSet<f32>>()
+                .unwrap_or_else(|| panic!("{:?}- Colliderset", self.name));
+            let layers = resources.get::<CollisionLayers>().unwrap();
+            let terrain_groups = self.terrain_groups(resources);
 
-        let box_shape_handle = ShapeHandle::new(Cuboid::new(Vector2::new(ratio / 2.0, 0.5)));
+            // Static colliders from the merged tiles of the collision layer.
+            if let Some(layer) = tilemap.layer(&desc.collision_layer) {
+                for rect in tilemap.merged_rects(layer) {
+                    let (left, top, right, bottom) = tilemap.world_rect(rect);
+                    let half_extents = Vector2::new(right - left, top - bottom) / 2.0;
+                    let collider = ColliderDesc::new(ShapeHandle::new(Cuboid::new(half_extents)))
+                        .translation(Vector2::new(left, bottom) + half_extents)
+                        .collision_groups(terrain_groups)
+                        .build(BodyPartHandle(ground_body_handle, 0));
+                    colliders.insert(collider);
+                }
+            }
 
-        // Build the collider.
-        let box_collider = ColliderDesc::new(box_shape_handle)
-            .density(1.0)
-            .build(BodyPartHandle(rigid_body_handle, 0));
+            // Object layer entries become sensors or prefab spawns.
+            for object in &tilemap.objects {
+                let name = object.properties.get("prefab").unwrap_or(&object.kind);
+                if object.kind == "sensor" {
+                    let layer = object
+                        .properties
+                        .get("layer")
+                        .map_or("trigger", String::as_str);
+                    let groups = layers.groups(layer).unwrap_or_else(CollisionGroups::new);
+                    let collider =
+                        ColliderDesc::new(ShapeHandle::new(Cuboid::new(object.half_extents)))
+                            .translation(object.center)
+                            .sensor(true)
+                            .collision_groups(groups)
+                            .build(BodyPartHandle(ground_body_handle, 0));
+                    colliders.insert(collider);
+                } else if prefabs.get(name).is_some() {
+                    spawns.push((name.clone(), object.center));
+                }
+            }
+        }
 
-        // Insert the collider to the body set.
-        colliders.insert(box_collider);
+        for (name, position) in spawns {
+            prefab::spawn_prefab(world, resources, &name, position);
+        }
+        world.push((tilemap,));
+    }
 
-        let key_input_handler = KeyInputHandler::new(character::process);
+    // Covers the ground, the tilemap and static prefabs, so it runs once
+    // everything has spawned.
+    fn build_navigation(&self, resources: &Resources) -> NavGraph {
+        let config = resources.get::<NavConfig>().unwrap();
+        let controller = resources
+            .get::<Prefabs>()
+            .unwrap()
+            .get(&config.prefab)
+            .and_then(|desc| desc.controller.clone())
+            .unwrap_or_default();
+        let bodies = resources.get::<DefaultBodySet<f32>>().unwrap();
+        let colliders = resources.get::<DefaultColliderSet<f32>>().unwrap();
+        NavGraph::build(&config, &controller, &bodies, &colliders)
+    }
 
-        world.push((rigid_body_handle, key_input_handler, animate, sprite));
+    fn terrain_groups(&self, resources: &Resources) -> CollisionGroups {
+        let layers = resources.get::<CollisionLayers>().unwrap();
+        layers
+            .groups(&self.desc.terrain_layer)
+            .unwrap_or_else(CollisionGroups::new)
     }
 }
@@ -1,68 +1,219 @@
+pub mod ai;
+use ai::{AiConfig, BehaviourTrees};
+pub mod audio;
+use audio::{Audio, AudioEvents};
+pub mod behaviour;
+use behaviour::BehaviourRegistry;
+pub mod dialogue;
+use dialogue::{Conversation, Dialogues};
+pub mod forces;
+pub mod items;
+use items::Items;
+pub mod joints;
+pub mod layers;
+use layers::CollisionLayers;
+pub mod navigation;
+use navigation::NavConfig;
 pub mod physics;
 use physics::*;
 mod level;
 use legion::*;
+pub mod prefab;
+use prefab::Prefabs;
+pub mod query;
+pub mod state;
+mod state_handlers;
 mod systems;
-use level::*;
+pub mod timing;
 use skulpin::winit::event::VirtualKeyCode as Keycode;
-use systems::DeltaTime;
+use state::{GameState, StateHandler, StateRequests, StateStack, StateTransition};
+use std::collections::HashMap;
+use timing::{FrameConfig, FrameTiming};
 // use super::deno::Deno;
 pub mod components;
 pub mod entities;
-use components::animate::Animate;
-use components::input::KeyInputHandler;
+use components::combat::{CombatContacts, DamageEvents};
+use components::hud::HudValues;
+use components::physics_body::{self, BodyEntities};
 use components::script;
+use nalgebra::Vector2;
 use skulpin::winit::event::ElementState;
 
 pub struct Game {
     pub world: World,
-    pub schedule: Schedule,
+    pub states: StateStack,
+    // The schedule, hooks and input handling of each state.
+    handlers: HashMap<GameState, StateHandler>,
     pub resources: Resources,
-    pub nsteps: usize,
 }
 
 impl Default for Game {
     fn default() -> Self {
-        let mut world = World::default();
-        let schedule = Schedule::builder()
-            .add_system(systems::physics_system())
-            .add_system(systems::animate_entities_system())
-            .build();
+        let world = World::default();
         let mut resources = Resources::default();
-        resources.insert(DeltaTime::default());
+        resources.insert(FrameTiming::new(FrameConfig::load("res/config/frame.ron")));
 
         let physics = Physics::new(&mut resources);
         resources.insert(physics);
+        resources.insert(BodyEntities::default());
+        resources.insert(CollisionLayers::load("res/config/collision_layers.ron"));
+        resources.insert(Audio::load(
+            "res/config/audio.ron",
+            Audio::default_backend(),
+        ));
+        resources.insert(AudioEvents::default());
+        resources.insert(HudValues::default());
+        resources.insert(level::TerrainLayer::default());
+        resources.insert(StateRequests::default());
+        resources.insert(CombatContacts::default());
+        resources.insert(DamageEvents::default());
 
         // let mut python = Python::default();
         // python.init();
 
         script::inject_resource_dependencies(&mut resources);
 
-        let level = Level::new();
-        level.init(&mut world, &mut resources);
+        let mut registry = BehaviourRegistry::default();
+        entities::register_behaviours(&mut registry);
+        registry.load_scripts("res/config/behaviours.ron");
+        resources.insert(registry);
 
-        Self {
+        resources.insert(Items::load("res/config/items.ron"));
+        resources.insert(Prefabs::load("res/prefabs"));
+        resources.insert(AiConfig::load("res/config/ai.ron"));
+        resources.insert(NavConfig::load("res/config/navigation.ron"));
+        resources.insert(BehaviourTrees::load("res/ai"));
+        resources.insert(Dialogues::load("res/dialogue"));
+        resources.insert(Conversation::default());
+        prefab::inject_script_functions(&mut resources);
+        state::inject_script_functions(&mut resources);
+        dialogue::inject_script_functions(&mut resources);
+        ai::run_scripts(&resources);
+
+        let mut game = Self {
             world,
-            schedule,
+            states: StateStack::new(GameState::Menu),
+            handlers: state_handlers::build(),
             resources,
-            nsteps: 3,
-        }
+        };
+        // Loads the level under the main menu.
+        game.transition(StateTransition::Push(GameState::Loading));
+        game
     }
 }
 
 impl Game {
-    // fn load_level(&mut self, level: Level) {
-    //     level.init(&mut self.world, &mut self.physics);
-    // }
+    // Runs one frame if the frame interval has passed. Returns whether it did.
+    pub fn update(&mut self) -> bool {
+        let started = {
+            let mut timing = self.resources.get_mut::<FrameTiming>().unwrap();
+            if !timing.update_due() {
+                return false;
+            }
+            timing.begin_update()
+        };
+
+        if let Some(handler) = self.handlers.get_mut(&self.states.current()) {
+            handler
+                .schedule
+                .execute(&mut self.world, &mut self.resources);
+        }
+        prefab::spawn_requested(&mut self.world, &self.resources);
+        dialogue::start_requested(&mut self.world, &self.resources);
+        physics_body::remove_orphans(&self.world, &self.resources);
+        self.play_audio();
+        self.apply_state_requests();
+
+        self.resources
+            .get_mut::<FrameTiming>()
+            .unwrap()
+            .end_update(started);
+        true
+    }
+
+    pub fn spawn_prefab(&mut self, name: &str, position: Vector2<f32>) -> Option<Entity> {
+        self.request_redraw();
+        prefab::spawn_prefab(&mut self.world, &self.resources, name, position)
+    }
+
+    // Removes the entity along with its body and colliders.
+    pub fn despawn(&mut self, entity: Entity) -> bool {
+        self.request_redraw();
+        physics_body::despawn(&mut self.world, &self.resources, entity)
+    }
+
+    // Changes state right away, running exit and enter hooks, then whatever
+    // the hooks requested in turn.
+    pub fn transition(&mut self, transition: StateTransition) {
+        let (exited, entered) = self.states.apply(transition);
+        for state in exited {
+            if let Some(hook) = self
+                .handlers
+                .get(&state)
+                .and_then(|handler| handler.on_exit)
+            {
+                hook(&mut self.world, &mut self.resources);
+            }
+        }
+        if let Some(state) = entered {
+            if let Some(hook) = self
+                .handlers
+                .get(&state)
+                .and_then(|handler| handler.on_enter)
+            {
+                hook(&mut self.world, &mut self.resources);
+            }
+        }
+        self.request_redraw();
+        self.apply_state_requests();
+    }
+
+    // Moves the conversation on, picking one of the shown choices if the line
+    // has any.
+    pub fn advance_dialogue(&mut self, choice: Option<usize>) {
+        dialogue::advance(&mut self.world, &self.resources, choice);
+        self.request_redraw();
+        self.apply_state_requests();
+    }
+
+    fn apply_state_requests(&mut self) {
+        let requests = self.resources.get_mut::<StateRequests>().unwrap().drain();
+        for transition in requests {
+            self.transition(transition);
+        }
+    }
+
+    fn play_audio(&mut self) {
+        let requests = self.resources.get_mut::<AudioEvents>().unwrap().drain();
+        let mut audio = self.resources.get_mut::<Audio>().unwrap();
+        for request in requests {
+            audio.handle(request);
+        }
+        audio.maintain();
+    }
+
+    // Marks the scene as changed so the next frame gets drawn.
+    pub fn request_redraw(&self) {
+        self.resources
+            .get_mut::<FrameTiming>()
+            .unwrap()
+            .request_redraw();
+    }
 }
 
 impl Game {
-    pub fn send(&mut self, keycode: Option<Keycode>, key_state: ElementState) {
-        // construct a query from a "view tuple"
-        let mut query = <(&KeyInputHandler, &mut Animate)>::query();
-        for (input_handler, animate) in query.iter_mut(&mut self.world) {
-            input_handler.process(keycode, &key_state, animate);
-        }
+    // Hands a key event to the current state. Returns whether it was used.
+    pub fn send(&mut self, keycode: Option<Keycode>, key_state: ElementState) -> bool {
+        self.request_redraw();
+        let input = self
+            .handlers
+            .get(&self.states.current())
+            .and_then(|handler| handler.input);
+        let used = match input {
+            Some(input) => input(keycode, &key_state, &mut self.world, &self.resources),
+            None => false,
+        };
+        self.apply_state_requests();
+        used
     }
 }
@@ -0,0 +1,521 @@
+use super::components::controller::CharacterController;
+use super::physics::GRAVITY;
+use log::error;
+use nalgebra::Point2;
+use ncollide2d::bounding_volume::AABB;
+use nphysics2d::object::{Body, BodySet, BodyStatus, DefaultBodySet, DefaultColliderSet};
+use ron::de::from_reader;
+use serde::Deserialize;
+use std::cmp::Ordering;
+use std::collections::{BinaryHeap, HashMap};
+use std::fs::File;
+
+// Slack on reachability so planned jumps aren't right at the controller's limit.
+const JUMP_SAFETY: f32 = 0.9;
+// Extra cost of a jump, so walking is preferred when it's about as short.
+const JUMP_COST: f32 = 0.5;
+// Falling is quicker than walking the same distance.
+const DROP_COST_SCALE: f32 = 0.5;
+// How far above a node a point may be and still count as standing on it.
+const STANDING_HEIGHT: f32 = 1.0;
+const EPSILON: f32 = 0.001;
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct NavConfig {
+    // The prefab whose `CharacterController` decides which jumps are possible.
+    pub prefab: String,
+    // Distance between nodes along a surface.
+    pub spacing: f32,
+    // Free height needed above a surface to walk on it.
+    pub clearance: f32,
+    // How far from a surface's ends its outermost nodes are.
+    pub margin: f32,
+}
+
+impl Default for NavConfig {
+    fn default() -> Self {
+        Self {
+            prefab: "adventurer".to_string(),
+            spacing: 0.25,
+            clearance: 0.4,
+            margin: 0.1,
+        }
+    }
+}
+
+impl NavConfig {
+    pub fn load(filename: &str) -> Self {
+        let f = File::open(crate::utils::from_out_dir(filename)).expect("Failed opening file");
+        match from_reader(f) {
+            Ok(x) => x,
+            Err(e) => {
+                error!("Failed to load navigation config: {}", e);
+                Self::default()
+            }
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum LinkKind {
+    Walk,
+    Jump,
+    // Walking off a surface's end and falling onto a lower one.
+    Drop,
+}
+
+#[derive(Debug, Copy, Clone)]
+pub struct NavNode {
+    pub position: Point2<f32>,
+    pub surface: usize,
+}
+
+#[derive(Debug, Copy, Clone)]
+pub struct NavLink {
+    pub to: usize,
+    pub kind: LinkKind,
+    pub cost: f32,
+}
+
+// The top of a static collider, minus the parts without clearance.
+#[derive(Debug, Copy, Clone)]
+struct Surface {
+    left: f32,
+    right: f32,
+    y: f32,
+}
+
+// One node of a movement plan, along with how it is reached from the one
+// before. The first step is where the plan starts.
+#[derive(Debug, Copy, Clone)]
+pub struct NavStep {
+    pub node: usize,
+    pub position: Point2<f32>,
+    pub kind: LinkKind,
+}
+
+// Where a character can stand on the level's static geometry and how it gets
+// from one place to another. Built when a level loads.
+#[derive(Debug, Default)]
+pub struct NavGraph {
+    pub nodes: Vec<NavNode>,
+    // Outgoing links, indexed like `nodes`.
+    pub links: Vec<Vec<NavLink>>,
+    surfaces: Vec<Surface>,
+}
+
+impl NavGraph {
+    pub fn build(
+        config: &NavConfig,
+        controller: &CharacterController,
+        bodies: &DefaultBodySet<f32>,
+        colliders: &DefaultColliderSet<f32>,
+    ) -> Self {
+        let blocks: Vec<AABB<f32>> = colliders
+            .iter()
+            .filter(|(_, collider)| !collider.is_sensor())
+            .filter(|(_, collider)| {
+                bodies
+                    .get(collider.body())
+                    .map_or(false, |body| body.status() == BodyStatus::Static)
+            })
+            .map(|(_, collider)| collider.shape().aabb(collider.position()))
+            .collect();
+
+        Self::from_surfaces(surfaces(&blocks, config.clearance), config, controller)
+    }
+
+    fn from_surfaces(
+        surfaces: Vec<Surface>,
+        config: &NavConfig,
+        controller: &CharacterController,
+    ) -> Self {
+        let mut graph = Self {
+            surfaces,
+            ..Self::default()
+        };
+        graph.add_nodes(config);
+        graph.add_walk_links();
+        graph.add_drop_links(config.margin);
+        graph.add_jump_links(controller);
+        graph
+    }
+
+    fn add_nodes(&mut self, config: &NavConfig) {
+        for (index, surface) in self.surfaces.iter().enumerate() {
+            let left = surface.left + config.margin;
+            let right = surface.right - config.margin;
+            if right <= left {
+                let x = (surface.left + surface.right) / 2.0;
+                self.nodes.push(NavNode {
+                    position: Point2::new(x, surface.y),
+                    surface: index,
+                });
+                continue;
+            }
+            let gaps = ((right - left) / config.spacing).ceil().max(1.0) as usize;
+            for i in 0..=gaps {
+                let x = left + (right - left) * i as f32 / gaps as f32;
+                self.nodes.push(NavNode {
+                    position: Point2::new(x, surface.y),
+                    surface: index,
+                });
+            }
+        }
+        self.links = vec![Vec::new(); self.nodes.len()];
+    }
+
+    fn link(&mut self, from: usize, to: usize, kind: LinkKind) {
+        let (a, b) = (self.nodes[from].position, self.nodes[to].position);
+        let cost = match kind {
+            LinkKind::Walk => (b - a).norm(),
+            LinkKind::Jump => (b - a).norm() + JUMP_COST,
+            LinkKind::Drop => (b.x - a.x).abs() + (a.y - b.y) * DROP_COST_SCALE,
+        };
+        self.links[from].push(NavLink { to, kind, cost });
+    }
+
+    // Nodes on a surface are added left to right, so neighbours are adjacent.
+    fn add_walk_links(&mut self) {
+        for i in 1..self.nodes.len() {
+            if self.nodes[i - 1].surface == self.nodes[i].surface {
+                self.link(i - 1, i, LinkKind::Walk);
+                self.link(i, i - 1, LinkKind::Walk);
+            }
+        }
+    }
+
+    // From each end of a surface onto the highest surface below it.
+    fn add_drop_links(&mut self, margin: f32) {
+        for index in 0..self.surfaces.len() {
+            let surface = self.surfaces[index];
+            let ends = [
+                (surface.left - margin, true),
+                (surface.right + margin, false),
+            ];
+            for (x, left) in ends.iter() {
+                let below = self
+                    .surfaces
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, other)| other.y < surface.y - EPSILON)
+                    .filter(|(_, other)| other.left <= *x && *x <= other.right)
+                    .max_by(|(_, a), (_, b)| a.y.partial_cmp(&b.y).unwrap())
+                    .map(|(below, _)| below);
+                let from = match self.surface_nodes(index) {
+                    Some(nodes) if *left => nodes.start,
+                    Some(nodes) => nodes.end - 1,
+                    None => continue,
+                };
+                if let Some(below) = below {
+                    if let Some(to) = self.nearest_on(below, *x) {
+                        self.link(from, to, LinkKind::Drop);
+                    }
+                }
+            }
+        }
+    }
+
+    // From each node to the closest reachable node of every other surface.
+    // Jumps down must clear the end of the surface jumped from, and jumps up
+    // must come from beside the surface landed on rather than below it.
+    fn add_jump_links(&mut self, controller: &CharacterController) {
+        let speed = controller.jump_speed;
+        let gravity = -GRAVITY;
+        let max_height = speed * speed / (2.0 * gravity) * JUMP_SAFETY;
+        for from in 0..self.nodes.len() {
+            let a = self.nodes[from];
+            let start = self.surfaces[a.surface];
+            let mut best: HashMap<usize, (usize, f32)> = HashMap::new();
+            for (to, b) in self.nodes.iter().enumerate() {
+                if b.surface == a.surface {
+                    continue;
+                }
+                let end = self.surfaces[b.surface];
+                let dy = b.position.y - a.position.y;
+                let dx = (b.position.x - a.position.x).abs();
+                if dy > max_height {
+                    continue;
+                }
+                if dy < 0.0 && start.left <= b.position.x && b.position.x <= start.right {
+                    continue;
+                }
+                if dy > 0.0 && end.left <= a.position.x && a.position.x <= end.right {
+                    continue;
+                }
+                // Time until the arc comes back down to the landing height.
+                let airtime = (speed + (speed * speed - 2.0 * gravity * dy).sqrt()) / gravity;
+                if dx > controller.max_speed * airtime * JUMP_SAFETY {
+                    continue;
+                }
+                let distance = (b.position - a.position).norm();
+                let closer = best
+                    .get(&b.surface)
+                    .map_or(true, |(_, best)| distance < *best);
+                if closer {
+                    best.insert(b.surface, (to, distance));
+                }
+            }
+            for (surface, (to, _)) in best {
+                let dropped = self.links[from]
+                    .iter()
+                    .any(|link| self.nodes[link.to].surface == surface);
+                if !dropped {
+                    self.link(from, to, LinkKind::Jump);
+                }
+            }
+        }
+    }
+
+    fn surface_nodes(&self, surface: usize) -> Option<std::ops::Range<usize>> {
+        let start = self.nodes.iter().position(|node| node.surface == surface)?;
+        let count = self.nodes[start..]
+            .iter()
+            .take_while(|node| node.surface == surface)
+            .count();
+        Some(start..start + count)
+    }
+
+    fn nearest_on(&self, surface: usize, x: f32) -> Option<usize> {
+        self.surface_nodes(surface)?.min_by(|a, b| {
+            let a = (self.nodes[*a].position.x - x).abs();
+            let b = (self.nodes[*b].position.x - x).abs();
+            a.partial_cmp(&b).unwrap()
+        })
+    }
+
+    // The closest node a character at `point` could be standing on, or the
+    // closest node at all if there is none below it.
+    pub fn nearest(&self, point: Point2<f32>) -> Option<usize> {
+        let distance = |node: &NavNode| (node.position - point).norm();
+        let closest = |standing: bool| {
+            self.nodes
+                .iter()
+                .enumerate()
+                .filter(|(_, node)| {
+                    let height = point.y - node.position.y;
+                    !standing || (-EPSILON..=STANDING_HEIGHT).contains(&height)
+                })
+                .min_by(|(_, a), (_, b)| distance(a).partial_cmp(&distance(b)).unwrap())
+                .map(|(index, _)| index)
+        };
+        closest(true).or_else(|| closest(false))
+    }
+
+    // A* from node to node. The heuristic is the horizontal distance, which no
+    // link costs less than.
+    pub fn find_path(&self, from: usize, to: usize) -> Option<Vec<NavStep>> {
+        let goal = self.nodes.get(to)?.position;
+        let heuristic = |node: usize| (self.nodes[node].position.x - goal.x).abs();
+        let mut open = BinaryHeap::new();
+        let mut costs: HashMap<usize, f32> = HashMap::new();
+        let mut came_from: HashMap<usize, (usize, LinkKind)> = HashMap::new();
+        costs.insert(from, 0.0);
+        open.push(Open {
+            estimate: heuristic(from),
+            node: from,
+        });
+
+        while let Some(Open { node, .. }) = open.pop() {
+            if node == to {
+                return Some(self.unwind(from, to, &came_from));
+            }
+            let cost = costs[&node];
+            for link in &self.links[node] {
+                let next_cost = cost + link.cost;
+                if costs
+                    .get(&link.to)
+                    .map_or(false, |known| *known <= next_cost)
+                {
+                    continue;
+                }
+                costs.insert(link.to, next_cost);
+                came_from.insert(link.to, (node, link.kind));
+                open.push(Open {
+                    estimate: next_cost + heuristic(link.to),
+                    node: link.to,
+                });
+            }
+        }
+        None
+    }
+
+    fn unwind(
+        &self,
+        from: usize,
+        to: usize,
+        came_from: &HashMap<usize, (usize, LinkKind)>,
+    ) -> Vec<NavStep> {
+        let mut steps = Vec::new();
+        let mut node = to;
+        while node != from {
+            let (previous, kind) = came_from[&node];
+            steps.push(NavStep {
+                node,
+                position: self.nodes[node].position,
+                kind,
+            });
+            node = previous;
+        }
+        steps.push(NavStep {
+            node: from,
+            position: self.nodes[from].position,
+            kind: LinkKind::Walk,
+        });
+        steps.reverse();
+        steps
+    }
+
+    // A movement plan between two points, e.g. an agent and its target.
+    pub fn plan(&self, from: Point2<f32>, to: Point2<f32>) -> Option<Vec<NavStep>> {
+        self.find_path(self.nearest(from)?, self.nearest(to)?)
+    }
+}
+
+// Min-heap entry for A*.
+#[derive(Debug, Copy, Clone, PartialEq)]
+struct Open {
+    estimate: f32,
+    node: usize,
+}
+
+impl Eq for Open {}
+
+impl Ord for Open {
+    fn cmp(&self, other: &Self) -> Ordering {
+        other
+            .estimate
+            .partial_cmp(&self.estimate)
+            .unwrap_or(Ordering::Equal)
+    }
+}
+
+impl PartialOrd for Open {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+// The walkable tops of `blocks`, with touching ones at the same height merged.
+fn surfaces(blocks: &[AABB<f32>], clearance: f32) -> Vec<Surface> {
+    let mut surfaces = Vec::new();
+    for (i, block) in blocks.iter().enumerate() {
+        let y = block.maxs().y;
+        let mut spans = vec![(block.mins().x, block.maxs().x)];
+        for (j, other) in blocks.iter().enumerate() {
+            let overhead = other.mins().y < y + clearance && other.maxs().y > y + EPSILON;
+            if i != j && overhead {
+                spans = subtract(&spans, other.mins().x, other.maxs().x);
+            }
+        }
+        surfaces.extend(
+            spans
+                .into_iter()
+                .filter(|(left, right)| right - left > EPSILON)
+                .map(|(left, right)| Surface { left, right, y }),
+        );
+    }
+
+    surfaces.sort_by(|a, b| {
+        (a.y, a.left)
+            .partial_cmp(&(b.y, b.left))
+            .unwrap_or(Ordering::Equal)
+    });
+    let mut merged: Vec<Surface> = Vec::new();
+    for surface in surfaces {
+        match merged.last_mut() {
+            Some(last)
+                if (last.y - surface.y).abs() < EPSILON && surface.left <= last.right + EPSILON =>
+            {
+                last.right = last.right.max(surface.right);
+            }
+            _ => merged.push(surface),
+        }
+    }
+    merged
+}
+
+fn subtract(spans: &[(f32, f32)], left: f32, right: f32) -> Vec<(f32, f32)> {
+    let mut rest = Vec::new();
+    for &(a, b) in spans {
+        if right <= a || left >= b {
+            rest.push((a, b));
+            continue;
+        }
+        if left > a {
+            rest.push((a, left));
+        }
+        if right < b {
+            rest.push((right, b));
+        }
+    }
+    rest
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Surfaces as (left, right, y), with the default config and controller.
+    fn graph(surfaces: &[(f32, f32, f32)]) -> NavGraph {
+        let surfaces = surfaces
+            .iter()
+            .map(|&(left, right, y)| Surface { left, right, y })
+            .collect();
+        NavGraph::from_surfaces(
+            surfaces,
+            &NavConfig::default(),
+            &CharacterController::default(),
+        )
+    }
+
+    fn nodes_of(graph: &NavGraph, surface: usize) -> Vec<usize> {
+        graph.surface_nodes(surface).unwrap().collect()
+    }
+
+    fn kinds(path: &[NavStep]) -> Vec<LinkKind> {
+        path.iter().skip(1).map(|step| step.kind).collect()
+    }
+
+    #[test]
+    fn walks_along_a_surface() {
+        let graph = graph(&[(0.0, 2.0, 0.0)]);
+        let nodes = nodes_of(&graph, 0);
+        let (first, last) = (nodes[0], *nodes.last().unwrap());
+        let path = graph.find_path(first, last).unwrap();
+        assert_eq!(path.first().unwrap().node, first);
+        assert_eq!(path.last().unwrap().node, last);
+        assert_eq!(path.len(), nodes.len());
+        assert!(kinds(&path).iter().all(|kind| *kind == LinkKind::Walk));
+    }
+
+    #[test]
+    fn jumps_onto_a_step_and_back() {
+        let graph = graph(&[(0.0, 2.0, 0.0), (3.0, 4.0, 0.5)]);
+        let (ground, step) = (nodes_of(&graph, 0), nodes_of(&graph, 1));
+        let up = graph.find_path(ground[0], *step.last().unwrap()).unwrap();
+        assert!(kinds(&up).contains(&LinkKind::Jump));
+        let down = graph.find_path(*step.last().unwrap(), ground[0]).unwrap();
+        assert_eq!(down.last().unwrap().node, ground[0]);
+    }
+
+    #[test]
+    fn ledges_out_of_reach_are_unreachable() {
+        let graph = graph(&[(0.0, 2.0, 0.0), (8.0, 9.0, 5.0)]);
+        let (ground, ledge) = (nodes_of(&graph, 0), nodes_of(&graph, 1));
+        assert!(graph.find_path(ground[0], ledge[0]).is_none());
+        assert!(graph.find_path(ledge[0], ground[0]).is_none());
+    }
+
+    #[test]
+    fn drops_where_it_cant_jump_back() {
+        let graph = graph(&[(-4.0, 4.0, 0.0), (0.0, 2.0, 3.0)]);
+        let (ground, high) = (nodes_of(&graph, 0), nodes_of(&graph, 1));
+        let down = graph.find_path(high[0], ground[0]).unwrap();
+        let down = kinds(&down);
+        assert!(down.contains(&LinkKind::Drop));
+        assert!(!down.contains(&LinkKind::Jump));
+        assert!(graph.find_path(ground[0], high[0]).is_none());
+    }
+}
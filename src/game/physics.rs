@@ -1,19 +1,31 @@
+use super::joints::JointAnchors;
 use legion::Resources;
 use nalgebra::Vector2;
+use ncollide2d::pipeline::{ContactEvent, ProximityEvent};
 use nphysics2d::force_generator::DefaultForceGeneratorSet;
-use nphysics2d::joint::DefaultJointConstraintSet;
-use nphysics2d::object::{DefaultBodySet, DefaultColliderSet};
+use nphysics2d::joint::{DefaultJointConstraintHandle, DefaultJointConstraintSet};
+use nphysics2d::object::{
+    DefaultBodyHandle, DefaultBodySet, DefaultColliderHandle, DefaultColliderSet, Ground,
+};
 use nphysics2d::world::{DefaultGeometricalWorld, DefaultMechanicalWorld};
+use std::collections::HashMap;
 
 pub const GRAVITY: f32 = -9.81;
+// Steps taken at most per update; time beyond that is dropped so a slow frame
+// can't snowball into ever more steps.
+const MAX_STEPS: usize = 8;
 
 // Will contain all the physics simulation state
 pub struct Physics {
     pub geometrical_world: DefaultGeometricalWorld<f32>,
     mechanical_world: DefaultMechanicalWorld<f32>,
     pub joint_constraints: DefaultJointConstraintSet<f32>,
+    pub joint_anchors: HashMap<DefaultJointConstraintHandle, JointAnchors>,
     pub force_generators: DefaultForceGeneratorSet<f32>,
-    pub nsteps: usize,
+    // Static body for level geometry and the world side of joints.
+    pub ground: DefaultBodyHandle,
+    // Frame time not yet simulated, in seconds.
+    accumulator: f32,
 }
 
 impl Physics {
@@ -21,23 +33,45 @@ impl Physics {
         let geometrical_world = DefaultGeometricalWorld::<f32>::new();
         let mechanical_world = DefaultMechanicalWorld::new(Vector2::y() * GRAVITY);
 
-        let bodies = DefaultBodySet::<f32>::new();
+        let mut bodies = DefaultBodySet::<f32>::new();
+        let ground = bodies.insert(Ground::new());
         let colliders = DefaultColliderSet::<f32>::new();
         let joint_constraints = DefaultJointConstraintSet::<f32>::new();
         let force_generators = DefaultForceGeneratorSet::<f32>::new();
 
         resources.insert(bodies);
         resources.insert(colliders);
+        resources.insert(PhysicsEvents::default());
 
         Self {
             geometrical_world,
             mechanical_world,
             joint_constraints,
+            joint_anchors: HashMap::new(),
             force_generators,
-            nsteps: 3,
+            ground,
+            accumulator: 0.0,
         }
     }
 
+    pub fn timestep(&self) -> f32 {
+        self.mechanical_world.timestep()
+    }
+
+    // Adds `frame_time` to the time owed and returns how many fixed steps
+    // are due now.
+    pub fn steps_due(&mut self, frame_time: f32) -> usize {
+        let dt = self.timestep();
+        self.accumulator += frame_time;
+        let steps = (self.accumulator / dt).floor() as usize;
+        if steps > MAX_STEPS {
+            self.accumulator = 0.0;
+            return MAX_STEPS;
+        }
+        self.accumulator -= steps as f32 * dt;
+        steps
+    }
+
     pub fn step(
         &mut self,
         bodies: &mut DefaultBodySet<f32>,
@@ -51,5 +85,33 @@ impl Physics {
             &mut self.joint_constraints,
             &mut self.force_generators,
         );
+        // nphysics drops the joints of removed bodies.
+        let joints = &self.joint_constraints;
+        self.joint_anchors
+            .retain(|handle, _| joints.get(*handle).is_some());
+    }
+}
+
+// Proximity and contact events from every step of the current update.
+// nphysics clears its own on each step, so they're gathered here as the
+// steps run and read by the systems after physics.
+#[derive(Debug, Default)]
+pub struct PhysicsEvents {
+    pub proximity: Vec<ProximityEvent<DefaultColliderHandle>>,
+    pub contacts: Vec<ContactEvent<DefaultColliderHandle>>,
+}
+
+impl PhysicsEvents {
+    pub fn clear(&mut self) {
+        self.proximity.clear();
+        self.contacts.clear();
+    }
+
+    // Keeps the events of the step that just ran.
+    pub fn record(&mut self, physics: &Physics) {
+        let world = &physics.geometrical_world;
+        self.proximity
+            .extend(world.proximity_events().iter().cloned());
+        self.contacts.extend(world.contact_events().iter().cloned());
     }
 }
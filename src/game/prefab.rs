@@ -0,0 +1,442 @@
+use super::ai::BehaviourTrees;
+use super::behaviour::BehaviourRegistry;
+use super::components::ai::Agent;
+use super::components::animate::Animate;
+use super::components::combat::{Health, Hitbox, Hurtbox};
+use super::components::controller::CharacterController;
+use super::components::input::KeyInputHandler;
+use super::components::inventory::{Inventory, Pickup};
+use super::components::morph::ColliderMorph;
+use super::components::npc::Npc;
+use super::components::particles::{ParticleEmitter, ParticleShape};
+use super::components::physics_body::{self, PhysicsBody};
+use super::components::primitive::Primitive;
+use super::components::script;
+use super::components::sound::{FrameSounds, ImpactSound};
+use super::components::sprite::{Sprite, SpriteSheet};
+use super::dialogue::Dialogues;
+use super::forces::ForceDesc;
+use super::joints::JointDesc;
+use super::layers::CollisionLayers;
+use super::physics::Physics;
+use legion::{Entity, Resources, World};
+use log::error;
+use nalgebra::Vector2;
+use ncollide2d::shape::{Ball, Cuboid, ShapeHandle};
+use nphysics2d::object::{
+    BodyPartHandle, BodyStatus, ColliderDesc, DefaultBodySet, DefaultColliderSet, RigidBodyDesc,
+};
+use ron::de::from_reader;
+use rustpython_vm::function::FuncArgs;
+use rustpython_vm::pyobject::PyResult;
+use rustpython_vm::{scope::Scope, Interpreter, VirtualMachine};
+use serde::Deserialize;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::path::Path;
+
+#[derive(Debug, Deserialize)]
+pub struct PrefabDesc {
+    pub body: BodyDesc,
+    #[serde(default)]
+    pub collider: Option<ColliderTemplate>,
+    #[serde(default)]
+    pub render: Option<RenderDesc>,
+    #[serde(default)]
+    pub animate: Option<Animate>,
+    #[serde(default)]
+    pub input: Option<KeyInputHandler>,
+    #[serde(default)]
+    pub controller: Option<CharacterController>,
+    // Reshapes the collider with the sprite's frames.
+    #[serde(default)]
+    pub morph: Option<ColliderMorph>,
+    #[serde(default)]
+    pub frame_sounds: Option<FrameSounds>,
+    #[serde(default)]
+    pub impact_sound: Option<ImpactSound>,
+    #[serde(default)]
+    pub particles: Option<ParticleEmitter>,
+    #[serde(default)]
+    pub health: Option<Health>,
+    #[serde(default)]
+    pub hitbox: Option<Hitbox>,
+    #[serde(default)]
+    pub hurtbox: Option<Hurtbox>,
+    #[serde(default)]
+    pub inventory: Option<Inventory>,
+    #[serde(default)]
+    pub pickup: Option<Pickup>,
+    // Behaviour tree driven AI; "home" on its blackboard is the spawn position.
+    #[serde(default)]
+    pub agent: Option<Agent>,
+    #[serde(default)]
+    pub npc: Option<Npc>,
+    // "self" names the spawned body; ground anchors and force zones are
+    // relative to the spawn position.
+    #[serde(default)]
+    pub joints: Vec<JointDesc>,
+    #[serde(default)]
+    pub forces: Vec<ForceDesc>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct BodyDesc {
+    #[serde(default)]
+    pub status: BodyStatusDesc,
+    #[serde(default = "default_true")]
+    pub gravity_enabled: bool,
+    // Keeps the body upright, e.g. for anything with a `CharacterController`.
+    #[serde(default)]
+    pub lock_rotation: bool,
+}
+
+#[derive(Debug, Copy, Clone, Deserialize)]
+pub enum BodyStatusDesc {
+    Dynamic,
+    Static,
+    Kinematic,
+}
+
+impl Default for BodyStatusDesc {
+    fn default() -> Self {
+        Self::Dynamic
+    }
+}
+
+impl From<BodyStatusDesc> for BodyStatus {
+    fn from(status: BodyStatusDesc) -> Self {
+        match status {
+            BodyStatusDesc::Dynamic => BodyStatus::Dynamic,
+            BodyStatusDesc::Static => BodyStatus::Static,
+            BodyStatusDesc::Kinematic => BodyStatus::Kinematic,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ColliderTemplate {
+    pub shape: ShapeDesc,
+    #[serde(default = "default_density")]
+    pub density: f32,
+    #[serde(default)]
+    pub sensor: bool,
+    #[serde(default)]
+    pub translation: (f32, f32),
+    // A layer from the collision layer config; sensor layers make the collider a sensor.
+    #[serde(default)]
+    pub layer: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub enum ShapeDesc {
+    Ball { radius: f32 },
+    Cuboid { half_extents: (f32, f32) },
+    // A box of the given height whose width follows the initial sprite clip's ratio.
+    SpriteClip { height: f32 },
+}
+
+#[derive(Debug, Deserialize)]
+pub enum RenderDesc {
+    Primitive { draw_id: String },
+    Sprite { draw_id: String, source: String },
+}
+
+fn default_true() -> bool {
+    true
+}
+
+fn default_density() -> f32 {
+    1.0
+}
+
+#[derive(Default)]
+pub struct Prefabs {
+    descs: HashMap<String, PrefabDesc>,
+    sheets: HashMap<String, SpriteSheet>,
+}
+
+impl Prefabs {
+    // Loads every `<name>.ron` in the directory as the prefab `name`.
+    pub fn load(dir: &str) -> Self {
+        let mut prefabs = Self::default();
+        let entries = fs::read_dir(crate::utils::from_out_dir(dir))
+            .unwrap_or_else(|e| panic!("{:?}- Prefab directory: {}", dir, e));
+        for entry in entries.filter_map(|entry| entry.ok()) {
+            let path = entry.path();
+            if path.extension().map_or(true, |ext| ext != "ron") {
+                continue;
+            }
+            let name = path.file_stem().unwrap().to_string_lossy().to_string();
+            prefabs.insert(&name, &path);
+        }
+        prefabs
+    }
+
+    fn insert(&mut self, name: &str, path: &Path) {
+        let f = File::open(path).expect("Failed opening file");
+        let desc: PrefabDesc = match from_reader(f) {
+            Ok(x) => x,
+            Err(e) => {
+                error!("Failed to load prefab {:?}: {}", name, e);
+                return;
+            }
+        };
+
+        let render_source = match &desc.render {
+            Some(RenderDesc::Sprite { source, .. }) => Some(source),
+            _ => None,
+        };
+        let particle_source = match desc.particles.as_ref().map(|emitter| &emitter.shape) {
+            Some(ParticleShape::Sprite { source, .. }) => Some(source),
+            _ => None,
+        };
+        for source in render_source.into_iter().chain(particle_source) {
+            if !self.sheets.contains_key(source) {
+                let config_path = crate::utils::from_out_dir(source);
+                self.sheets
+                    .insert(source.clone(), SpriteSheet::from_config(&config_path));
+            }
+        }
+        self.descs.insert(name.to_string(), desc);
+    }
+
+    pub fn get(&self, name: &str) -> Option<&PrefabDesc> {
+        self.descs.get(name)
+    }
+}
+
+// Done on spawn so systems and the renderer don't report them every frame.
+fn report_unknown_behaviours(resources: &Resources, name: &str, desc: &PrefabDesc) {
+    let registry = resources.get::<BehaviourRegistry>().unwrap();
+    let mut unknown = Vec::new();
+    match &desc.render {
+        Some(RenderDesc::Primitive { draw_id }) if registry.primitive(draw_id).is_none() => {
+            unknown.push(draw_id)
+        }
+        Some(RenderDesc::Sprite { draw_id, .. }) if registry.sprite(draw_id).is_none() => {
+            unknown.push(draw_id)
+        }
+        _ => {}
+    }
+    if let Some(animate) = &desc.animate {
+        if registry.animation(&animate.animate_id).is_none() {
+            unknown.push(&animate.animate_id);
+        }
+        if !registry.has_delta(&animate.delta_id) {
+            unknown.push(&animate.delta_id);
+        }
+    }
+    if let Some(input) = &desc.input {
+        if !registry.has_process(&input.process_id) {
+            unknown.push(&input.process_id);
+        }
+    }
+    for id in unknown {
+        error!("Prefab {:?} uses unknown behaviour {:?}", name, id);
+    }
+}
+
+pub fn spawn_prefab(
+    world: &mut World,
+    resources: &Resources,
+    name: &str,
+    position: Vector2<f32>,
+) -> Option<Entity> {
+    let prefabs = resources.get::<Prefabs>().unwrap();
+    let desc = match prefabs.get(name) {
+        Some(desc) => desc,
+        None => {
+            error!("Unknown prefab {:?}", name);
+            return None;
+        }
+    };
+    let sheet = match &desc.render {
+        Some(RenderDesc::Sprite { source, .. }) => prefabs.sheets.get(source),
+        _ => None,
+    };
+    report_unknown_behaviours(resources, name, desc);
+
+    let mut bodies = resources
+        .get_mut::<DefaultBodySet<f32>>()
+        .unwrap_or_else(|| panic!("{:?}- Bodyset", name));
+    let mut colliders = resources
+        .get_mut::<DefaultColliderSet<f32>>()
+        .unwrap_or_else(|| panic!("{:?}- Colliderset", name));
+
+    // Build the rigid body.
+    let rigid_body = RigidBodyDesc::new()
+        .translation(position)
+        .status(desc.body.status.into())
+        .gravity_enabled(desc.body.gravity_enabled)
+        .kinematic_rotations(desc.body.lock_rotation)
+        .build();
+
+    // Insert the rigid body to the body set.
+    let rigid_body_handle = bodies.insert(rigid_body);
+
+    let mut primitive_size = 1.0;
+    let mut collider_handle = None;
+    if let Some(template) = &desc.collider {
+        let shape_handle = match template.shape {
+            ShapeDesc::Ball { radius } => {
+                primitive_size = radius;
+                ShapeHandle::new(Ball::new(radius))
+            }
+            ShapeDesc::Cuboid { half_extents } => {
+                primitive_size = half_extents.0 * 2.0;
+                ShapeHandle::new(Cuboid::new(Vector2::new(half_extents.0, half_extents.1)))
+            }
+            ShapeDesc::SpriteClip { height } => {
+                let initial = desc.animate.as_ref().map_or(0, |anim| anim.state::<u32>());
+                let ratio = sheet.map_or(1.0, |sheet| sheet.get_clip(initial, 0).width_over_height);
+                ShapeHandle::new(Cuboid::new(Vector2::new(ratio * height, height) / 2.0))
+            }
+        };
+
+        // Build the collider.
+        let mut collider_desc = ColliderDesc::new(shape_handle)
+            .density(template.density)
+            .sensor(template.sensor)
+            .translation(Vector2::new(template.translation.0, template.translation.1));
+        if let Some(layer) = &template.layer {
+            let layers = resources.get::<CollisionLayers>().unwrap();
+            if let Some(groups) = layers.groups(layer) {
+                collider_desc = collider_desc
+                    .collision_groups(groups)
+                    .sensor(template.sensor || layers.is_sensor(layer));
+            }
+        }
+        let collider = collider_desc.build(BodyPartHandle(rigid_body_handle, 0));
+
+        // Insert the collider to the collider set.
+        collider_handle = Some(colliders.insert(collider));
+    }
+
+    if !desc.joints.is_empty() || !desc.forces.is_empty() {
+        let mut physics = resources.get_mut::<Physics>().unwrap();
+        let resolve = |name: &str| Some(rigid_body_handle).filter(|_| name == "self");
+        for joint in &desc.joints {
+            joint.build(&mut physics, position, resolve);
+        }
+        for force in &desc.forces {
+            force.build(&mut physics, position, resolve);
+        }
+    }
+
+    let physics_body = collider_handle.into_iter().fold(
+        PhysicsBody::new(rigid_body_handle),
+        PhysicsBody::with_collider,
+    );
+    let entity = physics_body::spawn(world, resources, physics_body);
+    let mut entry = world.entry(entity).unwrap();
+    match &desc.render {
+        Some(RenderDesc::Primitive { draw_id }) => {
+            let mut primitive = Primitive::new(draw_id);
+            primitive.size = primitive_size;
+            entry.add_component(primitive);
+        }
+        Some(RenderDesc::Sprite { draw_id, .. }) => {
+            if let Some(sheet) = sheet {
+                entry.add_component(Sprite::new(draw_id, sheet.clone()));
+            }
+        }
+        None => {}
+    }
+    if let Some(animate) = &desc.animate {
+        entry.add_component(animate.clone());
+    }
+    if let Some(input) = &desc.input {
+        entry.add_component(input.clone());
+    }
+    if let Some(controller) = &desc.controller {
+        entry.add_component(controller.clone());
+    }
+    if let Some(morph) = &desc.morph {
+        entry.add_component(morph.clone());
+    }
+    if let Some(frame_sounds) = &desc.frame_sounds {
+        entry.add_component(frame_sounds.clone());
+    }
+    if let Some(impact_sound) = &desc.impact_sound {
+        entry.add_component(impact_sound.clone());
+    }
+    if let Some(emitter) = &desc.particles {
+        let mut emitter = emitter.clone();
+        if let ParticleShape::Sprite { source, .. } = &emitter.shape {
+            emitter.sheet = prefabs.sheets.get(source).cloned();
+        }
+        entry.add_component(emitter);
+    }
+    if let Some(health) = &desc.health {
+        let mut health = health.clone();
+        health.reset();
+        health.spawn_point = position;
+        health.spawn_state = desc.animate.as_ref().map(Animate::current);
+        entry.add_component(health);
+    }
+    if let Some(hitbox) = &desc.hitbox {
+        entry.add_component(hitbox.clone());
+    }
+    if let Some(hurtbox) = &desc.hurtbox {
+        entry.add_component(hurtbox.clone());
+    }
+    if let Some(inventory) = &desc.inventory {
+        entry.add_component(inventory.clone());
+    }
+    if let Some(pickup) = &desc.pickup {
+        entry.add_component(pickup.clone());
+    }
+    if let Some(agent) = &desc.agent {
+        let known = resources
+            .get::<BehaviourTrees>()
+            .map_or(false, |trees| trees.get(&agent.tree).is_some());
+        if !known {
+            error!("Unknown behaviour tree {:?}", agent.tree);
+        }
+        let mut agent = agent.clone();
+        agent.blackboard.set_vector("home", position);
+        entry.add_component(agent);
+    }
+    if let Some(npc) = &desc.npc {
+        let known = resources
+            .get::<Dialogues>()
+            .map_or(false, |dialogues| dialogues.get(&npc.dialogue).is_some());
+        if !known {
+            error!("Unknown dialogue {:?}", npc.dialogue);
+        }
+        entry.add_component(npc.clone());
+    }
+
+    Some(entity)
+}
+
+thread_local! {
+    // Spawns requested from scripts, drained once the schedule has run.
+    static SPAWN_REQUESTS: RefCell<Vec<(String, Vector2<f32>)>> = RefCell::new(Vec::new());
+}
+
+pub fn request_spawn(name: &str, position: Vector2<f32>) {
+    SPAWN_REQUESTS.with(|requests| requests.borrow_mut().push((name.to_string(), position)));
+}
+
+pub fn spawn_requested(world: &mut World, resources: &Resources) {
+    let requests = SPAWN_REQUESTS.with(|requests| requests.replace(Vec::new()));
+    for (name, position) in requests {
+        spawn_prefab(world, resources, &name, position);
+    }
+}
+
+// spawn_prefab(name: str, x: float, y: float)
+fn py_spawn_prefab(args: FuncArgs, vm: &VirtualMachine) -> PyResult {
+    let (name, x, y): (String, f64, f64) = args.bind(vm)?;
+    request_spawn(&name, Vector2::new(x as f32, y as f32));
+    Ok(vm.ctx.none())
+}
+
+pub fn inject_script_functions(resources: &mut Resources) {
+    let mut runtime = resources.get_mut::<Interpreter>().unwrap();
+    let mut scope = resources.get_mut::<Scope>().unwrap();
+    script::inject_function(&mut runtime, &mut scope, "spawn_prefab", py_spawn_prefab);
+}
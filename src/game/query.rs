@@ -0,0 +1,301 @@
+use super::components::physics_body::BodyEntities;
+use super::physics::Physics;
+use legion::{Entity, Resources};
+use nalgebra::{Isometry2, Point2, Vector2};
+use ncollide2d::bounding_volume::{BoundingVolume, AABB};
+use ncollide2d::pipeline::CollisionGroups;
+use ncollide2d::query::{self, Ray};
+use ncollide2d::shape::Shape;
+use nphysics2d::object::{
+    BodyStatus, Collider, DefaultBodyHandle, DefaultBodySet, DefaultColliderHandle,
+    DefaultColliderSet,
+};
+use std::cmp::Ordering;
+
+// Narrows which colliders a query considers.
+pub struct QueryFilter {
+    pub groups: CollisionGroups,
+    pub exclude: Vec<Entity>,
+    pub include_sensors: bool,
+    pub dynamic_only: bool,
+}
+
+impl Default for QueryFilter {
+    fn default() -> Self {
+        Self {
+            groups: CollisionGroups::new(),
+            exclude: Vec::new(),
+            include_sensors: false,
+            dynamic_only: false,
+        }
+    }
+}
+
+impl QueryFilter {
+    pub fn excluding(entity: Entity) -> Self {
+        Self {
+            exclude: vec![entity],
+            ..Self::default()
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone)]
+pub struct RayHit {
+    pub entity: Option<Entity>,
+    pub collider: DefaultColliderHandle,
+    pub toi: f32,
+    pub point: Point2<f32>,
+    pub normal: Vector2<f32>,
+}
+
+#[derive(Debug, Copy, Clone)]
+pub struct ShapeHit {
+    pub entity: Option<Entity>,
+    pub collider: DefaultColliderHandle,
+    pub toi: f32,
+    pub point: Point2<f32>,
+    pub normal: Vector2<f32>,
+}
+
+// Read-only queries against the physics world that answer in entities.
+pub struct PhysicsQuery<'a> {
+    physics: &'a Physics,
+    bodies: &'a DefaultBodySet<f32>,
+    colliders: &'a DefaultColliderSet<f32>,
+    entities: &'a BodyEntities,
+}
+
+impl<'a> PhysicsQuery<'a> {
+    pub fn new(
+        physics: &'a Physics,
+        bodies: &'a DefaultBodySet<f32>,
+        colliders: &'a DefaultColliderSet<f32>,
+        entities: &'a BodyEntities,
+    ) -> Self {
+        Self {
+            physics,
+            bodies,
+            colliders,
+            entities,
+        }
+    }
+
+    pub fn entity(&self, collider: DefaultColliderHandle) -> Option<Entity> {
+        self.entities.collider_entity(collider)
+    }
+
+    fn accepts(
+        &self,
+        handle: DefaultColliderHandle,
+        collider: &Collider<f32, DefaultBodyHandle>,
+        filter: &QueryFilter,
+    ) -> bool {
+        if collider.is_sensor() && !filter.include_sensors {
+            return false;
+        }
+        if filter.dynamic_only {
+            let dynamic = self
+                .bodies
+                .rigid_body(collider.body())
+                .map_or(false, |body| body.status() == BodyStatus::Dynamic);
+            if !dynamic {
+                return false;
+            }
+        }
+        self.entity(handle)
+            .map_or(true, |entity| !filter.exclude.contains(&entity))
+    }
+
+    // Every hit along the ray, nearest first.
+    pub fn cast_ray_all(
+        &self,
+        origin: Point2<f32>,
+        dir: Vector2<f32>,
+        max_toi: f32,
+        filter: &QueryFilter,
+    ) -> Vec<RayHit> {
+        let ray = Ray::new(origin, dir);
+        let mut hits: Vec<RayHit> = self
+            .physics
+            .geometrical_world
+            .interferences_with_ray(self.colliders, &ray, max_toi, &filter.groups)
+            .filter(|(handle, collider, _)| self.accepts(*handle, collider, filter))
+            .map(|(handle, _, intersection)| RayHit {
+                entity: self.entity(handle),
+                collider: handle,
+                toi: intersection.toi,
+                point: ray.point_at(intersection.toi),
+                normal: intersection.normal,
+            })
+            .collect();
+        // A zero `dir` can give NaN times of impact.
+        hits.sort_by(|a, b| a.toi.partial_cmp(&b.toi).unwrap_or(Ordering::Equal));
+        hits
+    }
+
+    pub fn cast_ray(
+        &self,
+        origin: Point2<f32>,
+        dir: Vector2<f32>,
+        max_toi: f32,
+        filter: &QueryFilter,
+    ) -> Option<RayHit> {
+        self.cast_ray_all(origin, dir, max_toi, filter)
+            .into_iter()
+            .next()
+    }
+
+    // True if nothing accepted by the filter lies between the two points.
+    // Colliders containing either end, e.g. the looker's and the target's own,
+    // don't block the view.
+    pub fn line_of_sight(&self, from: Point2<f32>, to: Point2<f32>, filter: &QueryFilter) -> bool {
+        if from == to {
+            return true;
+        }
+        let ends: Vec<DefaultColliderHandle> = [from, to]
+            .iter()
+            .flat_map(|point| {
+                self.physics
+                    .geometrical_world
+                    .interferences_with_point(self.colliders, point, &filter.groups)
+                    .map(|(handle, _)| handle)
+            })
+            .collect();
+        self.cast_ray_all(from, to - from, 1.0, filter)
+            .iter()
+            .all(|hit| ends.contains(&hit.collider))
+    }
+
+    pub fn intersect_point(&self, point: Point2<f32>, filter: &QueryFilter) -> Vec<Entity> {
+        self.physics
+            .geometrical_world
+            .interferences_with_point(self.colliders, &point, &filter.groups)
+            .filter(|(handle, collider)| self.accepts(*handle, collider, filter))
+            .filter_map(|(handle, _)| self.entity(handle))
+            .collect()
+    }
+
+    // Entities whose collider bounding boxes overlap the box.
+    pub fn intersect_aabb(
+        &self,
+        mins: Point2<f32>,
+        maxs: Point2<f32>,
+        filter: &QueryFilter,
+    ) -> Vec<Entity> {
+        let aabb = AABB::new(mins, maxs);
+        self.physics
+            .geometrical_world
+            .interferences_with_aabb(self.colliders, &aabb, &filter.groups)
+            .filter(|(handle, collider)| self.accepts(*handle, collider, filter))
+            .filter_map(|(handle, _)| self.entity(handle))
+            .collect()
+    }
+
+    // Sweeps `shape` from `position` along `velocity` and returns the first hit.
+    pub fn cast_shape(
+        &self,
+        shape: &dyn Shape<f32>,
+        position: &Isometry2<f32>,
+        velocity: Vector2<f32>,
+        max_toi: f32,
+        filter: &QueryFilter,
+    ) -> Option<ShapeHit> {
+        let end = Isometry2::from_parts(
+            (position.translation.vector + velocity * max_toi).into(),
+            position.rotation,
+        );
+        let swept = shape.aabb(position).merged(&shape.aabb(&end));
+
+        self.physics
+            .geometrical_world
+            .interferences_with_aabb(self.colliders, &swept, &filter.groups)
+            .filter(|(handle, collider)| self.accepts(*handle, collider, filter))
+            .filter_map(|(handle, collider)| {
+                let toi = query::time_of_impact(
+                    position,
+                    &velocity,
+                    shape,
+                    collider.position(),
+                    &Vector2::zeros(),
+                    collider.shape(),
+                    max_toi,
+                    0.0,
+                )?;
+                Some(ShapeHit {
+                    entity: self.entity(handle),
+                    collider: handle,
+                    toi: toi.toi,
+                    point: collider.position() * toi.witness2,
+                    normal: collider.position() * toi.normal2.into_inner(),
+                })
+            })
+            .min_by(|a, b| a.toi.partial_cmp(&b.toi).unwrap_or(Ordering::Equal))
+    }
+}
+
+// Runs `f` with a query over the physics resources.
+pub fn with_physics_query<R>(resources: &Resources, f: impl FnOnce(&PhysicsQuery) -> R) -> R {
+    let physics = resources.get::<Physics>().unwrap();
+    let bodies = resources.get::<DefaultBodySet<f32>>().unwrap();
+    let colliders = resources.get::<DefaultColliderSet<f32>>().unwrap();
+    let entities = resources.get::<BodyEntities>().unwrap();
+    f(&PhysicsQuery::new(&physics, &bodies, &colliders, &entities))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ncollide2d::shape::{Cuboid, ShapeHandle};
+    use nphysics2d::object::{BodyPartHandle, ColliderDesc};
+
+    // A world with a wall one unit wide standing on x = 0.
+    fn with_wall(f: impl FnOnce(&PhysicsQuery)) {
+        let mut resources = Resources::default();
+        let mut physics = Physics::new(&mut resources);
+        let mut bodies = resources.remove::<DefaultBodySet<f32>>().unwrap();
+        let mut colliders = resources.remove::<DefaultColliderSet<f32>>().unwrap();
+        let wall = ColliderDesc::new(ShapeHandle::new(Cuboid::new(Vector2::new(0.5, 2.0))))
+            .build(BodyPartHandle(physics.ground, 0));
+        colliders.insert(wall);
+        // Puts the collider in the broad phase.
+        physics.step(&mut bodies, &mut colliders);
+        let entities = BodyEntities::default();
+        f(&PhysicsQuery::new(&physics, &bodies, &colliders, &entities));
+    }
+
+    #[test]
+    fn wall_blocks_line_of_sight() {
+        with_wall(|query| {
+            let filter = QueryFilter::default();
+            let (left, right) = (Point2::new(-2.0, 0.0), Point2::new(2.0, 0.0));
+            assert!(!query.line_of_sight(left, right, &filter));
+            assert!(query.line_of_sight(left, Point2::new(-1.0, 0.0), &filter));
+        });
+    }
+
+    #[test]
+    fn line_of_sight_to_the_same_point() {
+        with_wall(|query| {
+            let filter = QueryFilter::default();
+            assert!(query.line_of_sight(Point2::origin(), Point2::origin(), &filter));
+            assert!(query.line_of_sight(Point2::new(-2.0, 0.0), Point2::new(-2.0, 0.0), &filter));
+        });
+    }
+
+    #[test]
+    fn zero_length_casts_dont_panic() {
+        with_wall(|query| {
+            let filter = QueryFilter::default();
+            query.cast_ray_all(Point2::origin(), Vector2::zeros(), 1.0, &filter);
+            let ball = ncollide2d::shape::Ball::new(0.1);
+            query.cast_shape(
+                &ball,
+                &Isometry2::identity(),
+                Vector2::zeros(),
+                1.0,
+                &filter,
+            );
+        });
+    }
+}
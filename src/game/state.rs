@@ -0,0 +1,262 @@
+use super::components::script;
+use legion::{Resources, Schedule, World};
+use rustpython_vm::function::FuncArgs;
+use rustpython_vm::pyobject::PyResult;
+use rustpython_vm::{scope::Scope, Interpreter, VirtualMachine};
+use skulpin::winit::event::ElementState;
+use skulpin::winit::event::VirtualKeyCode as Keycode;
+use std::cell::RefCell;
+
+// What the game is doing, which decides the schedule that runs and where
+// input goes. The top of the stack is the current state.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum GameState {
+    // (Re)loads the level, then pops itself.
+    Loading,
+    Menu,
+    Options,
+    Playing,
+    Paused,
+    Cutscene,
+    // Talking to an NPC; the world waits while the menus drive the dialogue box.
+    Dialogue,
+    GameOver,
+}
+
+impl GameState {
+    // The name scripts use, e.g. "game_over".
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "loading" => Some(Self::Loading),
+            "menu" => Some(Self::Menu),
+            "options" => Some(Self::Options),
+            "playing" => Some(Self::Playing),
+            "paused" => Some(Self::Paused),
+            "cutscene" => Some(Self::Cutscene),
+            "dialogue" => Some(Self::Dialogue),
+            "game_over" => Some(Self::GameOver),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum StateTransition {
+    Push(GameState),
+    Pop,
+    Replace(GameState),
+    // Exits every state and starts over from this one.
+    Reset(GameState),
+}
+
+// Called with the state's world when it enters or leaves the stack.
+pub type StateHook = fn(&mut World, &mut Resources);
+// Gets key events while the state is on top. Returns whether it used them;
+// unused ones go to the menus.
+pub type StateInput = fn(Option<Keycode>, &ElementState, &mut World, &Resources) -> bool;
+
+pub struct StateHandler {
+    pub schedule: Schedule,
+    pub on_enter: Option<StateHook>,
+    pub on_exit: Option<StateHook>,
+    pub input: Option<StateInput>,
+}
+
+impl StateHandler {
+    pub fn new(schedule: Schedule) -> Self {
+        Self {
+            schedule,
+            on_enter: None,
+            on_exit: None,
+            input: None,
+        }
+    }
+
+    pub fn on_enter(mut self, hook: StateHook) -> Self {
+        self.on_enter = Some(hook);
+        self
+    }
+
+    pub fn on_exit(mut self, hook: StateHook) -> Self {
+        self.on_exit = Some(hook);
+        self
+    }
+
+    pub fn input(mut self, input: StateInput) -> Self {
+        self.input = Some(input);
+        self
+    }
+}
+
+#[derive(Debug)]
+pub struct StateStack {
+    states: Vec<GameState>,
+}
+
+impl StateStack {
+    pub fn new(initial: GameState) -> Self {
+        Self {
+            states: vec![initial],
+        }
+    }
+
+    pub fn current(&self) -> GameState {
+        *self.states.last().unwrap()
+    }
+
+    // Applies `transition`, returning the states that left the stack, top
+    // first, and the one that entered. The bottom state is never popped.
+    pub fn apply(&mut self, transition: StateTransition) -> (Vec<GameState>, Option<GameState>) {
+        match transition {
+            StateTransition::Push(state) => {
+                self.states.push(state);
+                (Vec::new(), Some(state))
+            }
+            StateTransition::Pop => {
+                if self.states.len() > 1 {
+                    (self.states.pop().into_iter().collect(), None)
+                } else {
+                    (Vec::new(), None)
+                }
+            }
+            StateTransition::Replace(state) => {
+                let exited = self.states.pop().into_iter().collect();
+                self.states.push(state);
+                (exited, Some(state))
+            }
+            StateTransition::Reset(state) => {
+                let exited = self.states.drain(..).rev().collect();
+                self.states.push(state);
+                (exited, Some(state))
+            }
+        }
+    }
+}
+
+// Transitions requested by systems, applied once the schedule has run.
+#[derive(Debug, Default)]
+pub struct StateRequests {
+    transitions: Vec<StateTransition>,
+}
+
+impl StateRequests {
+    pub fn push(&mut self, state: GameState) {
+        self.transitions.push(StateTransition::Push(state));
+    }
+
+    pub fn pop(&mut self) {
+        self.transitions.push(StateTransition::Pop);
+    }
+
+    pub fn replace(&mut self, state: GameState) {
+        self.transitions.push(StateTransition::Replace(state));
+    }
+
+    pub fn reset(&mut self, state: GameState) {
+        self.transitions.push(StateTransition::Reset(state));
+    }
+
+    // Includes the ones requested from scripts.
+    pub fn drain(&mut self) -> Vec<StateTransition> {
+        let mut transitions = std::mem::take(&mut self.transitions);
+        SCRIPT_REQUESTS.with(|requests| transitions.append(&mut requests.borrow_mut()));
+        transitions
+    }
+}
+
+thread_local! {
+    // Transitions requested from scripts, picked up by `StateRequests::drain`.
+    static SCRIPT_REQUESTS: RefCell<Vec<StateTransition>> = RefCell::new(Vec::new());
+}
+
+fn request_from_script(transition: StateTransition) {
+    SCRIPT_REQUESTS.with(|requests| requests.borrow_mut().push(transition));
+}
+
+fn bind_state(args: FuncArgs, vm: &VirtualMachine) -> PyResult<GameState> {
+    let (name,): (String,) = args.bind(vm)?;
+    GameState::from_name(&name)
+        .ok_or_else(|| vm.new_value_error(format!("unknown game state {:?}", name)))
+}
+
+// push_state(name: str)
+fn py_push_state(args: FuncArgs, vm: &VirtualMachine) -> PyResult {
+    request_from_script(StateTransition::Push(bind_state(args, vm)?));
+    Ok(vm.ctx.none())
+}
+
+// pop_state()
+fn py_pop_state(_args: FuncArgs, vm: &VirtualMachine) -> PyResult {
+    request_from_script(StateTransition::Pop);
+    Ok(vm.ctx.none())
+}
+
+// replace_state(name: str)
+fn py_replace_state(args: FuncArgs, vm: &VirtualMachine) -> PyResult {
+    request_from_script(StateTransition::Replace(bind_state(args, vm)?));
+    Ok(vm.ctx.none())
+}
+
+pub fn inject_script_functions(resources: &mut Resources) {
+    let mut runtime = resources.get_mut::<Interpreter>().unwrap();
+    let mut scope = resources.get_mut::<Scope>().unwrap();
+    script::inject_function(&mut runtime, &mut scope, "push_state", py_push_state);
+    script::inject_function(&mut runtime, &mut scope, "pop_state", py_pop_state);
+    script::inject_function(&mut runtime, &mut scope, "replace_state", py_replace_state);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use GameState::*;
+
+    fn stack(states: &[GameState]) -> StateStack {
+        StateStack {
+            states: states.to_vec(),
+        }
+    }
+
+    #[test]
+    fn push_enters_the_new_state() {
+        let mut states = stack(&[Playing]);
+        assert_eq!(
+            states.apply(StateTransition::Push(Paused)),
+            (vec![], Some(Paused))
+        );
+        assert_eq!(states.states, vec![Playing, Paused]);
+    }
+
+    #[test]
+    fn pop_exits_the_top_state() {
+        let mut states = stack(&[Playing, Paused]);
+        assert_eq!(states.apply(StateTransition::Pop), (vec![Paused], None));
+        assert_eq!(states.current(), Playing);
+    }
+
+    #[test]
+    fn the_last_state_is_never_popped() {
+        let mut states = stack(&[Menu]);
+        assert_eq!(states.apply(StateTransition::Pop), (vec![], None));
+        assert_eq!(states.current(), Menu);
+    }
+
+    #[test]
+    fn replace_swaps_the_top_state() {
+        let mut states = stack(&[Playing, Dialogue]);
+        assert_eq!(
+            states.apply(StateTransition::Replace(Cutscene)),
+            (vec![Dialogue], Some(Cutscene))
+        );
+        assert_eq!(states.states, vec![Playing, Cutscene]);
+    }
+
+    #[test]
+    fn reset_exits_every_state_top_first() {
+        let mut states = stack(&[Menu, Playing, Paused]);
+        assert_eq!(
+            states.apply(StateTransition::Reset(Loading)),
+            (vec![Paused, Playing, Menu], Some(Loading))
+        );
+        assert_eq!(states.states, vec![Loading]);
+    }
+}
@@ -0,0 +1,205 @@
+use super::behaviour::BehaviourRegistry;
+use super::components::animate::Animate;
+use super::components::combat::{CombatContacts, DamageEvents, Health};
+use super::components::controller::CharacterController;
+use super::components::hud::HudValues;
+use super::components::input::KeyInputHandler;
+use super::components::inventory::Inventory;
+use super::components::npc::Npc;
+use super::components::physics_body::BodyEntities;
+use super::dialogue::{self, Conversation};
+use super::items::Items;
+use super::level::Level;
+use super::physics::Physics;
+use super::state::{GameState, StateHandler, StateRequests};
+use super::systems;
+use legion::{IntoQuery, Resources, Schedule, World};
+use skulpin::winit::event::ElementState;
+use skulpin::winit::event::VirtualKeyCode as Keycode;
+use std::collections::HashMap;
+
+pub fn build() -> HashMap<GameState, StateHandler> {
+    let mut handlers = HashMap::new();
+    handlers.insert(
+        GameState::Loading,
+        StateHandler::new(Schedule::builder().build()).on_enter(load_level),
+    );
+    for state in &[
+        GameState::Menu,
+        GameState::Options,
+        GameState::Paused,
+        GameState::GameOver,
+    ] {
+        handlers.insert(*state, StateHandler::new(menu_schedule()));
+    }
+    handlers.insert(
+        GameState::Playing,
+        StateHandler::new(gameplay_schedule()).input(gameplay_input),
+    );
+    handlers.insert(
+        GameState::Cutscene,
+        StateHandler::new(cutscene_schedule()).input(skip_input),
+    );
+    handlers.insert(
+        GameState::Dialogue,
+        StateHandler::new(cutscene_schedule())
+            .input(dialogue_input)
+            .on_exit(close_conversation),
+    );
+    handlers
+}
+
+fn gameplay_schedule() -> Schedule {
+    Schedule::builder()
+        .add_thread_local_fn(systems::tick_agents)
+        .add_system(systems::physics_system())
+        .add_system(systems::detect_hits_system())
+        .add_system(systems::apply_damage_system())
+        .add_system(systems::update_health_system())
+        .add_system(systems::collect_pickups_system())
+        .add_system(systems::track_npcs_system())
+        .add_system(systems::animate_entities_system())
+        .add_system(systems::morph_colliders_system())
+        .add_system(systems::frame_sounds_system())
+        .add_system(systems::impact_sounds_system())
+        .add_system(systems::sensor_bursts_system())
+        .add_system(systems::update_particles_system())
+        .add_system(systems::update_hud_system())
+        .build()
+}
+
+// The world stays still behind menus, only the HUD keeps up.
+fn menu_schedule() -> Schedule {
+    Schedule::builder()
+        .add_system(systems::update_hud_system())
+        .build()
+}
+
+// Animation without physics or player control.
+fn cutscene_schedule() -> Schedule {
+    Schedule::builder()
+        .add_system(systems::animate_entities_system())
+        .add_system(systems::frame_sounds_system())
+        .add_system(systems::update_particles_system())
+        .add_system(systems::update_hud_system())
+        .build()
+}
+
+// Starts the level over from its config, then hands back to the state below.
+fn load_level(world: &mut World, resources: &mut Resources) {
+    world.clear();
+    let physics = Physics::new(resources);
+    resources.insert(physics);
+    resources.insert(BodyEntities::default());
+    resources.insert(HudValues::default());
+    // Collider handles from the old level mean nothing now.
+    resources.insert(CombatContacts::default());
+    resources.insert(DamageEvents::default());
+
+    let level = Level::new();
+    level.init(world, resources);
+    resources.get_mut::<StateRequests>().unwrap().pop();
+}
+
+fn gameplay_input(
+    keycode: Option<Keycode>,
+    key_state: &ElementState,
+    world: &mut World,
+    resources: &Resources,
+) -> bool {
+    if keycode == Some(Keycode::Escape) {
+        if *key_state == ElementState::Pressed {
+            resources
+                .get_mut::<StateRequests>()
+                .unwrap()
+                .push(GameState::Paused);
+        }
+        return true;
+    }
+
+    let talk_to = <&Npc>::query()
+        .iter(world)
+        .find(|npc| npc.interacts(keycode, key_state))
+        .map(|npc| npc.dialogue.clone());
+    if let Some(name) = talk_to {
+        dialogue::start(world, resources, &name);
+        return true;
+    }
+
+    send_to_player(keycode, key_state, world, resources);
+
+    let items = resources.get::<Items>().unwrap();
+    let mut values = resources.get_mut::<HudValues>().unwrap();
+    let mut query = <(&KeyInputHandler, &mut Inventory, Option<&mut Health>)>::query();
+    for (_, inventory, mut health) in query.iter_mut(world) {
+        let item = match inventory.send(keycode, key_state) {
+            Some(item) => item,
+            None => continue,
+        };
+        // Items without effects, e.g. keys, stay in the inventory.
+        let effects = match items.get(&item) {
+            Some(desc) if !desc.effects.is_empty() => &desc.effects,
+            _ => continue,
+        };
+        if inventory.remove(&item, 1) {
+            for effect in effects {
+                effect.apply(health.as_deref_mut(), &mut values);
+            }
+        }
+    }
+    true
+}
+
+// Animation and movement keys of the player characters.
+fn send_to_player(
+    keycode: Option<Keycode>,
+    key_state: &ElementState,
+    world: &mut World,
+    resources: &Resources,
+) {
+    let registry = resources.get::<BehaviourRegistry>().unwrap();
+    // construct a query from a "view tuple"
+    let mut query = <(&KeyInputHandler, &mut Animate)>::query();
+    for (input_handler, animate) in query.iter_mut(world) {
+        if let Some(input) = input_handler.process(keycode, key_state, &registry, resources) {
+            animate.delta(input, &registry, resources);
+        }
+    }
+
+    let mut query = <(&KeyInputHandler, &mut CharacterController)>::query();
+    for (_, controller) in query.iter_mut(world) {
+        controller.send(keycode, key_state);
+    }
+}
+
+// Presses go to the dialogue box. Releases still reach the player, so keys
+// let go mid-conversation don't stay held afterwards.
+fn dialogue_input(
+    keycode: Option<Keycode>,
+    key_state: &ElementState,
+    world: &mut World,
+    resources: &Resources,
+) -> bool {
+    if *key_state == ElementState::Released {
+        send_to_player(keycode, key_state, world, resources);
+    }
+    false
+}
+
+// Also covers leaving the dialogue some other way than finishing it.
+fn close_conversation(_world: &mut World, resources: &mut Resources) {
+    *resources.get_mut::<Conversation>().unwrap() = Conversation::default();
+}
+
+// Any key ends the cutscene.
+fn skip_input(
+    _keycode: Option<Keycode>,
+    key_state: &ElementState,
+    _world: &mut World,
+    resources: &Resources,
+) -> bool {
+    if *key_state == ElementState::Pressed {
+        resources.get_mut::<StateRequests>().unwrap().pop();
+    }
+    true
+}
@@ -0,0 +1,108 @@
+use super::super::ai::tree::Context;
+use super::super::ai::{AiConfig, BehaviourTrees};
+use super::super::behaviour::BehaviourRegistry;
+use super::super::components::ai::{Agent, Target};
+use super::super::components::animate::Animate;
+use super::super::components::combat::{DamageEvents, Health, Hurtbox};
+use super::super::components::controller::CharacterController;
+use super::super::components::physics_body::{BodyEntities, PhysicsBody};
+use super::super::layers::CollisionLayers;
+use super::super::level::TerrainLayer;
+use super::super::physics::Physics;
+use super::super::query::{PhysicsQuery, QueryFilter};
+use super::super::timing::FrameTiming;
+use legion::{Entity, IntoQuery, Resources, World};
+use nphysics2d::object::{DefaultBodySet, DefaultColliderSet};
+
+// Ticks each agent's tree at the configured rate and steers its controller.
+// Runs thread local, as script leaves need the python interpreter.
+pub fn tick_agents(world: &mut World, resources: &mut Resources) {
+    let resources = &*resources;
+    let frame_time = resources.get::<FrameTiming>().unwrap().frame_time;
+    let interval = resources.get::<AiConfig>().unwrap().interval();
+    let trees = resources.get::<BehaviourTrees>().unwrap();
+    let registry = resources.get::<BehaviourRegistry>().unwrap();
+    let bodies = resources.get::<DefaultBodySet<f32>>().unwrap();
+    let mut damage = resources.get_mut::<DamageEvents>().unwrap();
+    let physics = resources.get::<Physics>().unwrap();
+    let colliders = resources.get::<DefaultColliderSet<f32>>().unwrap();
+    let entities = resources.get::<BodyEntities>().unwrap();
+    let physics_query = PhysicsQuery::new(&physics, &bodies, &colliders, &entities);
+    // Only level geometry blocks the view.
+    let terrain = resources.get::<TerrainLayer>().unwrap();
+    let blockers = QueryFilter {
+        groups: resources
+            .get::<CollisionLayers>()
+            .unwrap()
+            .query_groups(&[terrain.0.as_str()]),
+        ..QueryFilter::default()
+    };
+    let position = |physics_body: &PhysicsBody| {
+        bodies
+            .rigid_body(physics_body.body)
+            .map(|body| body.position().translation.vector)
+    };
+
+    let mut query = <(Entity, &Hurtbox, &PhysicsBody, Option<&Health>)>::query();
+    let targets: Vec<Target> = query
+        .iter(world)
+        .filter(|(_, _, _, health)| health.map_or(true, |health| !health.is_dead()))
+        .filter_map(|(entity, hurtbox, physics_body, _)| {
+            Some(Target {
+                entity: *entity,
+                team: hurtbox.team.clone(),
+                position: position(physics_body)?,
+            })
+        })
+        .collect();
+
+    let mut query = <(
+        Entity,
+        &mut Agent,
+        &PhysicsBody,
+        Option<&Health>,
+        Option<&mut CharacterController>,
+        Option<&mut Animate>,
+    )>::query();
+    for (entity, agent, physics_body, health, controller, anim) in query.iter_mut(world) {
+        agent.since_tick += frame_time;
+        if agent.since_tick < interval {
+            continue;
+        }
+        let dt = std::mem::take(&mut agent.since_tick);
+        // The dead are left to their death action.
+        if health.map_or(false, Health::is_dead) {
+            continue;
+        }
+        let (tree, position) = match (trees.get(&agent.tree), position(physics_body)) {
+            (Some(tree), Some(position)) => (tree, position),
+            _ => continue,
+        };
+
+        agent.perceive(
+            *entity,
+            position,
+            health,
+            &targets,
+            &physics_query,
+            &blockers,
+        );
+        let previous = agent.steering;
+        tree.tick(&mut Context {
+            entity: *entity,
+            dt,
+            blackboard: &mut agent.blackboard,
+            memory: &mut agent.memory,
+            steering: &mut agent.steering,
+            damage: &mut damage,
+            resources,
+        });
+
+        if let Some(controller) = controller {
+            controller.drive(agent.steering.axis, agent.steering.jump);
+        }
+        if let (Some(anim), Some(input)) = (anim, agent.move_input(&previous)) {
+            anim.delta(input, &registry, resources);
+        }
+    }
+}
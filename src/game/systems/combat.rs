@@ -0,0 +1,212 @@
+use super::super::components::animate::Animate;
+use super::super::components::combat::{
+    CombatContacts, DamageEvent, DamageEvents, DeathAction, Health, Hitbox, Hurtbox,
+};
+use super::super::components::controller::CharacterController;
+use super::super::components::hud::HudValues;
+use super::super::components::physics_body::{despawn_later, BodyEntities, PhysicsBody};
+use super::super::physics::PhysicsEvents;
+use super::super::state::{GameState, StateRequests};
+use super::super::timing::FrameTiming;
+use legion::storage::Component;
+use legion::systems::CommandBuffer;
+use legion::world::SubWorld;
+use legion::{system, Entity, EntityStore};
+use nalgebra::Vector2;
+use ncollide2d::query::Proximity;
+use nphysics2d::algebra::{Force2, ForceType, Velocity2};
+use nphysics2d::math::Isometry;
+use nphysics2d::object::{Body, DefaultBodyHandle, DefaultBodySet, DefaultColliderHandle};
+
+// The entity owning `collider` and its `T`, if that `T` covers the collider.
+fn attached<T: Component + Clone>(
+    world: &SubWorld,
+    entities: &BodyEntities,
+    collider: DefaultColliderHandle,
+    index: fn(&T) -> Option<usize>,
+) -> Option<(Entity, T, DefaultBodyHandle)> {
+    let entity = entities.collider_entity(collider)?;
+    let entry = world.entry_ref(entity).ok()?;
+    let component = entry.get_component::<T>().ok()?;
+    let physics_body = entry.get_component::<PhysicsBody>().ok()?;
+    let covers =
+        index(component).map_or(true, |i| physics_body.colliders.get(i) == Some(&collider));
+    let attached = if covers {
+        Some((entity, component.clone(), physics_body.body))
+    } else {
+        None
+    };
+    attached
+}
+
+// Tracks overlapping hitboxes and hurtboxes and queues damage for each pair.
+#[system]
+#[read_component(Hitbox)]
+#[read_component(Hurtbox)]
+#[read_component(PhysicsBody)]
+pub fn detect_hits(
+    world: &SubWorld,
+    #[resource] physics_events: &PhysicsEvents,
+    #[resource] bodies: &DefaultBodySet<f32>,
+    #[resource] entities: &BodyEntities,
+    #[resource] contacts: &mut CombatContacts,
+    #[resource] events: &mut DamageEvents,
+) {
+    for prox in &physics_events.proximity {
+        let pairs = [
+            (prox.collider1, prox.collider2),
+            (prox.collider2, prox.collider1),
+        ];
+        for pair in pairs.iter() {
+            if prox.new_status == Proximity::Intersecting {
+                contacts.pairs.insert(*pair);
+            } else {
+                contacts.pairs.remove(pair);
+            }
+        }
+    }
+
+    let mut stale = Vec::new();
+    for (hit, hurt) in &contacts.pairs {
+        let hitbox = attached::<Hitbox>(world, entities, *hit, |hitbox| hitbox.collider);
+        let hurtbox = attached::<Hurtbox>(world, entities, *hurt, |hurtbox| hurtbox.collider);
+        let ((source, hitbox, source_body), (target, hurtbox, target_body)) =
+            match (hitbox, hurtbox) {
+                (Some(hitbox), Some(hurtbox)) => (hitbox, hurtbox),
+                // Not a hitbox and hurtbox pair, or one of them is gone.
+                _ => {
+                    stale.push((*hit, *hurt));
+                    continue;
+                }
+            };
+        if source == target || !hitbox.hits(&hurtbox) {
+            continue;
+        }
+
+        let position = |handle| {
+            bodies
+                .rigid_body(handle)
+                .map(|body| body.position().translation.vector)
+        };
+        let side = match (position(source_body), position(target_body)) {
+            (Some(from), Some(to)) if to.x >= from.x => 1.0,
+            (Some(_), Some(_)) => -1.0,
+            _ => 0.0,
+        };
+        events.push(DamageEvent {
+            target,
+            source: Some(source),
+            amount: hitbox.damage,
+            knockback: Vector2::new(side, 0.5).normalize() * hitbox.knockback,
+        });
+    }
+    for pair in &stale {
+        contacts.pairs.remove(pair);
+    }
+}
+
+#[system]
+#[write_component(Health)]
+#[write_component(CharacterController)]
+#[write_component(Animate)]
+#[read_component(PhysicsBody)]
+pub fn apply_damage(
+    world: &mut SubWorld,
+    #[resource] events: &mut DamageEvents,
+    #[resource] bodies: &mut DefaultBodySet<f32>,
+) {
+    for event in events.drain() {
+        let mut entry = match world.entry_mut(event.target) {
+            Ok(entry) => entry,
+            Err(_) => continue,
+        };
+        let state = entry.get_component::<Animate>().ok().map(Animate::current);
+        let (stun, death_state) = match entry.get_component_mut::<Health>() {
+            Ok(health) => {
+                if !health.damage(event.amount) {
+                    continue;
+                }
+                let death_state = state
+                    .filter(|_| health.is_dead())
+                    .and_then(|state| health.death_states.get(&state).copied());
+                let stun = if health.is_dead() {
+                    std::f32::INFINITY
+                } else {
+                    health.stun
+                };
+                (stun, death_state)
+            }
+            Err(_) => continue,
+        };
+
+        if let Ok(controller) = entry.get_component_mut::<CharacterController>() {
+            controller.stun(stun);
+        }
+        if let (Ok(anim), Some(death_state)) = (entry.get_component_mut::<Animate>(), death_state) {
+            anim.play(death_state);
+        }
+        if event.knockback == Vector2::zeros() {
+            continue;
+        }
+        if let Ok(physics_body) = entry.get_component::<PhysicsBody>() {
+            if let Some(body) = bodies.rigid_body_mut(physics_body.body) {
+                let force = Force2::linear(event.knockback);
+                body.apply_force(0, &force, ForceType::VelocityChange, true);
+            }
+        }
+    }
+}
+
+// Counts down invulnerability and runs death actions once their delay is up.
+#[system(for_each)]
+#[allow(clippy::too_many_arguments)]
+pub fn update_health(
+    entity: &Entity,
+    health: &mut Health,
+    physics_body: &PhysicsBody,
+    anim: Option<&mut Animate>,
+    controller: Option<&mut CharacterController>,
+    commands: &mut CommandBuffer,
+    #[resource] bodies: &mut DefaultBodySet<f32>,
+    #[resource] values: &mut HudValues,
+    #[resource] requests: &mut StateRequests,
+    #[resource] timing: &FrameTiming,
+) {
+    let dt = timing.frame_time;
+    health.invulnerable_for = (health.invulnerable_for - dt).max(0.0);
+    if health.show_on_hud {
+        values.set("health", health.current.max(0.0));
+        values.set("max_health", health.max);
+    }
+
+    let dead_for = match health.dead_for.as_mut() {
+        Some(dead_for) => {
+            *dead_for += dt;
+            *dead_for
+        }
+        None => return,
+    };
+    if dead_for < health.on_death.delay() {
+        return;
+    }
+    // Only runs once; the entity stays dead until respawned.
+    health.dead_for = None;
+    match health.on_death {
+        DeathAction::Despawn { .. } => despawn_later(commands, *entity),
+        DeathAction::Respawn { .. } => {
+            if let Some(body) = bodies.rigid_body_mut(physics_body.body) {
+                let spawn_point = health.spawn_point;
+                body.set_position(Isometry::translation(spawn_point.x, spawn_point.y));
+                body.set_velocity(Velocity2::zero());
+            }
+            health.reset();
+            if let Some(controller) = controller {
+                controller.recover();
+            }
+            if let (Some(anim), Some(state)) = (anim, health.spawn_state) {
+                anim.play(state);
+            }
+        }
+        DeathAction::GameOver { .. } => requests.push(GameState::GameOver),
+    }
+}
@@ -0,0 +1,97 @@
+use super::super::components::combat::Health;
+use super::super::components::hud::HudValues;
+use super::super::components::inventory::{Inventory, Pickup};
+use super::super::components::physics_body::{despawn_later, BodyEntities};
+use super::super::items::Items;
+use super::super::physics::PhysicsEvents;
+use super::super::timing::FrameTiming;
+use legion::systems::CommandBuffer;
+use legion::world::SubWorld;
+use legion::{system, EntityStore};
+use log::error;
+use ncollide2d::query::Proximity;
+use std::collections::HashSet;
+
+// Moves pickups into the inventories that touch them. Pickups that don't fit
+// stay, holding what's left.
+#[system]
+#[write_component(Pickup)]
+#[write_component(Inventory)]
+#[write_component(Health)]
+pub fn collect_pickups(
+    world: &mut SubWorld,
+    commands: &mut CommandBuffer,
+    #[resource] events: &PhysicsEvents,
+    #[resource] entities: &BodyEntities,
+    #[resource] items: &Items,
+    #[resource] values: &mut HudValues,
+    #[resource] timing: &mut FrameTiming,
+) {
+    let mut collected = HashSet::new();
+    for prox in &events.proximity {
+        if prox.new_status != Proximity::Intersecting {
+            continue;
+        }
+        let pairs = [
+            (prox.collider1, prox.collider2),
+            (prox.collider2, prox.collider1),
+        ];
+        for (pickup_collider, collector_collider) in pairs.iter() {
+            let (pickup_entity, collector) = match (
+                entities.collider_entity(*pickup_collider),
+                entities.collider_entity(*collector_collider),
+            ) {
+                (Some(pickup), Some(collector)) => (pickup, collector),
+                _ => continue,
+            };
+            if collected.contains(&pickup_entity) {
+                continue;
+            }
+            let pickup = match world.entry_ref(pickup_entity) {
+                Ok(entry) => match entry.get_component::<Pickup>() {
+                    Ok(pickup) => pickup.clone(),
+                    Err(_) => continue,
+                },
+                Err(_) => continue,
+            };
+            let desc = match items.get(&pickup.item) {
+                Some(desc) => desc,
+                None => {
+                    error!("Unknown item {:?}", pickup.item);
+                    continue;
+                }
+            };
+
+            let mut entry = match world.entry_mut(collector) {
+                Ok(entry) => entry,
+                Err(_) => continue,
+            };
+            let left = if desc.instant {
+                if entry.get_component::<Inventory>().is_err() {
+                    continue;
+                }
+                for _ in 0..pickup.count {
+                    for effect in &desc.effects {
+                        effect.apply(entry.get_component_mut::<Health>().ok(), values);
+                    }
+                }
+                0
+            } else {
+                match entry.get_component_mut::<Inventory>() {
+                    Ok(inventory) => inventory.add(&pickup.item, pickup.count, desc.stack_size),
+                    Err(_) => continue,
+                }
+            };
+            timing.request_redraw();
+
+            if left == 0 {
+                collected.insert(pickup_entity);
+                despawn_later(commands, pickup_entity);
+            } else if let Ok(mut entry) = world.entry_mut(pickup_entity) {
+                if let Ok(pickup) = entry.get_component_mut::<Pickup>() {
+                    pickup.count = left;
+                }
+            }
+        }
+    }
+}
@@ -1,69 +1,237 @@
+use super::audio::AudioEvents;
+use super::behaviour::BehaviourRegistry;
 use super::components::animate::Animate;
-use super::Physics;
-use legion::system;
+use super::components::controller::CharacterController;
+use super::components::hud::{HudValues, HudWidget};
+use super::components::morph::ColliderMorph;
+use super::components::particles::ParticleEmitter;
+use super::components::physics_body::{BodyEntities, PhysicsBody};
+use super::components::sound::{FrameSounds, ImpactSound};
+use super::components::sprite::{Sprite, DEFAULT_FRAME_DURATION};
+use super::physics::{Physics, PhysicsEvents};
+use super::timing::FrameTiming;
+use legion::world::SubWorld;
+use legion::{system, EntityStore, IntoQuery};
+use nalgebra::Point2;
+use ncollide2d::pipeline::ContactEvent;
 use ncollide2d::query::Proximity;
-use nphysics2d::object::{DefaultBodyHandle, DefaultBodySet, DefaultColliderSet};
-use std::time::{Duration, Instant};
+use nphysics2d::object::{Body, DefaultBodySet, DefaultColliderSet};
 
-pub struct DeltaTime {
-    instant: Instant,
-}
+mod ai;
+pub use ai::*;
+mod combat;
+pub use combat::*;
+mod items;
+pub use items::*;
+mod npcs;
+pub use npcs::*;
 
-impl Default for DeltaTime {
-    fn default() -> Self {
-        Self {
-            instant: Instant::now(),
-        }
-    }
-}
+// Frames shorter than this are held for this long, so a zero duration can't stall the loop.
+const MIN_FRAME_DURATION: f32 = 0.001;
 
 #[system(for_each)]
 pub fn animate_entities(
     anim: &mut Animate,
-    body_handle: &DefaultBodyHandle,
-    #[resource] time: &mut DeltaTime,
+    physics_body: &PhysicsBody,
+    sprite: Option<&Sprite>,
     #[resource] bodies: &mut DefaultBodySet<f32>,
+    #[resource] registry: &BehaviourRegistry,
+    #[resource] timing: &mut FrameTiming,
 ) {
-    let refresh_rate = 7.5;
-    let frame_length = Duration::from_secs_f32(1.0 / refresh_rate);
+    let duration = |anim: &Animate| {
+        sprite
+            .map_or(DEFAULT_FRAME_DURATION, |sprite| {
+                sprite.source.frame_duration(anim.current(), anim.ticks)
+            })
+            .max(MIN_FRAME_DURATION)
+    };
 
-    if time.instant.elapsed() >= frame_length {
-        anim.animate(body_handle, bodies);
-        time.instant = Instant::now();
+    anim.elapsed += timing.frame_time;
+    let mut advanced = false;
+    while anim.elapsed >= duration(anim) {
+        anim.elapsed -= duration(anim);
+        anim.animate(&physics_body.body, bodies, registry);
+        if let Some(sprite) = sprite {
+            let frames = sprite.source.frame_count(anim.current());
+            if frames > 0 {
+                anim.ticks %= frames;
+            }
+        }
+        advanced = true;
+    }
+    if advanced {
+        timing.request_redraw();
     }
 }
 
 #[system]
+#[read_component(PhysicsBody)]
+#[write_component(CharacterController)]
 pub fn physics(
+    world: &mut SubWorld,
     #[resource] bodies: &mut DefaultBodySet<f32>,
     #[resource] colliders: &mut DefaultColliderSet<f32>,
     #[resource] physics: &mut Physics,
+    #[resource] events: &mut PhysicsEvents,
+    #[resource] timing: &mut FrameTiming,
 ) {
-    for _ in 1..physics.nsteps {
+    let dt = physics.timestep();
+    let mut query = <(&PhysicsBody, &mut CharacterController)>::query();
+    events.clear();
+    for _ in 0..physics.steps_due(timing.frame_time) {
+        for (physics_body, controller) in query.iter_mut(world) {
+            controller.step(physics_body, dt, physics, bodies, colliders);
+        }
         physics.step(bodies, colliders);
+        events.record(physics);
+    }
+    // Sleeping bodies don't move, so there is nothing new to draw.
+    if bodies.iter().any(|(_, body)| body.is_active()) {
+        timing.request_redraw();
     }
+}
 
-    for prox in physics.geometrical_world.proximity_events() {
-        let c1 = colliders.get(prox.collider1).unwrap();
-        let c2 = colliders.get(prox.collider2).unwrap();
-        let body1 = c1.body();
-        let body2 = c2.body();
-        println!("oof");
-        println!("{:?}", prox.new_status);
+#[system(for_each)]
+pub fn morph_colliders(
+    anim: &Animate,
+    sprite: &Sprite,
+    morph: &mut ColliderMorph,
+    physics_body: &PhysicsBody,
+    #[resource] colliders: &mut DefaultColliderSet<f32>,
+) {
+    let frame = (anim.current(), anim.ticks);
+    if morph.shaped_for == Some(frame) {
+        return;
+    }
+    morph.shaped_for = Some(frame);
 
-        if prox.new_status == Proximity::Intersecting {
-            println!("oof");
+    let clip = sprite.source.get_clip(frame.0, frame.1);
+    for (i, handle) in physics_body.colliders.iter().enumerate() {
+        // Frames without the slice keep the previous shape.
+        let shape = match morph.shape(i, &sprite.source, clip) {
+            Some(shape) => shape,
+            None => continue,
+        };
+        if let Some(collider) = colliders.get_mut(*handle) {
+            collider.set_shape(shape);
         }
     }
 }
 
-// #[system(for_each)]
-// pub fn morph_colliders(
-//     anim: &Animate,
-//     sprite: &Sprite,
-//     morpher: &Morpher,
-//     body_handle: &DefaultBodyHandle,
-//     #[resource] colliders: &mut DefaultColliderSet<f32>,
-// ) {
+#[system(for_each)]
+pub fn frame_sounds(
+    anim: &Animate,
+    sounds: &mut FrameSounds,
+    #[resource] events: &mut AudioEvents,
+) {
+    let frame = (anim.current(), anim.ticks);
+    if sounds.last_frame == Some(frame) {
+        return;
+    }
+    sounds.last_frame = Some(frame);
+    for sound in sounds.sounds_for(frame.0, frame.1) {
+        events.play(sound);
+    }
+}
 
-// }
+#[system]
+#[read_component(ImpactSound)]
+#[read_component(PhysicsBody)]
+pub fn impact_sounds(
+    world: &SubWorld,
+    #[resource] physics_events: &PhysicsEvents,
+    #[resource] bodies: &DefaultBodySet<f32>,
+    #[resource] entities: &BodyEntities,
+    #[resource] events: &mut AudioEvents,
+) {
+    for event in &physics_events.contacts {
+        let (collider1, collider2) = match event {
+            ContactEvent::Started(collider1, collider2) => (*collider1, *collider2),
+            ContactEvent::Stopped(_, _) => continue,
+        };
+        for collider in [collider1, collider2].iter() {
+            let entity = match entities.collider_entity(*collider) {
+                Some(entity) => entity,
+                None => continue,
+            };
+            let entry = match world.entry_ref(entity) {
+                Ok(entry) => entry,
+                Err(_) => continue,
+            };
+            let (impact, physics_body) = match (
+                entry.get_component::<ImpactSound>(),
+                entry.get_component::<PhysicsBody>(),
+            ) {
+                (Ok(impact), Ok(physics_body)) => (impact, physics_body),
+                _ => continue,
+            };
+            let speed = bodies
+                .rigid_body(physics_body.body)
+                .map_or(0.0, |body| body.velocity().linear.norm());
+            if speed >= impact.min_speed {
+                events.play(&impact.sound);
+            }
+        }
+    }
+}
+
+#[system]
+#[write_component(ParticleEmitter)]
+pub fn sensor_bursts(
+    world: &mut SubWorld,
+    #[resource] events: &PhysicsEvents,
+    #[resource] colliders: &DefaultColliderSet<f32>,
+    #[resource] entities: &BodyEntities,
+) {
+    for prox in &events.proximity {
+        if prox.new_status != Proximity::Intersecting {
+            continue;
+        }
+        for handle in [prox.collider1, prox.collider2].iter() {
+            let is_sensor = colliders
+                .get(*handle)
+                .map_or(false, |collider| collider.is_sensor());
+            let entity = match entities.collider_entity(*handle) {
+                Some(entity) if is_sensor => entity,
+                _ => continue,
+            };
+            if let Ok(mut entry) = world.entry_mut(entity) {
+                if let Ok(emitter) = entry.get_component_mut::<ParticleEmitter>() {
+                    emitter.sensor_touched();
+                }
+            }
+        }
+    }
+}
+
+#[system(for_each)]
+pub fn update_particles(
+    emitter: &mut ParticleEmitter,
+    physics_body: &PhysicsBody,
+    anim: Option<&Animate>,
+    #[resource] bodies: &DefaultBodySet<f32>,
+    #[resource] timing: &mut FrameTiming,
+) {
+    if let Some(anim) = anim {
+        emitter.state_changed(anim.current());
+    }
+    let origin = match bodies.rigid_body(physics_body.body) {
+        Some(body) => Point2::from(body.position().translation.vector),
+        None => return,
+    };
+    emitter.step(origin, timing.frame_time);
+    if !emitter.particles.is_empty() {
+        timing.request_redraw();
+    }
+}
+
+#[system(for_each)]
+pub fn update_hud(
+    widget: &mut HudWidget,
+    #[resource] values: &HudValues,
+    #[resource] timing: &mut FrameTiming,
+) {
+    if widget.refresh(values, timing.fps) {
+        timing.request_redraw();
+    }
+}
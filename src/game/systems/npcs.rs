@@ -0,0 +1,47 @@
+use super::super::components::input::KeyInputHandler;
+use super::super::components::npc::Npc;
+use super::super::components::physics_body::BodyEntities;
+use super::super::physics::PhysicsEvents;
+use super::super::timing::FrameTiming;
+use legion::world::SubWorld;
+use legion::{system, EntityStore};
+use ncollide2d::query::Proximity;
+
+// Keeps track of which NPCs the player is close enough to talk to.
+#[system]
+#[write_component(Npc)]
+#[read_component(KeyInputHandler)]
+pub fn track_npcs(
+    world: &mut SubWorld,
+    #[resource] events: &PhysicsEvents,
+    #[resource] entities: &BodyEntities,
+    #[resource] timing: &mut FrameTiming,
+) {
+    for prox in &events.proximity {
+        let pairs = [
+            (prox.collider1, prox.collider2),
+            (prox.collider2, prox.collider1),
+        ];
+        for (npc_collider, player_collider) in pairs.iter() {
+            let (npc, player) = match (
+                entities.collider_entity(*npc_collider),
+                entities.collider_entity(*player_collider),
+            ) {
+                (Some(npc), Some(player)) => (npc, player),
+                _ => continue,
+            };
+            let is_player = world.entry_ref(player).map_or(false, |entry| {
+                entry.get_component::<KeyInputHandler>().is_ok()
+            });
+            if !is_player {
+                continue;
+            }
+            if let Ok(mut entry) = world.entry_mut(npc) {
+                if let Ok(npc) = entry.get_component_mut::<Npc>() {
+                    npc.in_range = prox.new_status == Proximity::Intersecting;
+                    timing.request_redraw();
+                }
+            }
+        }
+    }
+}
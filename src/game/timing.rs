@@ -0,0 +1,143 @@
+use log::error;
+use ron::de::from_reader;
+use serde::Deserialize;
+use std::fs::File;
+use std::time::{Duration, Instant};
+
+// Frames are averaged over this many seconds for the reported rate.
+const FPS_SMOOTHING: f32 = 0.5;
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct FrameConfig {
+    // Rate the game is updated at while the window is focused.
+    pub target_fps: f32,
+    // Rate used while unfocused, so the plugin stays cheap in the background.
+    pub background_fps: f32,
+    // Leave draw pacing to the presenter instead of capping it at the update rate.
+    pub vsync: bool,
+    // Longest frame time handed to systems, so a stall (e.g. a dragged window)
+    // doesn't make everything jump ahead at once.
+    pub max_frame_time: f32,
+}
+
+impl Default for FrameConfig {
+    fn default() -> Self {
+        Self {
+            target_fps: 60.0,
+            background_fps: 10.0,
+            vsync: true,
+            max_frame_time: 0.25,
+        }
+    }
+}
+
+impl FrameConfig {
+    pub fn load(filename: &str) -> Self {
+        let f = File::open(crate::utils::from_out_dir(filename)).expect("Failed opening file");
+        match from_reader(f) {
+            Ok(x) => x,
+            Err(e) => {
+                error!("Failed to load frame config: {}", e);
+                Self::default()
+            }
+        }
+    }
+}
+
+// Frame pacing state and statistics, readable by systems as a resource.
+pub struct FrameTiming {
+    pub config: FrameConfig,
+    pub focused: bool,
+    // Seconds between the last two updates.
+    pub frame_time: f32,
+    pub fps: f32,
+    pub frame_count: u64,
+    pub update_duration: Duration,
+    pub draw_duration: Duration,
+    pub draw_count: u64,
+    last_update: Option<Instant>,
+    last_draw: Option<Instant>,
+    dirty: bool,
+}
+
+impl FrameTiming {
+    pub fn new(config: FrameConfig) -> Self {
+        Self {
+            config,
+            focused: true,
+            frame_time: 0.0,
+            fps: 0.0,
+            frame_count: 0,
+            update_duration: Duration::default(),
+            draw_duration: Duration::default(),
+            draw_count: 0,
+            last_update: None,
+            last_draw: None,
+            // The first frame always needs drawing.
+            dirty: true,
+        }
+    }
+
+    pub fn frame_interval(&self) -> Duration {
+        let fps = if self.focused {
+            self.config.target_fps
+        } else {
+            self.config.background_fps
+        };
+        Duration::from_secs_f32(1.0 / fps.max(1.0))
+    }
+
+    // True once a full frame interval has passed since the last update.
+    pub fn update_due(&self) -> bool {
+        self.last_update
+            .map_or(true, |last| last.elapsed() >= self.frame_interval())
+    }
+
+    pub fn begin_update(&mut self) -> Instant {
+        let now = Instant::now();
+        if let Some(last) = self.last_update {
+            let elapsed = (now - last).as_secs_f32();
+            self.frame_time = elapsed.min(self.config.max_frame_time);
+            if elapsed > 0.0 {
+                // Exponential moving average so the value is readable on screen.
+                let alpha = (elapsed / FPS_SMOOTHING).min(1.0);
+                self.fps += (1.0 / elapsed - self.fps) * alpha;
+            }
+        }
+        self.last_update = Some(now);
+        self.frame_count += 1;
+        now
+    }
+
+    pub fn end_update(&mut self, started: Instant) {
+        self.update_duration = started.elapsed();
+    }
+
+    // Marks the scene as changed so the next frame is drawn.
+    pub fn request_redraw(&mut self) {
+        self.dirty = true;
+    }
+
+    pub fn should_draw(&self) -> bool {
+        if !self.dirty {
+            return false;
+        }
+        self.config.vsync
+            || self
+                .last_draw
+                .map_or(true, |last| last.elapsed() >= self.frame_interval())
+    }
+
+    pub fn begin_draw(&mut self) -> Instant {
+        let now = Instant::now();
+        self.last_draw = Some(now);
+        self.dirty = false;
+        now
+    }
+
+    pub fn end_draw(&mut self, started: Instant) {
+        self.draw_duration = started.elapsed();
+        self.draw_count += 1;
+    }
+}
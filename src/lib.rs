@@ -2,18 +2,25 @@ use log::error;
 use neovide_plugin::*;
 
 use skulpin::winit::event::{
-    ElementState, ModifiersState, MouseButton, MouseScrollDelta, WindowEvent,
+    ElementState, ModifiersState, MouseButton, MouseScrollDelta, VirtualKeyCode, WindowEvent,
 };
 use skulpin::winit::event_loop::{ControlFlow, EventLoopProxy};
 use skulpin::winit::window::Window;
-use skulpin::{winit::dpi::LogicalSize, Renderer as SkulpinRenderer, WinitWindow};
+use skulpin::{winit::dpi::LogicalSize, Renderer as SkulpinRenderer, RendererBuilder, WinitWindow};
+use std::time::{SystemTime, UNIX_EPOCH};
 
 mod renderer;
 use renderer::*;
 pub mod game;
+use game::audio::Audio;
+use game::state::{GameState, StateTransition};
+use game::timing::FrameTiming;
 use game::*;
 // pub mod deno;
 // pub mod python;
+mod ui;
+use ui::menus::{self, MenuAction};
+use ui::Ui;
 mod utils;
 
 #[derive(Default)]
@@ -22,6 +29,7 @@ pub struct Fork {
     saved_handle: Option<Box<dyn WindowHandle>>,
     game: Game,
     renderer: Renderer,
+    ui: Ui,
     modifiers: ModifiersState,
 }
 
@@ -29,6 +37,55 @@ impl Fork {
     pub fn save_handle(&mut self, handle: Box<dyn WindowHandle>) {
         self.saved_handle = Some(handle);
     }
+
+    // For the host to build the skulpin renderer with, so the frame config's
+    // `vsync` decides how frames are presented.
+    pub fn renderer_builder(&self) -> RendererBuilder {
+        let vsync = self
+            .game
+            .resources
+            .get::<FrameTiming>()
+            .map_or(true, |timing| timing.config.vsync);
+        RendererBuilder::new().vsync_enabled(vsync)
+    }
+
+    // Returns false when the game should close.
+    fn apply_menu_action(&mut self, action: MenuAction) -> bool {
+        match action {
+            MenuAction::Play => self
+                .game
+                .transition(StateTransition::Replace(GameState::Playing)),
+            MenuAction::Resume | MenuAction::CloseOptions => {
+                self.game.transition(StateTransition::Pop)
+            }
+            MenuAction::OpenOptions => self
+                .game
+                .transition(StateTransition::Push(GameState::Options)),
+            // Both start the level over underneath.
+            MenuAction::Retry => {
+                self.game
+                    .transition(StateTransition::Reset(GameState::Playing));
+                self.game
+                    .transition(StateTransition::Push(GameState::Loading));
+            }
+            MenuAction::QuitToMenu => {
+                self.game
+                    .transition(StateTransition::Reset(GameState::Menu));
+                self.game
+                    .transition(StateTransition::Push(GameState::Loading));
+            }
+            MenuAction::Exit => return false,
+            MenuAction::SetBusVolume(bus, volume) => {
+                if let Some(mut audio) = self.game.resources.get_mut::<Audio>() {
+                    audio.set_bus_volume(&bus, volume);
+                }
+            }
+            MenuAction::SetViewport(policy) => self.renderer.viewport.policy = policy,
+            MenuAction::AdvanceDialogue(choice) => self.game.advance_dialogue(choice),
+        }
+        self.game.request_redraw();
+        true
+    }
 }
 
 impl NeovideEventProcessor for Fork {
@@ -43,12 +100,41 @@ impl NeovideEventProcessor for Fork {
             }
             // WindowEvent::DroppedFile(path) => {}
             WindowEvent::KeyboardInput { input, .. } => {
-                self.game.send(input.virtual_keycode, input.state);
+                match (input.virtual_keycode, input.state) {
+                    (Some(VirtualKeyCode::F3), ElementState::Pressed) => {
+                        self.renderer.debug.toggle();
+                        self.game.request_redraw();
+                    }
+                    (Some(VirtualKeyCode::F3), _) => {}
+                    (Some(VirtualKeyCode::F12), ElementState::Pressed) => {
+                        let secs = SystemTime::now()
+                            .duration_since(UNIX_EPOCH)
+                            .map_or(0, |elapsed| elapsed.as_secs());
+                        let path = format!("screenshot-{}.png", secs);
+                        self.renderer.save_screenshot(&self.game, &path);
+                    }
+                    (Some(VirtualKeyCode::F12), _) => {}
+                    _ => {
+                        let used = self.game.send(input.virtual_keycode, input.state);
+                        // What the current state doesn't use drives the menus.
+                        if let (false, Some(keycode), ElementState::Pressed) =
+                            (used, input.virtual_keycode, input.state)
+                        {
+                            self.ui.key_pressed(keycode);
+                        }
+                    }
+                }
             }
             WindowEvent::ModifiersChanged(m) => {
                 self.modifiers.set(m, true);
             }
-            // WindowEvent::CursorMoved { position, .. } => {}
+            WindowEvent::CursorMoved { position, .. } => {
+                let position = position.to_logical::<f32>(self.renderer.scale_factor);
+                self.ui.mouse_moved((position.x, position.y));
+                if self.game.states.current() != GameState::Playing {
+                    self.game.request_redraw();
+                }
+            }
             WindowEvent::MouseWheel {
                 delta: MouseScrollDelta::LineDelta(_x, _y),
                 ..
@@ -58,16 +144,30 @@ impl NeovideEventProcessor for Fork {
                 state,
                 ..
             } => {
-                if state == ElementState::Pressed {
-                } else {
-                    unimplemented!();
+                self.ui.mouse_button(state == ElementState::Pressed);
+                self.game.request_redraw();
+            }
+            WindowEvent::Focused(focused) => {
+                if let Some(mut timing) = self.game.resources.get_mut::<FrameTiming>() {
+                    timing.focused = focused;
+                    timing.request_redraw();
                 }
             }
-            // WindowEvent::Focused(focus) => {}
-            // WindowEvent::Resized(size) => {
-            //     let scale_factor = self.window.as_ref().unwrap().scale_factor();
-            //     self.renderer.logical_size = size.to_logical(scale_factor);
-            // }
+            WindowEvent::Resized(size) => {
+                let scale_factor = self
+                    .window
+                    .as_ref()
+                    .map_or(self.renderer.scale_factor, |window| window.scale_factor());
+                self.renderer.resize(size, scale_factor);
+                self.game.request_redraw();
+            }
+            WindowEvent::ScaleFactorChanged {
+                scale_factor,
+                new_inner_size,
+            } => {
+                self.renderer.resize(*new_inner_size, scale_factor);
+                self.game.request_redraw();
+            }
             _ => {}
         }
         None
@@ -94,33 +194,55 @@ impl WindowHandle for Fork {
         self.renderer.logical_size
     }
 
+    // False when no update was due, so the host can idle.
     fn update(&mut self) -> bool {
-        self.game
-            .schedule
-            .execute(&mut self.game.world, &mut self.game.resources);
-        true
+        self.game.update()
     }
 
     fn should_draw(&self) -> bool {
-        true
+        self.game
+            .resources
+            .get::<FrameTiming>()
+            .map_or(true, |timing| timing.should_draw())
     }
 
     fn draw(&mut self, skulpin_renderer: &mut SkulpinRenderer) -> bool {
         if self.should_draw() {
+            let started = self
+                .game
+                .resources
+                .get_mut::<FrameTiming>()
+                .map(|mut timing| timing.begin_draw());
             let renderer = &mut self.renderer;
+            let ui = &mut self.ui;
             let game = &self.game;
+            let mut actions = Vec::new();
             let window = WinitWindow::new(&self.window.as_ref().unwrap());
             let error = skulpin_renderer
                 .draw(&window, |canvas, coordinate_system_helper| {
-                    for _ in 0..3 {
-                        renderer.draw(canvas, &coordinate_system_helper, game);
-                    }
+                    renderer.draw(canvas, &coordinate_system_helper, game);
+                    let screen = (
+                        renderer.logical_size.width as f32,
+                        renderer.logical_size.height as f32,
+                    );
+                    let policy = renderer.viewport.policy;
+                    actions = menus::draw(ui, canvas, &renderer.fonts, screen, game, policy);
                 })
                 .is_err();
             if error {
                 error!("Render failed. Closing");
                 return false;
             }
+            if let (Some(mut timing), Some(started)) =
+                (self.game.resources.get_mut::<FrameTiming>(), started)
+            {
+                timing.end_draw(started);
+            }
+            for action in actions {
+                if !self.apply_menu_action(action) {
+                    return false;
+                }
+            }
         }
         true
     }
@@ -0,0 +1,254 @@
+use super::super::game::navigation::{LinkKind, NavGraph};
+use super::super::game::physics::Physics;
+use nalgebra::{Isometry2, Point2};
+use ncollide2d::query::Proximity;
+use ncollide2d::shape::{Ball, Capsule, Compound, ConvexPolygon, Cuboid, Polyline, Segment, Shape};
+use nphysics2d::object::{Body, BodySet, DefaultBodyHandle, DefaultBodySet, DefaultColliderSet};
+use skulpin::skia_safe::{paint, Canvas, Color, Paint, Path, Point, Rect};
+use std::collections::HashSet;
+
+const STROKE_WIDTH: f32 = 0.02;
+const CONTACT_NORMAL_LENGTH: f32 = 0.2;
+const VELOCITY_SCALE: f32 = 0.25;
+
+// Which parts of the physics world the debug overlay shows.
+pub struct DebugDraw {
+    pub enabled: bool,
+    pub shapes: bool,
+    pub contacts: bool,
+    pub aabbs: bool,
+    pub joints: bool,
+    pub velocities: bool,
+    pub navigation: bool,
+}
+
+impl Default for DebugDraw {
+    fn default() -> Self {
+        Self {
+            enabled: cfg!(feature = "bounds"),
+            shapes: true,
+            contacts: true,
+            aabbs: false,
+            joints: true,
+            velocities: true,
+            navigation: true,
+        }
+    }
+}
+
+impl DebugDraw {
+    pub fn toggle(&mut self) {
+        self.enabled = !self.enabled;
+    }
+}
+
+fn stroke(color: Color) -> Paint {
+    let mut paint = Paint::default();
+    paint.set_color(color);
+    paint.set_anti_alias(true);
+    paint.set_style(paint::Style::Stroke);
+    paint.set_stroke_width(STROKE_WIDTH);
+    paint
+}
+
+fn point(p: Point2<f32>) -> Point {
+    Point::new(p.x, p.y)
+}
+
+pub fn draw(
+    canvas: &mut Canvas,
+    debug: &DebugDraw,
+    physics: &Physics,
+    bodies: &DefaultBodySet<f32>,
+    colliders: &DefaultColliderSet<f32>,
+    navigation: Option<&NavGraph>,
+) {
+    if !debug.enabled {
+        return;
+    }
+
+    if debug.shapes {
+        let intersecting: HashSet<_> = physics
+            .geometrical_world
+            .proximity_pairs(colliders, true)
+            .filter(|pair| pair.5 == Proximity::Intersecting)
+            .flat_map(|pair| vec![pair.0, pair.2])
+            .collect();
+        let solid = stroke(Color::GREEN);
+        let sensor = stroke(Color::YELLOW);
+        let triggered = stroke(Color::from_rgb(255, 128, 0));
+        for (handle, collider) in colliders.iter() {
+            let paint = if !collider.is_sensor() {
+                &solid
+            } else if intersecting.contains(&handle) {
+                &triggered
+            } else {
+                &sensor
+            };
+            draw_shape(canvas, collider.shape(), collider.position(), paint);
+        }
+    }
+
+    if debug.aabbs {
+        let paint = stroke(Color::GRAY);
+        for (_, collider) in colliders.iter() {
+            let aabb = collider.shape().aabb(collider.position());
+            let (mins, maxs) = (aabb.mins(), aabb.maxs());
+            canvas.draw_rect(Rect::new(mins.x, mins.y, maxs.x, maxs.y), &paint);
+        }
+    }
+
+    if debug.contacts {
+        let paint = stroke(Color::RED);
+        for (_, _, _, _, _, manifold) in physics.geometrical_world.contact_pairs(colliders, true) {
+            for tracked in manifold.contacts() {
+                let contact = &tracked.contact;
+                let normal_end =
+                    contact.world1 + contact.normal.into_inner() * CONTACT_NORMAL_LENGTH;
+                canvas.draw_circle(point(contact.world1), STROKE_WIDTH * 2.0, &paint);
+                canvas.draw_line(point(contact.world1), point(normal_end), &paint);
+            }
+        }
+    }
+
+    if debug.joints {
+        let paint = stroke(Color::CYAN);
+        // The ground's position is the identity, so its anchors stay put.
+        let anchor = |body: DefaultBodyHandle, local: &Point2<f32>| {
+            bodies
+                .get(body)?
+                .part(0)
+                .map(|part| part.position() * local)
+        };
+        for (handle, _) in physics.joint_constraints.iter() {
+            let anchors = match physics.joint_anchors.get(&handle) {
+                Some(anchors) => anchors,
+                None => continue,
+            };
+            let p1 = anchor(anchors.body1, &anchors.anchor1);
+            let p2 = anchor(anchors.body2, &anchors.anchor2);
+            for p in p1.iter().chain(p2.iter()) {
+                canvas.draw_circle(point(*p), STROKE_WIDTH * 3.0, &paint);
+            }
+            if let (Some(p1), Some(p2)) = (p1, p2) {
+                canvas.draw_line(point(p1), point(p2), &paint);
+            }
+        }
+    }
+
+    if debug.velocities {
+        let paint = stroke(Color::MAGENTA);
+        for (_, body) in bodies.iter() {
+            if let Some(part) = body.part(0) {
+                let center = part.center_of_mass();
+                let end = center + part.velocity().linear * VELOCITY_SCALE;
+                canvas.draw_line(point(center), point(end), &paint);
+            }
+        }
+    }
+
+    if let (true, Some(graph)) = (debug.navigation, navigation) {
+        draw_navigation(canvas, graph);
+    }
+}
+
+fn draw_navigation(canvas: &mut Canvas, graph: &NavGraph) {
+    let walk = stroke(Color::WHITE);
+    let jump = stroke(Color::BLUE);
+    let drop = stroke(Color::from_rgb(255, 160, 60));
+    for (from, links) in graph.links.iter().enumerate() {
+        let a = graph.nodes[from].position;
+        canvas.draw_circle(point(a), STROKE_WIDTH * 2.0, &walk);
+        for link in links {
+            let b = graph.nodes[link.to].position;
+            match link.kind {
+                LinkKind::Walk => {
+                    canvas.draw_line(point(a), point(b), &walk);
+                }
+                // Jumps and drops arc through the midpoint raised by a
+                // quarter of their length.
+                LinkKind::Jump | LinkKind::Drop => {
+                    let paint = if link.kind == LinkKind::Jump {
+                        &jump
+                    } else {
+                        &drop
+                    };
+                    let mid = Point2::new((a.x + b.x) / 2.0, a.y.max(b.y) + (b - a).norm() / 4.0);
+                    let mut path = Path::new();
+                    path.move_to(point(a));
+                    path.quad_to(point(mid), point(b));
+                    canvas.draw_path(&path, paint);
+                }
+            }
+        }
+    }
+}
+
+fn draw_shape(
+    canvas: &mut Canvas,
+    shape: &dyn Shape<f32>,
+    position: &Isometry2<f32>,
+    paint: &Paint,
+) {
+    if let Some(ball) = shape.as_shape::<Ball<f32>>() {
+        let center = point(Point2::from(position.translation.vector));
+        canvas.draw_circle(center, ball.radius(), paint);
+        // A spoke so rotation is visible.
+        let spoke = position * Point2::new(ball.radius(), 0.0);
+        canvas.draw_line(center, point(spoke), paint);
+    } else if let Some(cuboid) = shape.as_shape::<Cuboid<f32>>() {
+        let h = cuboid.half_extents();
+        let corners = [
+            Point2::new(-h.x, -h.y),
+            Point2::new(h.x, -h.y),
+            Point2::new(h.x, h.y),
+            Point2::new(-h.x, h.y),
+        ];
+        draw_polygon(canvas, position, &corners, paint);
+    } else if let Some(polygon) = shape.as_shape::<ConvexPolygon<f32>>() {
+        draw_polygon(canvas, position, polygon.points(), paint);
+    } else if let Some(polyline) = shape.as_shape::<Polyline<f32>>() {
+        let points = polyline.points();
+        for edge in polyline.edges() {
+            let a = position * points[edge.indices.x];
+            let b = position * points[edge.indices.y];
+            canvas.draw_line(point(a), point(b), paint);
+        }
+    } else if let Some(segment) = shape.as_shape::<Segment<f32>>() {
+        let a = position * segment.a();
+        let b = position * segment.b();
+        canvas.draw_line(point(a), point(b), paint);
+    } else if let Some(capsule) = shape.as_shape::<Capsule<f32>>() {
+        let a = position * Point2::new(0.0, -capsule.half_height());
+        let b = position * Point2::new(0.0, capsule.half_height());
+        canvas.draw_circle(point(a), capsule.radius(), paint);
+        canvas.draw_circle(point(b), capsule.radius(), paint);
+        canvas.draw_line(point(a), point(b), paint);
+    } else if let Some(compound) = shape.as_shape::<Compound<f32>>() {
+        for (local, part) in compound.shapes() {
+            draw_shape(canvas, part.as_ref(), &(position * local), paint);
+        }
+    } else {
+        let aabb = shape.aabb(position);
+        let (mins, maxs) = (aabb.mins(), aabb.maxs());
+        canvas.draw_rect(Rect::new(mins.x, mins.y, maxs.x, maxs.y), paint);
+    }
+}
+
+fn draw_polygon(
+    canvas: &mut Canvas,
+    position: &Isometry2<f32>,
+    points: &[Point2<f32>],
+    paint: &Paint,
+) {
+    let mut path = Path::new();
+    let mut points = points.iter().map(|p| point(position * p));
+    if let Some(first) = points.next() {
+        path.move_to(first);
+        for p in points {
+            path.line_to(p);
+        }
+        path.close();
+    }
+    canvas.draw_path(&path, paint);
+}
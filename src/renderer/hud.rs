@@ -0,0 +1,168 @@
+use super::super::game::components::hud::{Anchor, HudWidget, WidgetKind};
+use super::super::game::components::inventory::Inventory;
+use super::super::game::components::npc::Npc;
+use super::super::game::components::particles::Rgba;
+use super::super::game::components::sprite::make_skia_image;
+use super::super::game::items::Items;
+use super::text::Fonts;
+use legion::{IntoQuery, World};
+use skulpin::skia_safe::{paint, Canvas, Color4f, FilterQuality, Image, Paint, Rect};
+use std::collections::HashMap;
+
+const SLOT_SPACING: f32 = 4.0;
+const ICON_PADDING: f32 = 4.0;
+// Above a bottom inventory row.
+const PROMPT_OFFSET: (f32, f32) = (0.0, 64.0);
+const PROMPT_FONT: &str = "default";
+const PROMPT_COLOR: Rgba = (1.0, 1.0, 1.0, 0.9);
+
+// Draws every widget in logical pixels, so `screen` is the logical window size.
+// Item icons are turned into images once and kept in `icons`.
+pub fn draw(
+    canvas: &mut Canvas,
+    fonts: &Fonts,
+    world: &World,
+    items: &Items,
+    icons: &mut HashMap<String, Image>,
+    screen: (f32, f32),
+) {
+    let mut query = <&HudWidget>::query();
+    for widget in query.iter(world) {
+        if let Some(text) = &widget.text {
+            let size = fonts.measure(&widget.font, text);
+            let position = widget.anchor.place(size, screen, widget.offset);
+            fonts.draw(canvas, &widget.font, text, position, widget.color);
+        }
+
+        if let (
+            WidgetKind::Bar {
+                size, background, ..
+            },
+            Some(fill),
+        ) = (&widget.kind, widget.fill)
+        {
+            let (x, y) = widget.anchor.place(*size, screen, widget.offset);
+            canvas.draw_rect(
+                Rect::from_xywh(x, y, size.0, size.1),
+                &fill_paint(*background),
+            );
+            canvas.draw_rect(
+                Rect::from_xywh(x, y, size.0 * fill, size.1),
+                &fill_paint(widget.color),
+            );
+        }
+
+        if let WidgetKind::Inventory {
+            slot_size,
+            background,
+        } = &widget.kind
+        {
+            // The first inventory is the player's.
+            if let Some(inventory) = <&Inventory>::query().iter(world).next() {
+                let slots = SlotRow {
+                    slot_size: *slot_size,
+                    background: *background,
+                };
+                slots.draw(canvas, fonts, widget, inventory, items, icons, screen);
+            }
+        }
+    }
+}
+
+// What the player can do right now, e.g. "[E] Talk" next to an NPC.
+pub fn draw_prompt(canvas: &mut Canvas, fonts: &Fonts, world: &World, screen: (f32, f32)) {
+    let mut query = <&Npc>::query();
+    if let Some(npc) = query.iter(world).find(|npc| npc.in_range) {
+        let text = format!("[{}] {}", npc.key, npc.prompt);
+        let size = fonts.measure(PROMPT_FONT, &text);
+        let position = Anchor::Bottom.place(size, screen, PROMPT_OFFSET);
+        fonts.draw(canvas, PROMPT_FONT, &text, position, PROMPT_COLOR);
+    }
+}
+
+fn fill_paint(color: Rgba) -> Paint {
+    let (r, g, b, a) = color;
+    Paint::new(Color4f::new(r, g, b, a), None)
+}
+
+struct SlotRow {
+    slot_size: f32,
+    background: Rgba,
+}
+
+impl SlotRow {
+    #[allow(clippy::too_many_arguments)]
+    fn draw(
+        &self,
+        canvas: &mut Canvas,
+        fonts: &Fonts,
+        widget: &HudWidget,
+        inventory: &Inventory,
+        items: &Items,
+        icons: &mut HashMap<String, Image>,
+        screen: (f32, f32),
+    ) {
+        let count = inventory.capacity as f32;
+        let size = (
+            count * self.slot_size + (count - 1.0).max(0.0) * SLOT_SPACING,
+            self.slot_size,
+        );
+        let (left, top) = widget.anchor.place(size, screen, widget.offset);
+
+        let mut outline = fill_paint(widget.color);
+        outline.set_style(paint::Style::Stroke);
+        outline.set_stroke_width(2.0);
+        let mut icon_paint = Paint::default();
+        icon_paint.set_filter_quality(FilterQuality::None);
+
+        for i in 0..inventory.capacity {
+            let x = left + i as f32 * (self.slot_size + SLOT_SPACING);
+            let rect = Rect::from_xywh(x, top, self.slot_size, self.slot_size);
+            canvas.draw_rect(rect, &fill_paint(self.background));
+            if i == inventory.selected && !inventory.slots.is_empty() {
+                canvas.draw_rect(rect, &outline);
+            }
+            let stack = match inventory.slots.get(i) {
+                Some(stack) => stack,
+                None => continue,
+            };
+
+            if !icons.contains_key(&stack.item) {
+                if let Some(clip) = items.icon(&stack.item) {
+                    icons.insert(stack.item.clone(), make_skia_image(&clip.image));
+                }
+            }
+            match icons.get(&stack.item) {
+                Some(icon) => {
+                    let inner = Rect::from_xywh(
+                        rect.left + ICON_PADDING,
+                        rect.top + ICON_PADDING,
+                        self.slot_size - ICON_PADDING * 2.0,
+                        self.slot_size - ICON_PADDING * 2.0,
+                    );
+                    canvas.draw_image_rect(icon, None, inner, &icon_paint);
+                }
+                // Without an icon, the first letter of the name stands in.
+                None => {
+                    let name = items
+                        .get(&stack.item)
+                        .map_or(&stack.item, |desc| &desc.name);
+                    let letter: String = name.chars().take(1).collect();
+                    let (width, height) = fonts.measure(&widget.font, &letter);
+                    let position = (
+                        rect.center_x() - width / 2.0,
+                        rect.center_y() - height / 2.0,
+                    );
+                    fonts.draw(canvas, &widget.font, &letter, position, widget.color);
+                }
+            }
+
+            if stack.count > 1 {
+                let text = stack.count.to_string();
+                let (width, height) = fonts.measure(&widget.font, &text);
+                let position = (rect.right - width - 2.0, rect.bottom - height);
+                fonts.draw(canvas, &widget.font, &text, position, widget.color);
+            }
+        }
+    }
+}
@@ -1,21 +1,56 @@
+use super::game::behaviour::BehaviourRegistry;
 use super::game::components::animate::Animate;
+use super::game::components::particles::{ParticleEmitter, ParticleLayer};
+use super::game::components::physics_body::PhysicsBody;
 use super::game::components::primitive::Primitive;
 use super::game::components::sprite::Sprite;
+use super::game::components::tilemap::Tilemap;
+use super::game::items::Items;
+use super::game::navigation::NavGraph;
+use super::game::physics::Physics;
 use super::game::*;
 use legion::IntoQuery;
-use nphysics2d::object::{DefaultBodyHandle, DefaultBodySet};
-use skulpin::skia_safe::{matrix, paint, Canvas, Color, Color4f, Paint, Rect};
-use skulpin::winit::dpi::LogicalSize;
+use log::error;
+use nphysics2d::object::{DefaultBodySet, DefaultColliderSet};
+use skulpin::skia_safe::{
+    paint, Canvas, Color, Color4f, EncodedImageFormat, Image, Paint, Rect, Surface,
+};
+use skulpin::winit::dpi::{LogicalSize, PhysicalSize};
 use skulpin::CoordinateSystemHelper;
+use std::collections::HashMap;
+use std::fs;
+
+pub mod debug;
+mod hud;
+mod particles;
+pub mod text;
+mod tilemap;
+pub mod viewport;
+use debug::DebugDraw;
+use text::Fonts;
+use viewport::ViewportConfig;
 
 pub struct Renderer {
     pub logical_size: LogicalSize<u32>,
+    pub scale_factor: f64,
+    pub viewport: ViewportConfig,
+    pub debug: DebugDraw,
+    pub fonts: Fonts,
+    tileset_images: HashMap<String, Image>,
+    item_icons: HashMap<String, Image>,
 }
 
 impl Default for Renderer {
     fn default() -> Self {
+        let viewport = ViewportConfig::load("res/config/viewport.ron");
         Self {
-            logical_size: LogicalSize::new(640, 480),
+            logical_size: LogicalSize::new(viewport.initial_size.0, viewport.initial_size.1),
+            scale_factor: 1.0,
+            viewport,
+            debug: DebugDraw::default(),
+            fonts: Fonts::load("res/config/fonts.ron"),
+            tileset_images: HashMap::new(),
+            item_icons: HashMap::new(),
         }
     }
 }
@@ -24,50 +59,131 @@ pub const GROUND_THICKNESS: f32 = 0.2;
 pub const GROUND_HALF_EXTENTS_WIDTH: f32 = 3.0;
 
 impl Renderer {
+    // Called on resize and scale factor changes with the new physical size.
+    pub fn resize(&mut self, size: PhysicalSize<u32>, scale_factor: f64) {
+        self.scale_factor = scale_factor;
+        self.logical_size = size.to_logical(scale_factor);
+    }
+
     pub fn draw(
         &mut self,
         canvas: &mut Canvas,
         coordinate_system_helper: &CoordinateSystemHelper,
         game: &Game,
     ) -> bool {
-        let x_half_extents = GROUND_HALF_EXTENTS_WIDTH * 1.5;
-        let y_half_extents = x_half_extents
-            / (coordinate_system_helper.surface_extents().width as f32
-                / coordinate_system_helper.surface_extents().height as f32);
-
-        coordinate_system_helper
-            .use_visible_range(
-                canvas,
-                Rect {
-                    left: -x_half_extents,
-                    right: x_half_extents,
-                    top: y_half_extents + 1.0,
-                    bottom: -y_half_extents + 1.0,
-                },
-                matrix::ScaleToFit::Center,
-            )
-            .unwrap();
+        let extents = coordinate_system_helper.surface_extents();
+        let layout = self
+            .viewport
+            .layout(extents.width as f32, extents.height as f32);
 
+        coordinate_system_helper.use_physical_coordinates(canvas);
         // Generally would want to clear data every time we draw
         canvas.clear(Color::from_argb(0, 0, 0, 0));
+        canvas.save();
+        layout.apply(canvas);
+        self.draw_world(canvas, &layout.visible, game);
+        canvas.restore();
+
+        // The HUD is laid out in logical pixels over the whole window.
+        coordinate_system_helper.use_logical_coordinates(canvas);
+        let screen = (
+            self.logical_size.width as f32,
+            self.logical_size.height as f32,
+        );
+        let items = game.resources.get::<Items>().unwrap();
+        hud::draw(
+            canvas,
+            &self.fonts,
+            &game.world,
+            &items,
+            &mut self.item_icons,
+            screen,
+        );
+        if game.states.current() == state::GameState::Playing {
+            hud::draw_prompt(canvas, &self.fonts, &game.world, screen);
+        }
+
+        true
+    }
+
+    // Renders the world into an image without a window, e.g. for screenshots.
+    pub fn draw_offscreen(&mut self, game: &Game, width: i32, height: i32) -> Option<Image> {
+        let mut surface = Surface::new_raster_n32_premul((width, height))?;
+        let layout = self.viewport.layout(width as f32, height as f32);
+
+        let canvas = surface.canvas();
+        canvas.clear(Color::from_argb(0, 0, 0, 0));
+        canvas.save();
+        layout.apply(canvas);
+        self.draw_world(canvas, &layout.visible, game);
+        canvas.restore();
 
+        Some(surface.image_snapshot())
+    }
+
+    // Saves the world, debug overlay included, as a PNG the size of the window.
+    pub fn save_screenshot(&mut self, game: &Game, path: &str) -> bool {
+        let size = self.logical_size.to_physical::<i32>(self.scale_factor);
+        let data = self
+            .draw_offscreen(game, size.width, size.height)
+            .and_then(|image| image.encode_to_data(EncodedImageFormat::PNG));
+        let result = match data {
+            Some(data) => fs::write(path, data.as_bytes()).map_err(|e| e.to_string()),
+            None => Err("Couldn't render the image".to_string()),
+        };
+        if let Err(e) = &result {
+            error!("Failed to save screenshot {:?}: {}", path, e);
+        }
+        result.is_ok()
+    }
+
+    fn draw_world(&mut self, canvas: &mut Canvas, visible: &Rect, game: &Game) {
         // Make a color to draw with
         let mut paint = Paint::new(Color4f::new(0.0, 1.0, 0.0, 1.0), None);
         paint.set_anti_alias(true);
         paint.set_style(paint::Style::Stroke);
         paint.set_stroke_width(0.02);
 
+        let tile_paint = Paint::default();
+        let mut query = <&Tilemap>::query();
+        for tilemap in query.iter(&game.world) {
+            tilemap::draw(
+                canvas,
+                tilemap,
+                visible,
+                &mut self.tileset_images,
+                &tile_paint,
+            );
+        }
+
+        let mut emitters = <&ParticleEmitter>::query();
+        for emitter in emitters.iter(&game.world) {
+            particles::draw(canvas, emitter, ParticleLayer::Behind);
+        }
+
         let body_set = game.resources.get::<DefaultBodySet<f32>>().unwrap();
-        let mut query = <(&DefaultBodyHandle, &Animate, &Sprite)>::query();
-        for (handle, animate, sprite) in query.iter(&game.world) {
-            let body = body_set.rigid_body(*handle).unwrap();
-            (sprite.draw_fn)(canvas, body.position(), &sprite.source, &animate);
+        let registry = game.resources.get::<BehaviourRegistry>().unwrap();
+        let mut query = <(&PhysicsBody, &Animate, &Sprite)>::query();
+        for (physics_body, animate, sprite) in query.iter(&game.world) {
+            // The body may already be gone if the entity is being despawned.
+            let body = match body_set.rigid_body(physics_body.body) {
+                Some(body) => body,
+                None => continue,
+            };
+            if let Some(draw_fn) = registry.sprite(&sprite.draw_id) {
+                draw_fn(canvas, body.position(), &sprite.source, &animate);
+            }
         }
 
-        let mut query = <(&DefaultBodyHandle, &Primitive)>::query();
-        for (handle, primative) in query.iter(&game.world) {
-            let body = body_set.rigid_body(*handle).unwrap();
-            (primative.draw_fn)(canvas, body.position());
+        let mut query = <(&PhysicsBody, &Primitive)>::query();
+        for (physics_body, primative) in query.iter(&game.world) {
+            let body = match body_set.rigid_body(physics_body.body) {
+                Some(body) => body,
+                None => continue,
+            };
+            if let Some(draw_fn) = registry.primitive(&primative.draw_id) {
+                draw_fn(canvas, body.position(), primative);
+            }
         }
 
         canvas.draw_rect(
@@ -79,8 +195,21 @@ impl Renderer {
             },
             &paint,
         );
-        coordinate_system_helper.use_logical_coordinates(canvas);
 
-        true
+        for emitter in emitters.iter(&game.world) {
+            particles::draw(canvas, emitter, ParticleLayer::InFront);
+        }
+
+        let physics = game.resources.get::<Physics>().unwrap();
+        let colliders = game.resources.get::<DefaultColliderSet<f32>>().unwrap();
+        let navigation = game.resources.get::<NavGraph>();
+        debug::draw(
+            canvas,
+            &self.debug,
+            &physics,
+            &body_set,
+            &colliders,
+            navigation.as_deref(),
+        );
     }
 }
@@ -0,0 +1,40 @@
+use super::super::game::components::particles::{ParticleEmitter, ParticleLayer, ParticleShape};
+use super::super::game::components::sprite::make_skia_image;
+use skulpin::skia_safe::{Canvas, Color4f, Paint, Point, Rect};
+
+pub fn draw(canvas: &mut Canvas, emitter: &ParticleEmitter, layer: ParticleLayer) {
+    if emitter.layer != layer || emitter.particles.is_empty() {
+        return;
+    }
+
+    // Sprite particles share one image per emitter and frame.
+    let image = match (&emitter.shape, &emitter.sheet) {
+        (ParticleShape::Sprite { key, frame, .. }, Some(sheet)) => {
+            Some(make_skia_image(&sheet.get_clip(*key, *frame).image))
+        }
+        _ => None,
+    };
+
+    let mut paint = Paint::default();
+    paint.set_anti_alias(true);
+    for particle in &emitter.particles {
+        let (r, g, b, a) = emitter.color(particle);
+        paint.set_color4f(Color4f::new(r, g, b, a), None);
+        let size = emitter.size(particle);
+        let (x, y) = (particle.position.x, particle.position.y);
+        let rect = Rect::from_xywh(x - size / 2.0, y - size / 2.0, size, size);
+        match &emitter.shape {
+            ParticleShape::Circle => {
+                canvas.draw_circle(Point::new(x, y), size / 2.0, &paint);
+            }
+            ParticleShape::Square => {
+                canvas.draw_rect(rect, &paint);
+            }
+            ParticleShape::Sprite { .. } => {
+                if let Some(image) = &image {
+                    canvas.draw_image_rect(image, None, rect, &paint);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,202 @@
+use super::super::game::components::particles::Rgba;
+use super::super::game::components::sprite::{make_skia_image, SpriteSheet};
+use log::{error, warn};
+use ron::de::from_reader;
+use serde::Deserialize;
+use skulpin::skia_safe::{Canvas, Color4f, Font, FontStyle, Image, Paint, Rect, Typeface};
+use std::collections::HashMap;
+use std::fs::File;
+
+const DEFAULT_SIZE: f32 = 16.0;
+
+#[derive(Debug, Default, Deserialize)]
+pub struct FontConfig {
+    #[serde(default)]
+    pub fonts: HashMap<String, FontDesc>,
+}
+
+#[derive(Debug, Deserialize)]
+pub enum FontDesc {
+    // A system font. Without a family Skia picks its default one.
+    Skia {
+        #[serde(default)]
+        family: Option<String>,
+        #[serde(default = "default_size")]
+        size: f32,
+        #[serde(default)]
+        bold: bool,
+    },
+    // Glyphs are the frames of `animation` in a sprite sheet, one per
+    // character of `chars`, drawn `height` logical pixels high.
+    Bitmap {
+        source: String,
+        animation: String,
+        chars: String,
+        #[serde(default = "default_size")]
+        height: f32,
+        // Extra logical pixels between glyphs.
+        #[serde(default)]
+        spacing: f32,
+    },
+}
+
+fn default_size() -> f32 {
+    DEFAULT_SIZE
+}
+
+struct Glyph {
+    image: Image,
+    width: f32,
+}
+
+struct BitmapFont {
+    glyphs: HashMap<char, Glyph>,
+    height: f32,
+    spacing: f32,
+    paint: Paint,
+}
+
+enum TextFont {
+    Skia(Font),
+    Bitmap(BitmapFont),
+}
+
+pub struct Fonts {
+    fonts: HashMap<String, TextFont>,
+    fallback: Font,
+}
+
+impl Default for Fonts {
+    fn default() -> Self {
+        Self {
+            fonts: HashMap::new(),
+            fallback: Font::new(Typeface::default(), DEFAULT_SIZE),
+        }
+    }
+}
+
+impl Fonts {
+    pub fn load(filename: &str) -> Self {
+        let mut fonts = Self::default();
+        let f = File::open(crate::utils::from_out_dir(filename)).expect("Failed opening file");
+        let config: FontConfig = match from_reader(f) {
+            Ok(x) => x,
+            Err(e) => {
+                error!("Failed to load font config: {}", e);
+                return fonts;
+            }
+        };
+
+        for (name, desc) in config.fonts {
+            let font = match desc {
+                FontDesc::Skia { family, size, bold } => {
+                    let style = if bold {
+                        FontStyle::bold()
+                    } else {
+                        FontStyle::normal()
+                    };
+                    let typeface = family
+                        .and_then(|family| Typeface::new(&family, style))
+                        .unwrap_or_default();
+                    TextFont::Skia(Font::new(typeface, size))
+                }
+                FontDesc::Bitmap {
+                    source,
+                    animation,
+                    chars,
+                    height,
+                    spacing,
+                } => {
+                    let sheet = SpriteSheet::from_config(&crate::utils::from_out_dir(&source));
+                    let mut glyphs = HashMap::new();
+                    for (i, c) in chars.chars().enumerate() {
+                        let clip = match sheet.get_named_clip(&animation, i) {
+                            Some(clip) => clip,
+                            None => {
+                                warn!("Font {:?} has no glyph for {:?}", name, c);
+                                break;
+                            }
+                        };
+                        let glyph = Glyph {
+                            image: make_skia_image(&clip.image),
+                            width: clip.width_over_height * height,
+                        };
+                        glyphs.insert(c, glyph);
+                    }
+                    TextFont::Bitmap(BitmapFont {
+                        glyphs,
+                        height,
+                        spacing,
+                        paint: sheet.paint(),
+                    })
+                }
+            };
+            fonts.fonts.insert(name, font);
+        }
+        fonts
+    }
+
+    fn skia_font(&self, name: &str) -> &Font {
+        match self.fonts.get(name) {
+            Some(TextFont::Skia(font)) => font,
+            _ => &self.fallback,
+        }
+    }
+
+    // Width and height of `text` in `font`, in logical pixels.
+    pub fn measure(&self, font: &str, text: &str) -> (f32, f32) {
+        if let Some(TextFont::Bitmap(font)) = self.fonts.get(font) {
+            let width: f32 = text.chars().map(|c| font.advance(c)).sum::<f32>() - font.spacing;
+            return (width.max(0.0), font.height);
+        }
+        let font = self.skia_font(font);
+        let (width, _) = font.measure_str(text, None);
+        let (_, metrics) = font.metrics();
+        (width, metrics.descent - metrics.ascent)
+    }
+
+    // Draws `text` with its top left corner at `position`. Bitmap glyphs keep
+    // their own colours and only take the alpha of `color`.
+    pub fn draw(
+        &self,
+        canvas: &mut Canvas,
+        font: &str,
+        text: &str,
+        position: (f32, f32),
+        color: Rgba,
+    ) {
+        let (r, g, b, a) = color;
+        if let Some(TextFont::Bitmap(font)) = self.fonts.get(font) {
+            let mut paint = font.paint.clone();
+            paint.set_alpha_f(a);
+            let mut x = position.0;
+            for c in text.chars() {
+                if let Some(glyph) = font.glyphs.get(&c) {
+                    let rect = Rect::from_xywh(x, position.1, glyph.width, font.height);
+                    canvas.draw_image_rect(&glyph.image, None, rect, &paint);
+                }
+                x += font.advance(c);
+            }
+            return;
+        }
+
+        let font = self.skia_font(font);
+        let mut paint = Paint::new(Color4f::new(r, g, b, a), None);
+        paint.set_anti_alias(true);
+        let (_, metrics) = font.metrics();
+        canvas.draw_str(
+            text,
+            (position.0, position.1 - metrics.ascent),
+            font,
+            &paint,
+        );
+    }
+}
+
+impl BitmapFont {
+    // Characters without a glyph take the space of a square one.
+    fn advance(&self, c: char) -> f32 {
+        let width = self.glyphs.get(&c).map_or(self.height, |glyph| glyph.width);
+        width + self.spacing
+    }
+}
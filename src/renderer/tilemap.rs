@@ -0,0 +1,46 @@
+use super::super::game::components::sprite::make_skia_image;
+use super::super::game::components::tilemap::Tilemap;
+use skulpin::skia_safe::canvas::SrcRectConstraint;
+use skulpin::skia_safe::{Canvas, Image, Paint, Rect};
+use std::collections::HashMap;
+
+// Draws the visible tiles of every visible layer, converting each tileset to a
+// skia image once.
+pub fn draw(
+    canvas: &mut Canvas,
+    tilemap: &Tilemap,
+    visible: &Rect,
+    images: &mut HashMap<String, Image>,
+    paint: &Paint,
+) {
+    let range = tilemap.visible_tiles(visible.left, visible.top, visible.right, visible.bottom);
+    for layer in tilemap.layers.iter().filter(|layer| layer.visible) {
+        for row in range.row..range.row + range.rows {
+            for col in range.col..range.col + range.cols {
+                let gid = tilemap.gid(layer, col, row);
+                let tileset = match tilemap.tileset(gid) {
+                    Some(tileset) => tileset,
+                    None => continue,
+                };
+                let (x, y, w, h) = match tileset.source_rect(gid) {
+                    Some(rect) => rect,
+                    None => continue,
+                };
+                let image = images
+                    .entry(tileset.image_path.clone())
+                    .or_insert_with(|| make_skia_image(&tileset.image));
+
+                let src = Rect::from_xywh(x as f32, y as f32, w as f32, h as f32);
+                let left = tilemap.origin.x + col as f32 * tilemap.tile_size;
+                let top = tilemap.origin.y - row as f32 * tilemap.tile_size;
+                let dst = Rect::new(left, top - tilemap.tile_size, left + tilemap.tile_size, top);
+                canvas.draw_image_rect(
+                    &*image,
+                    Some((&src, SrcRectConstraint::Strict)),
+                    dst,
+                    paint,
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,142 @@
+use super::super::game::components::sprite::SpriteSheet;
+use log::error;
+use ron::de::from_reader;
+use serde::Deserialize;
+use skulpin::skia_safe::{Canvas, Rect};
+use std::fs::File;
+
+// How the world view is fitted to the window.
+#[derive(Debug, Copy, Clone, PartialEq, Deserialize)]
+pub enum ViewportPolicy {
+    // Keep `aspect` and fill the rest of the window with bars.
+    Letterbox,
+    // Keep `world_width` and show more or less of the world vertically.
+    Expand,
+    // Like letterbox, but only scale by whole multiples of the art's pixels
+    // per unit.
+    PixelPerfect,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct ViewportConfig {
+    pub policy: ViewportPolicy,
+    // Width of the visible world, in world units.
+    pub world_width: f32,
+    pub aspect: f32,
+    pub center: (f32, f32),
+    // The sprite sheet whose `pixels_per_unit` the art is drawn at, for
+    // pixel-perfect scaling.
+    pub pixel_art: String,
+    #[serde(skip)]
+    pub pixels_per_unit: Option<f32>,
+    // Window size before the first resize event arrives.
+    pub initial_size: (u32, u32),
+}
+
+impl Default for ViewportConfig {
+    fn default() -> Self {
+        Self {
+            policy: ViewportPolicy::Expand,
+            world_width: 9.0,
+            aspect: 4.0 / 3.0,
+            center: (0.0, 1.0),
+            pixel_art: "res/assets/adventurer_sprite.ron".to_string(),
+            pixels_per_unit: None,
+            initial_size: (640, 480),
+        }
+    }
+}
+
+impl ViewportConfig {
+    pub fn load(filename: &str) -> Self {
+        let f = File::open(crate::utils::from_out_dir(filename)).expect("Failed opening file");
+        let mut config: Self = match from_reader(f) {
+            Ok(x) => x,
+            Err(e) => {
+                error!("Failed to load viewport config: {}", e);
+                Self::default()
+            }
+        };
+        let sheet = crate::utils::from_out_dir(&config.pixel_art);
+        config.pixels_per_unit = SpriteSheet::config_pixels_per_unit(&sheet);
+        if config.policy == ViewportPolicy::PixelPerfect && config.pixels_per_unit.is_none() {
+            error!(
+                "Sprite sheet {:?} has no pixels_per_unit; letterboxing instead",
+                config.pixel_art
+            );
+        }
+        config
+    }
+
+    // Places the world on a surface of the given size in physical pixels.
+    pub fn layout(&self, width: f32, height: f32) -> ViewportLayout {
+        let world_height = self.world_width / self.aspect;
+        let (dest_width, dest_height, visible_width, visible_height) =
+            match (self.policy, self.pixels_per_unit) {
+                // Without the art's pixel size there's no pixel grid to keep to.
+                (ViewportPolicy::Letterbox, _) | (ViewportPolicy::PixelPerfect, None) => {
+                    let scale = (width / self.world_width).min(height / world_height);
+                    (
+                        self.world_width * scale,
+                        world_height * scale,
+                        self.world_width,
+                        world_height,
+                    )
+                }
+                (ViewportPolicy::Expand, _) => {
+                    let scale = width / self.world_width;
+                    (width, height, self.world_width, height / scale)
+                }
+                (ViewportPolicy::PixelPerfect, Some(pixels_per_unit)) => {
+                    let art_width = self.world_width * pixels_per_unit;
+                    let art_height = world_height * pixels_per_unit;
+                    let multiple = (width / art_width).min(height / art_height).floor();
+                    // Too small for 1:1, so fall back to a fractional scale.
+                    let multiple = if multiple >= 1.0 {
+                        multiple
+                    } else {
+                        (width / art_width).min(height / art_height)
+                    };
+                    (
+                        art_width * multiple,
+                        art_height * multiple,
+                        self.world_width,
+                        world_height,
+                    )
+                }
+            };
+
+        let left = ((width - dest_width) / 2.0).round();
+        let top = ((height - dest_height) / 2.0).round();
+        let (cx, cy) = self.center;
+        ViewportLayout {
+            dest: Rect::from_xywh(left, top, dest_width.round(), dest_height.round()),
+            // y points up in the world, so top is the larger value.
+            visible: Rect {
+                left: cx - visible_width / 2.0,
+                right: cx + visible_width / 2.0,
+                top: cy + visible_height / 2.0,
+                bottom: cy - visible_height / 2.0,
+            },
+        }
+    }
+}
+
+pub struct ViewportLayout {
+    // Where the world is drawn on the surface, in physical pixels.
+    pub dest: Rect,
+    // The part of the world shown.
+    pub visible: Rect,
+}
+
+impl ViewportLayout {
+    // Maps world coordinates into `dest` and clips to it.
+    pub fn apply(&self, canvas: &mut Canvas) {
+        let scale = self.dest.width() / self.visible.width();
+        canvas.clip_rect(self.dest, None, None);
+        canvas.translate((self.dest.center_x(), self.dest.center_y()));
+        canvas.scale((scale, -scale));
+        canvas.translate((-self.visible.center_x(), -self.visible.center_y()));
+    }
+}
@@ -0,0 +1,160 @@
+use super::super::game::audio::{Audio, MASTER_BUS, MUSIC_BUS};
+use super::super::game::dialogue::{Conversation, Line};
+use super::super::game::state::GameState;
+use super::super::game::Game;
+use super::super::renderer::text::Fonts;
+use super::super::renderer::viewport::ViewportPolicy;
+use super::{Ui, UiFrame};
+use skulpin::skia_safe::Canvas;
+
+const POLICIES: [(ViewportPolicy, &str); 3] = [
+    (ViewportPolicy::Expand, "Expand"),
+    (ViewportPolicy::Letterbox, "Letterbox"),
+    (ViewportPolicy::PixelPerfect, "Pixel perfect"),
+];
+
+// What the player picked, applied once the frame is drawn.
+#[derive(Debug, Clone, PartialEq)]
+pub enum MenuAction {
+    Play,
+    Resume,
+    Retry,
+    OpenOptions,
+    CloseOptions,
+    QuitToMenu,
+    Exit,
+    SetBusVolume(String, f32),
+    SetViewport(ViewportPolicy),
+    // Continues the conversation, with the index of the picked choice.
+    AdvanceDialogue(Option<usize>),
+}
+
+// Draws the menu for the current state over the frame, in logical pixels.
+pub fn draw(
+    ui: &mut Ui,
+    canvas: &mut Canvas,
+    fonts: &Fonts,
+    screen: (f32, f32),
+    game: &Game,
+    policy: ViewportPolicy,
+) -> Vec<MenuAction> {
+    let state = game.states.current();
+    match state {
+        GameState::Menu
+        | GameState::Options
+        | GameState::Paused
+        | GameState::Dialogue
+        | GameState::GameOver => {}
+        _ => return Vec::new(),
+    }
+
+    let mut frame = ui.begin(canvas, fonts, screen);
+    let mut actions = Vec::new();
+    match state {
+        GameState::Menu => main_menu(&mut frame, &mut actions),
+        GameState::Paused => pause_menu(&mut frame, &mut actions),
+        GameState::Options => {
+            let audio = game.resources.get::<Audio>().unwrap();
+            options(&mut frame, &mut actions, &audio, policy);
+        }
+        GameState::GameOver => game_over_menu(&mut frame, &mut actions),
+        GameState::Dialogue => {
+            let conversation = game.resources.get::<Conversation>().unwrap();
+            if let Some(line) = conversation.line() {
+                dialogue_box(&mut frame, &mut actions, line);
+            }
+        }
+        _ => {}
+    }
+    frame.end();
+    actions
+}
+
+fn main_menu(frame: &mut UiFrame, actions: &mut Vec<MenuAction>) {
+    frame.backdrop((0.05, 0.05, 0.08, 1.0));
+    frame.title("Fork");
+    if frame.button("Play") {
+        actions.push(MenuAction::Play);
+    }
+    if frame.button("Options") {
+        actions.push(MenuAction::OpenOptions);
+    }
+    if frame.button("Quit") {
+        actions.push(MenuAction::Exit);
+    }
+}
+
+fn pause_menu(frame: &mut UiFrame, actions: &mut Vec<MenuAction>) {
+    frame.backdrop((0.0, 0.0, 0.0, 0.6));
+    frame.title("Paused");
+    if frame.button("Resume") || frame.back() {
+        actions.push(MenuAction::Resume);
+    }
+    if frame.button("Options") {
+        actions.push(MenuAction::OpenOptions);
+    }
+    if frame.button("Quit to menu") {
+        actions.push(MenuAction::QuitToMenu);
+    }
+}
+
+fn game_over_menu(frame: &mut UiFrame, actions: &mut Vec<MenuAction>) {
+    frame.backdrop((0.2, 0.0, 0.0, 0.6));
+    frame.title("Game over");
+    if frame.button("Retry") {
+        actions.push(MenuAction::Retry);
+    }
+    if frame.button("Quit to menu") {
+        actions.push(MenuAction::QuitToMenu);
+    }
+}
+
+// Sits in the lower half of the screen, over the world.
+fn dialogue_box(frame: &mut UiFrame, actions: &mut Vec<MenuAction>, line: &Line) {
+    frame.skip_to(0.55);
+    frame.text_box(line.speaker.as_deref(), &line.text);
+    if line.choices.is_empty() {
+        if frame.button("Continue") {
+            actions.push(MenuAction::AdvanceDialogue(None));
+        }
+        return;
+    }
+    for (i, (_, text)) in line.choices.iter().enumerate() {
+        if frame.button(text) {
+            actions.push(MenuAction::AdvanceDialogue(Some(i)));
+        }
+    }
+}
+
+fn options(
+    frame: &mut UiFrame,
+    actions: &mut Vec<MenuAction>,
+    audio: &Audio,
+    policy: ViewportPolicy,
+) {
+    frame.backdrop((0.05, 0.05, 0.08, 0.9));
+    frame.title("Options");
+    for (bus, label) in &[
+        (MASTER_BUS, "Master"),
+        (MUSIC_BUS, "Music"),
+        ("sfx", "Effects"),
+    ] {
+        let mut volume = audio.bus_level(bus);
+        if frame.slider(label, &mut volume, 0.0, 1.0) {
+            actions.push(MenuAction::SetBusVolume(bus.to_string(), volume));
+        }
+    }
+
+    let names: Vec<&str> = POLICIES.iter().map(|(_, name)| *name).collect();
+    let mut selected = POLICIES
+        .iter()
+        .position(|(option, _)| *option == policy)
+        .unwrap_or(0);
+    if frame.list("Scaling", &names, &mut selected) {
+        actions.push(MenuAction::SetViewport(POLICIES[selected].0));
+    }
+
+    if frame.button("Back") || frame.back() {
+        actions.push(MenuAction::CloseOptions);
+    }
+}
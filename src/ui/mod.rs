@@ -0,0 +1,355 @@
+use super::game::components::particles::Rgba;
+use super::renderer::text::Fonts;
+use skulpin::skia_safe::{Canvas, Color4f, Paint, Point, Rect};
+use skulpin::winit::event::VirtualKeyCode as Keycode;
+
+pub mod menus;
+
+const WIDGET_WIDTH: f32 = 260.0;
+const ROW_HEIGHT: f32 = 32.0;
+const SPACING: f32 = 10.0;
+const FONT: &str = "default";
+const TITLE_FONT: &str = "title";
+
+const BOX_COLOR: Rgba = (0.15, 0.15, 0.2, 0.9);
+const FOCUS_COLOR: Rgba = (0.3, 0.4, 0.7, 0.95);
+const ACCENT_COLOR: Rgba = (0.5, 0.7, 1.0, 1.0);
+const TEXT_COLOR: Rgba = (1.0, 1.0, 1.0, 1.0);
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum UiAction {
+    Up,
+    Down,
+    Left,
+    Right,
+    Confirm,
+    Back,
+}
+
+// Keys that drive menu navigation.
+pub struct ActionMap {
+    bindings: Vec<(Keycode, UiAction)>,
+}
+
+impl Default for ActionMap {
+    fn default() -> Self {
+        Self {
+            bindings: vec![
+                (Keycode::Up, UiAction::Up),
+                (Keycode::W, UiAction::Up),
+                (Keycode::Down, UiAction::Down),
+                (Keycode::S, UiAction::Down),
+                (Keycode::Left, UiAction::Left),
+                (Keycode::A, UiAction::Left),
+                (Keycode::Right, UiAction::Right),
+                (Keycode::D, UiAction::Right),
+                (Keycode::Return, UiAction::Confirm),
+                (Keycode::Space, UiAction::Confirm),
+                (Keycode::Escape, UiAction::Back),
+                (Keycode::Back, UiAction::Back),
+            ],
+        }
+    }
+}
+
+impl ActionMap {
+    pub fn action(&self, key: Keycode) -> Option<UiAction> {
+        self.bindings
+            .iter()
+            .find(|(bound, _)| *bound == key)
+            .map(|(_, action)| *action)
+    }
+}
+
+// Input gathered between frames, used up by the next one.
+#[derive(Default)]
+struct Input {
+    actions: Vec<UiAction>,
+    // In logical pixels.
+    mouse: Option<(f32, f32)>,
+    moved: bool,
+    held: bool,
+    clicked: bool,
+}
+
+// Immediate-mode widgets: menus are rebuilt every frame from a `UiFrame`, and
+// each widget reports what happened to it in the previous input.
+#[derive(Default)]
+pub struct Ui {
+    pub actions: ActionMap,
+    input: Input,
+    focus: usize,
+    // Focusable widgets in the last frame, to wrap navigation.
+    focusable: usize,
+}
+
+impl Ui {
+    // Returns whether the key is bound to an action.
+    pub fn key_pressed(&mut self, key: Keycode) -> bool {
+        match self.actions.action(key) {
+            Some(action) => {
+                self.input.actions.push(action);
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn mouse_moved(&mut self, position: (f32, f32)) {
+        self.input.mouse = Some(position);
+        self.input.moved = true;
+    }
+
+    // A click is a release after a press.
+    pub fn mouse_button(&mut self, pressed: bool) {
+        if !pressed && self.input.held {
+            self.input.clicked = true;
+        }
+        self.input.held = pressed;
+    }
+
+    // Starts laying out widgets in a column down the middle of `screen`.
+    pub fn begin<'a>(
+        &'a mut self,
+        canvas: &'a mut Canvas,
+        fonts: &'a Fonts,
+        screen: (f32, f32),
+    ) -> UiFrame<'a> {
+        let count = self.focusable.max(1);
+        for action in &self.input.actions {
+            match action {
+                UiAction::Up => self.focus = (self.focus + count - 1) % count,
+                UiAction::Down => self.focus = (self.focus + 1) % count,
+                _ => {}
+            }
+        }
+        UiFrame {
+            ui: self,
+            canvas,
+            fonts,
+            screen,
+            cursor: screen.1 * 0.2,
+            index: 0,
+        }
+    }
+}
+
+pub struct UiFrame<'a> {
+    ui: &'a mut Ui,
+    canvas: &'a mut Canvas,
+    fonts: &'a Fonts,
+    screen: (f32, f32),
+    // Top of the next widget.
+    cursor: f32,
+    index: usize,
+}
+
+impl<'a> UiFrame<'a> {
+    fn next_rect(&mut self, height: f32) -> Rect {
+        let left = ((self.screen.0 - WIDGET_WIDTH) / 2.0).round();
+        let rect = Rect::from_xywh(left, self.cursor, WIDGET_WIDTH, height);
+        self.cursor += height + SPACING;
+        rect
+    }
+
+    fn hovered(&self, rect: &Rect) -> bool {
+        self.ui
+            .input
+            .mouse
+            .map_or(false, |(x, y)| rect.contains(Point::new(x, y)))
+    }
+
+    fn clicked(&self, rect: &Rect) -> bool {
+        self.ui.input.clicked && self.hovered(rect)
+    }
+
+    // Takes the next focus slot. Moving the mouse over `rect` focuses it.
+    fn focus(&mut self, rect: &Rect) -> bool {
+        let index = self.index;
+        self.index += 1;
+        if self.ui.input.moved && self.hovered(rect) {
+            self.ui.focus = index;
+        }
+        self.ui.focus == index
+    }
+
+    fn pressed(&self, action: UiAction) -> bool {
+        self.ui.input.actions.contains(&action)
+    }
+
+    fn fill(&mut self, rect: Rect, color: Rgba) {
+        let (r, g, b, a) = color;
+        let paint = Paint::new(Color4f::new(r, g, b, a), None);
+        self.canvas.draw_rect(rect, &paint);
+    }
+
+    fn text(&mut self, rect: &Rect, font: &str, text: &str, centered: bool) {
+        let (width, height) = self.fonts.measure(font, text);
+        let x = if centered {
+            rect.center_x() - width / 2.0
+        } else {
+            rect.left + SPACING
+        };
+        let y = rect.center_y() - height / 2.0;
+        self.fonts.draw(self.canvas, font, text, (x, y), TEXT_COLOR);
+    }
+
+    // Covers everything drawn so far.
+    pub fn backdrop(&mut self, color: Rgba) {
+        let rect = Rect::from_wh(self.screen.0, self.screen.1);
+        self.fill(rect, color);
+    }
+
+    // Moves the next widget down to `fraction` of the screen height.
+    pub fn skip_to(&mut self, fraction: f32) {
+        self.cursor = self.cursor.max(self.screen.1 * fraction);
+    }
+
+    // Word-wrapped text in a box twice as wide as other widgets, under an
+    // optional heading such as who is speaking.
+    pub fn text_box(&mut self, heading: Option<&str>, text: &str) {
+        let width = (WIDGET_WIDTH * 2.0).min(self.screen.0 - SPACING * 2.0);
+        let lines = self.wrap(text, width - SPACING * 2.0);
+        let (_, line_height) = self.fonts.measure(FONT, "Ag");
+        let rows = lines.len() + heading.map_or(0, |_| 1);
+        let height = rows as f32 * line_height + SPACING * 2.0;
+        let left = ((self.screen.0 - width) / 2.0).round();
+        let rect = Rect::from_xywh(left, self.cursor, width, height);
+        self.cursor += height + SPACING;
+
+        self.fill(rect, BOX_COLOR);
+        let x = rect.left + SPACING;
+        let mut y = rect.top + SPACING;
+        if let Some(heading) = heading {
+            self.fonts
+                .draw(self.canvas, FONT, heading, (x, y), ACCENT_COLOR);
+            y += line_height;
+        }
+        for line in &lines {
+            self.fonts.draw(self.canvas, FONT, line, (x, y), TEXT_COLOR);
+            y += line_height;
+        }
+    }
+
+    // Breaks `text` between words into lines at most `width` wide, unless a
+    // single word is wider.
+    fn wrap(&self, text: &str, width: f32) -> Vec<String> {
+        let mut lines = Vec::new();
+        let mut line = String::new();
+        for word in text.split_whitespace() {
+            let candidate = if line.is_empty() {
+                word.to_string()
+            } else {
+                format!("{} {}", line, word)
+            };
+            if !line.is_empty() && self.fonts.measure(FONT, &candidate).0 > width {
+                lines.push(std::mem::replace(&mut line, word.to_string()));
+            } else {
+                line = candidate;
+            }
+        }
+        if !line.is_empty() {
+            lines.push(line);
+        }
+        lines
+    }
+
+    pub fn title(&mut self, text: &str) {
+        let (_, height) = self.fonts.measure(TITLE_FONT, text);
+        let rect = self.next_rect(height);
+        self.text(&rect, TITLE_FONT, text, true);
+    }
+
+    // True when clicked, or confirmed while focused.
+    pub fn button(&mut self, label: &str) -> bool {
+        let rect = self.next_rect(ROW_HEIGHT);
+        let focused = self.focus(&rect);
+        self.fill(rect, if focused { FOCUS_COLOR } else { BOX_COLOR });
+        self.text(&rect, FONT, label, true);
+        self.clicked(&rect) || (focused && self.pressed(UiAction::Confirm))
+    }
+
+    // Left and right step by a tenth of the range, the mouse drags. Returns
+    // whether `value` changed.
+    pub fn slider(&mut self, label: &str, value: &mut f32, min: f32, max: f32) -> bool {
+        let rect = self.next_rect(ROW_HEIGHT);
+        let focused = self.focus(&rect);
+        let old = *value;
+        let step = (max - min) / 10.0;
+        if focused && self.pressed(UiAction::Left) {
+            *value -= step;
+        }
+        if focused && self.pressed(UiAction::Right) {
+            *value += step;
+        }
+        if let (true, Some((x, _))) = (
+            (self.ui.input.held || self.ui.input.clicked) && self.hovered(&rect),
+            self.ui.input.mouse,
+        ) {
+            *value = min + (max - min) * (x - rect.left) / rect.width();
+        }
+        *value = value.max(min).min(max);
+
+        self.fill(rect, if focused { FOCUS_COLOR } else { BOX_COLOR });
+        let fraction = (*value - min) / (max - min).max(std::f32::EPSILON);
+        let mut bar = rect;
+        bar.top = bar.bottom - 4.0;
+        bar.right = bar.left + bar.width() * fraction;
+        self.fill(bar, ACCENT_COLOR);
+        let text = format!("{} {:.0}%", label, fraction * 100.0);
+        self.text(&rect, FONT, &text, false);
+        (*value - old).abs() > std::f32::EPSILON
+    }
+
+    // One focusable block of rows. Left and right or clicking pick an item.
+    // Returns whether `selected` changed.
+    pub fn list(&mut self, label: &str, items: &[&str], selected: &mut usize) -> bool {
+        let title = self.next_rect(ROW_HEIGHT);
+        self.text(&title, FONT, label, false);
+        let rect = self.next_rect(ROW_HEIGHT * items.len() as f32);
+        let focused = self.focus(&rect);
+        let old = *selected;
+        let count = items.len().max(1);
+        if focused && self.pressed(UiAction::Left) {
+            *selected = (*selected + count - 1) % count;
+        }
+        if focused && self.pressed(UiAction::Right) {
+            *selected = (*selected + 1) % count;
+        }
+
+        self.fill(rect, if focused { FOCUS_COLOR } else { BOX_COLOR });
+        for (i, item) in items.iter().enumerate() {
+            let row = Rect::from_xywh(
+                rect.left,
+                rect.top + ROW_HEIGHT * i as f32,
+                rect.width(),
+                ROW_HEIGHT,
+            );
+            if self.clicked(&row) {
+                *selected = i;
+            }
+            if i == *selected {
+                let mut marker = row;
+                marker.right = marker.left + 4.0;
+                self.fill(marker, ACCENT_COLOR);
+            }
+            self.text(&row, FONT, item, false);
+        }
+        *selected != old
+    }
+
+    // True when the back action was pressed this frame.
+    pub fn back(&self) -> bool {
+        self.pressed(UiAction::Back)
+    }
+
+    // Finishes the frame and consumes its input.
+    pub fn end(self) {
+        self.ui.focusable = self.index;
+        self.ui.focus = self.ui.focus.min(self.index.saturating_sub(1));
+        let input = &mut self.ui.input;
+        input.actions.clear();
+        input.moved = false;
+        input.clicked = false;
+    }
+}